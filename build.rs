@@ -1,4 +1,8 @@
 #![allow(missing_docs)]
+use std::fmt::Write as _;
+use std::path::Path;
+use std::{env, fs};
+
 fn main() {
     #[cfg(feature = "r1")]
     println!("cargo:rebuild-if-changed=device_R1.yaml");
@@ -8,4 +12,127 @@ fn main() {
     println!("cargo:rebuild-if-changed=device_R4.yaml");
     #[cfg(feature = "r5")]
     println!("cargo:rebuild-if-changed=device_R5.yaml");
+
+    #[cfg(feature = "r1")]
+    generate_df_table("df_r1");
+    #[cfg(feature = "r3")]
+    generate_df_table("df_r3");
+    #[cfg(feature = "r4")]
+    generate_df_table("df_r4");
+    #[cfg(feature = "r5")]
+    generate_df_table("df_r5");
+}
+
+/// Data flash addresses span 0x4000 to 0x5FFF.
+const DF_START: u32 = 0x4000;
+const DF_END: u32 = 0x6000;
+
+/// Turn a revision's data flash table, `<name>.csv`, into the `PARAMS` table included by `src/versions/<name>.rs`.
+///
+/// The columns follow the TRM data flash tables: class, subclass, name, address, type, min, max, default and units.
+/// String types are listed as in the TRM, `S<n>` taking `n` bytes including the length byte. Parameters must be
+/// listed in address order without overlapping. Fields containing commas, such as names or units, are quoted.
+#[allow(dead_code)]
+fn generate_df_table(name: &str) {
+    let source = format!("{name}.csv");
+    println!("cargo:rerun-if-changed={source}");
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_path(&source)
+        .unwrap_or_else(|e| panic!("{source}: {e}"));
+
+    let mut out = String::from("/// Every parameter defined for this revision, in address order.\n");
+    out.push_str("pub const PARAMS: &[crate::dataflash::DfParam] = &[\n");
+
+    let mut next_free = DF_START;
+    for record in reader.records() {
+        let record = record.unwrap_or_else(|e| panic!("{source}: {e}"));
+        let line = record.position().map_or(0, csv::Position::line);
+        let fail = |msg: &str| -> ! { panic!("{source}:{line}: {msg}") };
+
+        let fields: Vec<&str> = record.iter().collect();
+        let [class, subclass, param, address, ty, min, max, default, units] = fields[..] else {
+            fail("expected 9 columns");
+        };
+
+        let address = address
+            .strip_prefix("0x")
+            .and_then(|a| u32::from_str_radix(a, 16).ok())
+            .unwrap_or_else(|| fail("address is not a hex number"));
+        let (ty, size) = df_type(ty).unwrap_or_else(|| fail("unknown type"));
+        if address < next_free {
+            fail("address overlaps the previous parameter or is out of order");
+        }
+        next_free = address + size;
+        if next_free > DF_END {
+            fail("parameter ends past the data flash");
+        }
+
+        let (range, default) = match ty.as_str() {
+            "F4" => {
+                let parse = |v: &str| v.parse::<f32>().unwrap_or_else(|_| fail("expected a float"));
+                let (min, max, default) = (parse(min), parse(max), parse(default));
+                if !(min..=max).contains(&default) {
+                    fail("default is out of range");
+                }
+                (
+                    format!("crate::dataflash::DfRange::Float {{ min: {min:?}, max: {max:?} }}"),
+                    format!("crate::dataflash::DfValue::Float({default:?})"),
+                )
+            }
+            t if t.starts_with("S(") => {
+                if default.len() + 1 > usize::try_from(size).unwrap() {
+                    fail("default is longer than the string");
+                }
+                (
+                    String::from("crate::dataflash::DfRange::None"),
+                    format!(
+                        "crate::dataflash::DfValue::Str(crate::dataflash::DfString::new(b{default:?}).unwrap())"
+                    ),
+                )
+            }
+            _ => {
+                let parse = |v: &str| v.parse::<i64>().unwrap_or_else(|_| fail("expected an integer"));
+                let (min, max, default) = (parse(min), parse(max), parse(default));
+                if !(min..=max).contains(&default) {
+                    fail("default is out of range");
+                }
+                (
+                    format!("crate::dataflash::DfRange::Int {{ min: {min}, max: {max} }}"),
+                    format!("crate::dataflash::DfValue::Int({default})"),
+                )
+            }
+        };
+
+        writeln!(
+            out,
+            "    crate::dataflash::DfParam {{ class: {class:?}, subclass: {subclass:?}, name: {param:?}, \
+             address: {address:#06X}, ty: crate::dataflash::DfType::{ty}, units: {units:?}, range: {range}, \
+             default: {default} }},"
+        )
+        .unwrap();
+    }
+    out.push_str("];\n");
+
+    let dest = Path::new(&env::var("OUT_DIR").unwrap()).join(format!("{name}.rs"));
+    fs::write(dest, out).unwrap();
+}
+
+/// Rust spelling and size in bytes of a TRM data flash type.
+#[allow(dead_code)]
+fn df_type(ty: &str) -> Option<(String, u32)> {
+    let size = match ty {
+        "I1" | "U1" | "H1" => 1,
+        "I2" | "U2" | "H2" => 2,
+        "I4" | "U4" | "H4" | "F4" => 4,
+        _ => {
+            let size: u32 = ty.strip_prefix('S')?.parse().ok()?;
+            if !(2..=33).contains(&size) {
+                return None;
+            }
+            return Some((format!("S({})", size - 1), size));
+        }
+    };
+
+    Some((ty.to_owned(), size))
 }
@@ -0,0 +1,82 @@
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+use rand_core::RngCore;
+use sha1::{Digest, Sha1};
+
+use crate::consts::{
+    AUTH_CHALLENGE_LEN_BYTES, AUTH_DIGEST_LEN_BYTES, AUTH_KEY_DATA_LEN_BYTES, AUTH_RESPONSE_DELAY_MS, AUTHENTICATE_CMD,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+/// 128-bit authentication key, as programmed into the gauge.
+pub type AuthKey = [u8; AUTH_KEY_DATA_LEN_BYTES as usize];
+
+/// 160-bit challenge written to `Authenticate()`.
+pub type AuthChallenge = [u8; AUTH_CHALLENGE_LEN_BYTES as usize];
+
+/// 160-bit digest read back from `Authenticate()`.
+pub type AuthDigest = [u8; AUTH_DIGEST_LEN_BYTES as usize];
+
+/// Generate a random challenge for [`Bq40z50::authenticate`](crate::Bq40z50::authenticate).
+///
+/// Use a cryptographically secure RNG, a predictable challenge allows replaying a previously observed digest.
+pub fn generate_challenge<R: RngCore>(rng: &mut R) -> AuthChallenge {
+    let mut challenge = [0u8; AUTH_CHALLENGE_LEN_BYTES as usize];
+    rng.fill_bytes(&mut challenge);
+    challenge
+}
+
+/// Compute the digest a genuine pack returns for `challenge`.
+///
+/// The gauge computes `SHA1(key || SHA1(key || challenge))`, with the key and challenge in the byte order they are
+/// sent over the bus.
+#[must_use]
+pub fn expected_digest(key: &AuthKey, challenge: &AuthChallenge) -> AuthDigest {
+    let inner = Sha1::new().chain_update(key).chain_update(challenge).finalize();
+    Sha1::new().chain_update(key).chain_update(inner).finalize().into()
+}
+
+/// Compare two digests without leaking the position of the first mismatch through timing.
+fn digests_match(a: &AuthDigest, b: &AuthDigest) -> bool {
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Challenge the pack through `Authenticate()` and verify its SHA-1 digest.
+    ///
+    /// Writes `challenge` as a block, waits for the gauge to compute the digest, then reads it back and compares it
+    /// against [`expected_digest`](crate::expected_digest) for `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::AuthenticationFailed`] if the digest does not
+    /// match, which indicates the pack does not hold `key`.
+    pub async fn authenticate(
+        &mut self,
+        key: &AuthKey,
+        challenge: &AuthChallenge,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        // [ AUTHENTICATE_CMD | Length | Challenge ]
+        let mut write_buf = [0u8; 2 + AUTH_CHALLENGE_LEN_BYTES as usize];
+        write_buf[0] = AUTHENTICATE_CMD;
+        write_buf[1] = AUTH_CHALLENGE_LEN_BYTES;
+        write_buf[2..].copy_from_slice(challenge);
+        self.write_with_retries(&write_buf, self.config.pec_write).await?;
+
+        self.delay.delay_ms(AUTH_RESPONSE_DELAY_MS).await;
+
+        // [ Length | Digest ]
+        let mut read_buf = [0u8; 1 + AUTH_DIGEST_LEN_BYTES as usize];
+        self.read_with_retries(&[AUTHENTICATE_CMD], &mut read_buf, self.config.pec_read)
+            .await?;
+
+        let mut digest = [0u8; AUTH_DIGEST_LEN_BYTES as usize];
+        digest.copy_from_slice(&read_buf[1..]);
+        if read_buf[0] != AUTH_DIGEST_LEN_BYTES || !digests_match(&digest, &expected_digest(key, challenge)) {
+            return Err(BQ40Z50Error::AuthenticationFailed);
+        }
+
+        Ok(())
+    }
+}
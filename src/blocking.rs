@@ -0,0 +1,77 @@
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use embedded_hal::delay::DelayNs as BlockingDelayTrait;
+use embedded_hal::i2c::I2c as BlockingI2cTrait;
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::{ErrorType, I2c as I2cTrait, Operation, SevenBitAddress};
+
+use crate::interface::DeviceInterface;
+
+/// Adapter exposing a blocking `embedded-hal` bus or delay through the `embedded-hal-async` traits.
+///
+/// Every future produced through this adapter completes on its first poll, which is what allows the blocking
+/// drivers to reuse the async transport (PEC and retry handling) by driving it with `block_on`. Since a transfer is
+/// over before the timeout delay is polled, `Config::timeout_ms` cannot bound a blocking transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Blocking<T>(pub T);
+
+impl<T: embedded_hal::i2c::ErrorType> ErrorType for Blocking<T> {
+    type Error = T::Error;
+}
+
+impl<T: BlockingI2cTrait> I2cTrait for Blocking<T> {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.0.transaction(address, operations)
+    }
+
+    async fn read(&mut self, address: SevenBitAddress, read: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(address, read)
+    }
+
+    async fn write(&mut self, address: SevenBitAddress, write: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, write)
+    }
+
+    async fn write_read(&mut self, address: SevenBitAddress, write: &[u8], read: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.write_read(address, write, read)
+    }
+}
+
+impl<T: BlockingDelayTrait> DelayTrait for Blocking<T> {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.0.delay_ns(ns);
+    }
+
+    async fn delay_us(&mut self, us: u32) {
+        self.0.delay_us(us);
+    }
+
+    async fn delay_ms(&mut self, ms: u32) {
+        self.0.delay_ms(ms);
+    }
+}
+
+/// BQ40Z50 interface over a blocking I2C bus.
+///
+/// Implements the blocking `device-driver` interface traits on top of the shared transport.
+pub type BlockingDeviceInterface<I2C, DELAY> = DeviceInterface<Blocking<I2C>, Blocking<DELAY>>;
+
+/// Drive a future built on [`Blocking`] adapters to completion.
+///
+/// The adapters never return `Pending`, so this resolves on the first poll. The loop only exists so that a future
+/// which does yield (e.g. a timeout racing the bus) is still polled until it finishes.
+pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Guided calibration against known reference values, writing the new coefficients to the `Calibration` data flash.
+//!
+//! Every [`CalibrationStep`] enters calibration mode, averages the raw CCADC samples the gauge outputs (or the
+//! temperatures reported by `DAStatus2()`), leaves calibration mode and writes the coefficients computed from the
+//! reference. The steps are run in the order listed by [`CalibrationStep`], as the current gain depends on both
+//! offsets. Requires the fuel gauge to be unsealed.
+
+use embedded_batteries_async::smart_battery::{DeciKelvin, MilliAmpsSigned, MilliVolts};
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{
+    CALIBRATION_MAX_POLLS, CALIBRATION_MODE_CMD, CALIBRATION_POLL_MS, CCADC_CAL_SIZE_BYTES, DA_STATUS_2_CMD,
+    DA_STATUS_2_TEMPERATURES, EXIT_CALIBRATION_OUTPUT_CMD, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, OUTPUT_CCADC_CAL_CMD,
+    OUTPUT_SHORTED_CCADC_CAL_CMD, SBS_CELL_COUNT, TEMPERATURE_UPDATE_MS,
+};
+use crate::dataflash::{DfParam, DfValue};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+const CALIBRATION: &str = "Calibration";
+/// `Capacity Gain` is `CC Gain` scaled by this factor
+const CAPACITY_GAIN_FACTOR: f32 = 298_261.62;
+/// Voltage gains are the reference voltage over the raw ADC counts, in 1/65536 units
+const VOLTAGE_GAIN_SCALE: i64 = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Temperature sensor with its own offset in the `Calibration` data flash
+pub enum TemperatureSensor {
+    Internal,
+    Ts1,
+    Ts2,
+    Ts3,
+    Ts4,
+}
+
+impl TemperatureSensor {
+    const fn offset_name(self) -> &'static str {
+        match self {
+            Self::Internal => "Internal Temp Offset",
+            Self::Ts1 => "External1 Temp Offset",
+            Self::Ts2 => "External2 Temp Offset",
+            Self::Ts3 => "External3 Temp Offset",
+            Self::Ts4 => "External4 Temp Offset",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Calibration performed by [`calibrate`](crate::Bq40z50::calibrate), with the reference applied to the pack
+pub enum CalibrationStep {
+    /// `CC Offset`, from the coulomb counter with its inputs shorted internally. No reference is needed.
+    CcOffset,
+    /// `Board Offset`, with no current flowing through the sense resistor
+    BoardOffset,
+    /// `Cell Gain` from the first `cell_count` cells, each at `cell`, then `Pack Gain` and `BAT Gain` from the
+    /// `stack` voltage
+    Voltage {
+        cell_count: u8,
+        cell: MilliVolts,
+        stack: MilliVolts,
+    },
+    /// `CC Gain` and `Capacity Gain`, with `reference` flowing through the sense resistor, discharge negative
+    Current { reference: MilliAmpsSigned },
+    /// Temperature offset of `sensor`, held at `reference`
+    Temperature {
+        sensor: TemperatureSensor,
+        reference: DeciKelvin,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Calibration coefficient written by a [`CalibrationStep`]
+pub struct CalibrationChange {
+    pub param: &'static DfParam,
+    pub before: DfValue,
+    pub after: DfValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Coefficients written by a [`CalibrationStep`], up to 3 for [`CalibrationStep::Voltage`]
+pub struct CalibrationReport {
+    pub changes: [Option<CalibrationChange>; 3],
+}
+
+impl CalibrationReport {
+    fn new(changes: &[CalibrationChange]) -> Self {
+        let mut report = Self { changes: [None; 3] };
+        for (slot, change) in report.changes.iter_mut().zip(changes) {
+            *slot = Some(*change);
+        }
+        report
+    }
+
+    /// Iterate over the written coefficients.
+    pub fn iter(&self) -> impl Iterator<Item = &CalibrationChange> {
+        self.changes.iter().flatten()
+    }
+}
+
+/// Raw CCADC outputs summed over every sample
+#[derive(Default)]
+struct CcadcSums {
+    samples: i64,
+    current: i64,
+    cell_voltages: [i64; SBS_CELL_COUNT],
+    pack_voltage: i64,
+    bat_voltage: i64,
+}
+
+impl CcadcSums {
+    /// Add a `MAC_OUTPUT_CCADC_CAL` block: refresh counter, status, current, cell 1 to 4, PACK and BAT voltages, then
+    /// the cell currents.
+    fn add(&mut self, data: &[u8; CCADC_CAL_SIZE_BYTES]) {
+        let word = |i: usize| i16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]);
+        let uword = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]);
+
+        self.samples += 1;
+        self.current += i64::from(word(0));
+        for (i, sum) in self.cell_voltages.iter_mut().enumerate() {
+            *sum += i64::from(uword(1 + i));
+        }
+        self.pack_voltage += i64::from(uword(5));
+        self.bat_voltage += i64::from(uword(6));
+    }
+}
+
+/// Integer division rounded to the nearest, `None` when dividing by zero.
+fn div_round(num: i64, den: i64) -> Option<i64> {
+    if den == 0 {
+        return None;
+    }
+    let (quotient, remainder) = (num / den, num % den);
+    if 2 * remainder.abs() < den.abs() {
+        Some(quotient)
+    } else if (num < 0) == (den < 0) {
+        Some(quotient + 1)
+    } else {
+        Some(quotient - 1)
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Run a calibration `step` on `samples` averaged readings and write the new coefficients to the `Calibration`
+    /// data flash described by `params`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::Timeout`] if the CCADC output stops refreshing,
+    /// [`BQ40Z50Error::Unsupported`] if `params` lacks a calibration parameter, [`BQ40Z50Error::UnexpectedResponse`]
+    /// if the readings do not allow computing a coefficient, or [`BQ40Z50Error::InvalidDfValue`] if the new
+    /// coefficient is out of range.
+    pub(crate) async fn calibrate(
+        &mut self,
+        params: &'static [DfParam],
+        step: CalibrationStep,
+        samples: u8,
+    ) -> Result<CalibrationReport, BQ40Z50Error<I2C::Error>> {
+        let samples = samples.max(1);
+
+        match step {
+            CalibrationStep::CcOffset => {
+                let sums = self.sample_ccadc(true, samples).await?;
+                let (_, offset_samples) = self
+                    .read_cal_int(params, "Current Offset", "Coulomb Counter Offset Samples")
+                    .await?;
+                let (param, before) = self.read_cal_int(params, "Current Offset", "CC Offset").await?;
+                let after =
+                    div_round(sums.current * offset_samples, sums.samples).ok_or(BQ40Z50Error::UnexpectedResponse)?;
+
+                Ok(CalibrationReport::new(&[self
+                    .write_cal_int(param, before, after)
+                    .await?]))
+            }
+            CalibrationStep::BoardOffset => {
+                let sums = self.sample_ccadc(false, samples).await?;
+                let (_, offset_samples) = self
+                    .read_cal_int(params, "Current Offset", "Coulomb Counter Offset Samples")
+                    .await?;
+                let (_, cc_offset) = self.read_cal_int(params, "Current Offset", "CC Offset").await?;
+                let (param, before) = self.read_cal_int(params, "Current Offset", "Board Offset").await?;
+                let after = div_round(sums.current * offset_samples, sums.samples)
+                    .ok_or(BQ40Z50Error::UnexpectedResponse)?
+                    - cc_offset;
+
+                Ok(CalibrationReport::new(&[self
+                    .write_cal_int(param, before, after)
+                    .await?]))
+            }
+            CalibrationStep::Voltage {
+                cell_count,
+                cell,
+                stack,
+            } => {
+                let cell_count = usize::from(cell_count).clamp(1, SBS_CELL_COUNT);
+                let sums = self.sample_ccadc(false, samples).await?;
+                let cell_sum: i64 = sums.cell_voltages[..cell_count].iter().sum();
+
+                let mut changes = [None; 3];
+                for (slot, (name, reference, raw_sum, count)) in changes.iter_mut().zip([
+                    ("Cell Gain", cell, cell_sum, cell_count),
+                    ("Pack Gain", stack, sums.pack_voltage, 1),
+                    ("BAT Gain", stack, sums.bat_voltage, 1),
+                ]) {
+                    // Infallible because the count is at most 4
+                    let count = i64::try_from(count).unwrap();
+                    let after = div_round(
+                        i64::from(reference) * VOLTAGE_GAIN_SCALE * sums.samples * count,
+                        raw_sum,
+                    )
+                    .ok_or(BQ40Z50Error::UnexpectedResponse)?;
+                    let (param, before) = self.read_cal_int(params, "Voltage", name).await?;
+                    *slot = Some(self.write_cal_int(param, before, after).await?);
+                }
+
+                Ok(CalibrationReport { changes })
+            }
+            CalibrationStep::Current { reference } => {
+                let sums = self.sample_ccadc(false, samples).await?;
+                let (_, offset_samples) = self
+                    .read_cal_int(params, "Current Offset", "Coulomb Counter Offset Samples")
+                    .await?;
+                let (_, cc_offset) = self.read_cal_int(params, "Current Offset", "CC Offset").await?;
+                let (_, board_offset) = self.read_cal_int(params, "Current Offset", "Board Offset").await?;
+                let counts = current_counts(&sums, cc_offset + board_offset, offset_samples)
+                    .ok_or(BQ40Z50Error::UnexpectedResponse)?;
+                let cc_gain = f32::from(reference) / counts;
+
+                let (cc_gain_param, cc_gain_before) = self.read_cal_float(params, "CC Gain").await?;
+                let (capacity_gain_param, capacity_gain_before) = self.read_cal_float(params, "Capacity Gain").await?;
+                let changes = [
+                    self.write_cal_float(cc_gain_param, cc_gain_before, cc_gain).await?,
+                    self.write_cal_float(
+                        capacity_gain_param,
+                        capacity_gain_before,
+                        cc_gain * CAPACITY_GAIN_FACTOR,
+                    )
+                    .await?,
+                ];
+
+                Ok(CalibrationReport::new(&changes))
+            }
+            CalibrationStep::Temperature { sensor, reference } => {
+                let mut sum = 0i64;
+                for sample in 0..samples {
+                    if sample != 0 {
+                        self.delay.delay_ms(TEMPERATURE_UPDATE_MS).await;
+                    }
+                    sum += i64::from(self.read_temperatures().await?[sensor as usize]);
+                }
+                let measured = div_round(sum, i64::from(samples)).ok_or(BQ40Z50Error::UnexpectedResponse)?;
+
+                let (param, before) = self.read_cal_int(params, "Temperature", sensor.offset_name()).await?;
+                let after = before + i64::from(reference) - measured;
+
+                Ok(CalibrationReport::new(&[self
+                    .write_cal_int(param, before, after)
+                    .await?]))
+            }
+        }
+    }
+
+    /// Sum `samples` distinct CCADC outputs, entering calibration mode for the duration of the sampling.
+    async fn sample_ccadc(&mut self, shorted: bool, samples: u8) -> Result<CcadcSums, BQ40Z50Error<I2C::Error>> {
+        self.mac_command(CALIBRATION_MODE_CMD).await?;
+
+        let cmd = if shorted {
+            OUTPUT_SHORTED_CCADC_CAL_CMD
+        } else {
+            OUTPUT_CCADC_CAL_CMD
+        };
+        let mut sums = CcadcSums::default();
+        let mut refresh_counter = None;
+        let mut polls = 0;
+        let mut result = Ok(());
+
+        while sums.samples < i64::from(samples) {
+            let mut data = [0u8; CCADC_CAL_SIZE_BYTES];
+            if let Err(e) = self
+                .mac_read_with_retries(
+                    &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, cmd[0], cmd[1]],
+                    &mut data,
+                    self.config.pec_read,
+                )
+                .await
+            {
+                result = Err(e);
+                break;
+            }
+
+            // The refresh counter only changes once the gauge has a new conversion
+            if refresh_counter == Some(data[0]) {
+                polls += 1;
+                if polls > CALIBRATION_MAX_POLLS {
+                    result = Err(BQ40Z50Error::Timeout);
+                    break;
+                }
+                self.delay.delay_ms(CALIBRATION_POLL_MS).await;
+                continue;
+            }
+            refresh_counter = Some(data[0]);
+            sums.add(&data);
+        }
+
+        // Leave calibration mode even if sampling or stopping the output failed, the sampling error takes precedence
+        let exit_output = self.mac_command(EXIT_CALIBRATION_OUTPUT_CMD).await;
+        let exit_mode = self.mac_command(CALIBRATION_MODE_CMD).await;
+
+        result.and(exit_output).and(exit_mode).map(|()| sums)
+    }
+
+    /// Read the internal and TS1 to TS4 temperatures from `DAStatus2()`.
+    async fn read_temperatures(&mut self) -> Result<[DeciKelvin; DA_STATUS_2_TEMPERATURES], BQ40Z50Error<I2C::Error>> {
+        let mut data = [0u8; 2 * DA_STATUS_2_TEMPERATURES];
+        self.mac_read_with_retries(
+            &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, DA_STATUS_2_CMD[0], DA_STATUS_2_CMD[1]],
+            &mut data,
+            self.config.pec_read,
+        )
+        .await?;
+
+        let mut temperatures = [0; DA_STATUS_2_TEMPERATURES];
+        for (temperature, bytes) in temperatures.iter_mut().zip(data.chunks_exact(2)) {
+            *temperature = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(temperatures)
+    }
+
+    async fn mac_command(
+        &mut self,
+        cmd: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.mac_write_with_retries(
+            &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, cmd[0], cmd[1]],
+            self.config.pec_write,
+        )
+        .await
+    }
+
+    async fn read_cal_int(
+        &mut self,
+        params: &'static [DfParam],
+        subclass: &str,
+        name: &str,
+    ) -> Result<(&'static DfParam, i64), BQ40Z50Error<I2C::Error>> {
+        let param = DfParam::lookup(params, CALIBRATION, subclass, name).ok_or(BQ40Z50Error::Unsupported)?;
+        match self.read_df_param(param).await? {
+            DfValue::Int(value) => Ok((param, value)),
+            DfValue::Float(_) | DfValue::Str(_) => Err(BQ40Z50Error::UnexpectedResponse),
+        }
+    }
+
+    async fn read_cal_float(
+        &mut self,
+        params: &'static [DfParam],
+        name: &str,
+    ) -> Result<(&'static DfParam, f32), BQ40Z50Error<I2C::Error>> {
+        let param = DfParam::lookup(params, CALIBRATION, "Current", name).ok_or(BQ40Z50Error::Unsupported)?;
+        match self.read_df_param(param).await? {
+            DfValue::Float(value) => Ok((param, value)),
+            DfValue::Int(_) | DfValue::Str(_) => Err(BQ40Z50Error::UnexpectedResponse),
+        }
+    }
+
+    async fn write_cal_int(
+        &mut self,
+        param: &'static DfParam,
+        before: i64,
+        after: i64,
+    ) -> Result<CalibrationChange, BQ40Z50Error<I2C::Error>> {
+        self.write_df_param(param, &DfValue::Int(after)).await?;
+        Ok(CalibrationChange {
+            param,
+            before: DfValue::Int(before),
+            after: DfValue::Int(after),
+        })
+    }
+
+    async fn write_cal_float(
+        &mut self,
+        param: &'static DfParam,
+        before: f32,
+        after: f32,
+    ) -> Result<CalibrationChange, BQ40Z50Error<I2C::Error>> {
+        self.write_df_param(param, &DfValue::Float(after)).await?;
+        Ok(CalibrationChange {
+            param,
+            before: DfValue::Float(before),
+            after: DfValue::Float(after),
+        })
+    }
+}
+
+/// Average coulomb counter reading with the offsets removed, `None` if no current was measured.
+#[allow(clippy::cast_precision_loss)]
+fn current_counts(sums: &CcadcSums, offsets: i64, offset_samples: i64) -> Option<f32> {
+    if offset_samples == 0 {
+        return None;
+    }
+    let counts = sums.current as f32 / sums.samples as f32 - offsets as f32 / offset_samples as f32;
+    (counts != 0.0).then_some(counts)
+}
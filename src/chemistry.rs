@@ -0,0 +1,212 @@
+//! Chemistry programming from a TI chemistry data flash `FlashStream`, verified against the chem ID and static
+//! chemistry data flash signature the gauge reports afterwards.
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{
+    CHEM_ID_CMD, DF_END_ADDR, DF_START_ADDR, LARGEST_DF_BLOCK_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES,
+    SIGNATURE_DELAY_MS, SIGNATURE_MISMATCH_MASK, STATIC_CHEM_DF_SIG_CMD,
+};
+use crate::error::BQ40Z50Error;
+use crate::flashstream::{FlashStream, FlashStreamCommand};
+use crate::interface::DeviceInterface;
+use crate::security::SecurityState;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Chemistry the gauge reports through `MAC_CHEM_ID` and `MAC_STATIC_CHEM_DF_SIG`
+pub struct ChemistryInfo {
+    pub chem_id: u16,
+    /// Static chemistry data flash signature, without the mismatch flag
+    pub signature: u16,
+    /// The signature computed by the gauge does not match the stored one
+    pub signature_mismatch: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Data flash block of a chemistry image
+pub struct ChemRecord {
+    /// 1-based line of the record
+    pub line: usize,
+    pub address: u16,
+    len: usize,
+    data: [u8; LARGEST_DF_BLOCK_SIZE_BYTES],
+}
+
+impl ChemRecord {
+    /// Data bytes of the record.
+    #[must_use]
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Parsed chemistry image
+///
+/// A chemistry image is the chemistry data block TI exports as a data flash `FlashStream` (`.df.fs`, see
+/// [`FlashStream`]), whose data flash writes are `ManufacturerBlockAccess()` block writes:
+///
+/// `W: 16 44 NN AA AA DD ..` writes the bytes `DD ..` (up to 32) to the data flash address `AAAA` (little endian),
+/// where `NN` is the block count, 2 address bytes plus the data bytes.
+///
+/// Any other write, e.g. a key sent to `ManufacturerAccess()`, is rejected: the image may only write data flash. `R:`,
+/// `C:` and `X:` commands are accepted but not played, the image is instead verified through the chem ID and static
+/// chemistry data flash signature, and the driver paces its own data flash writes. The bus address of the writes is
+/// ignored, they go to the gauge at the configured address.
+pub struct ChemImage<'a> {
+    contents: &'a str,
+}
+
+impl<'a> ChemImage<'a> {
+    /// Parse and validate the chemistry image held in `contents`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err` with the 1-based line of the first malformed or rejected command, or 0 if the image writes no data
+    /// flash.
+    pub fn parse(contents: &'a str) -> Result<Self, usize> {
+        let mut records = 0;
+        for command in FlashStream::new(contents) {
+            let (line, command) = command.map_err(|e| e.line())?;
+            if parse_command(line, &command)?.is_some() {
+                records += 1;
+            }
+        }
+
+        if records == 0 {
+            return Err(0);
+        }
+        Ok(Self { contents })
+    }
+
+    /// Iterate over the data flash blocks of the image.
+    pub fn records(&self) -> impl Iterator<Item = ChemRecord> + 'a {
+        FlashStream::new(self.contents).filter_map(|command| {
+            let (line, command) = command.ok()?;
+            parse_command(line, &command).ok()?
+        })
+    }
+}
+
+/// Parse the data flash block written by one command, `None` if it writes nothing.
+///
+/// Returns `Err` with `line` if the command is rejected.
+fn parse_command(line: usize, command: &FlashStreamCommand) -> Result<Option<ChemRecord>, usize> {
+    let FlashStreamCommand::Write { data, .. } = command else {
+        return Ok(None);
+    };
+
+    // [ MAC (0x44) | Count | Address (2 bytes) | Data (up to 32 bytes) ]
+    let &[MAC_CMD, count, address_lo, address_hi, ref bytes @ ..] = data.as_bytes() else {
+        return Err(line);
+    };
+    if bytes.is_empty() || bytes.len() > LARGEST_DF_BLOCK_SIZE_BYTES || usize::from(count) != 2 + bytes.len() {
+        return Err(line);
+    }
+
+    let mut record = ChemRecord {
+        line,
+        address: u16::from_le_bytes([address_lo, address_hi]),
+        len: bytes.len(),
+        data: [0u8; LARGEST_DF_BLOCK_SIZE_BYTES],
+    };
+    record.data[..bytes.len()].copy_from_slice(bytes);
+
+    let end = u32::from(record.address) + u32::try_from(record.len).map_err(|_| line)?;
+    if u32::from(record.address) < DF_START_ADDR || end > DF_END_ADDR + 1 {
+        return Err(line);
+    }
+
+    Ok(Some(record))
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read `MAC_CHEM_ID` and `MAC_STATIC_CHEM_DF_SIG`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub(crate) async fn read_chemistry(&mut self) -> Result<ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+        let mut chem_id = [0u8; 2];
+        self.mac_read_with_retries(
+            &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, CHEM_ID_CMD[0], CHEM_ID_CMD[1]],
+            &mut chem_id,
+            self.config.pec_read,
+        )
+        .await?;
+
+        let mut signature = [0u8; 2];
+        self.mac_read_with_retries(
+            &[
+                MAC_CMD,
+                MAC_CMD_ADDR_SIZE_BYTES,
+                STATIC_CHEM_DF_SIG_CMD[0],
+                STATIC_CHEM_DF_SIG_CMD[1],
+            ],
+            &mut signature,
+            self.config.pec_read,
+        )
+        .await?;
+        let signature = u16::from_le_bytes(signature);
+
+        Ok(ChemistryInfo {
+            chem_id: u16::from_le_bytes(chem_id),
+            signature: signature & !SIGNATURE_MISMATCH_MASK,
+            signature_mismatch: signature & SIGNATURE_MISMATCH_MASK != 0,
+        })
+    }
+
+    /// Write every data flash block of the chemistry image held in `contents`, then check that the gauge reports
+    /// `chem_id` and the static chemistry data flash `signature`. Requires the fuel gauge to be in full access.
+    ///
+    /// The whole image is validated before anything is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::SecurityStateMismatch`] if the gauge is not in
+    /// full access, [`BQ40Z50Error::InvalidImage`] if the image does not parse, [`BQ40Z50Error::ChemIdMismatch`] or
+    /// [`BQ40Z50Error::ChemSignatureMismatch`] if the gauge does not report the programmed chemistry.
+    pub(crate) async fn program_chemistry(
+        &mut self,
+        contents: &str,
+        chem_id: u16,
+        signature: u16,
+    ) -> Result<ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+        let image = ChemImage::parse(contents).map_err(|line| BQ40Z50Error::InvalidImage { line })?;
+
+        let actual = self.read_security_state().await?;
+        if actual != SecurityState::FullAccess {
+            return Err(BQ40Z50Error::SecurityStateMismatch {
+                expected: SecurityState::FullAccess,
+                actual,
+            });
+        }
+
+        for record in image.records() {
+            self.mac_write_to_df_with_retries(record.address, record.data(), self.config.pec_write)
+                .await?;
+        }
+        // The signature is only valid once the gauge has computed it over the new tables
+        self.delay.delay_ms(SIGNATURE_DELAY_MS).await;
+
+        let info = self.read_chemistry().await?;
+        if info.chem_id != chem_id {
+            return Err(BQ40Z50Error::ChemIdMismatch {
+                expected: chem_id,
+                actual: info.chem_id,
+            });
+        }
+        if info.signature_mismatch || info.signature != signature & !SIGNATURE_MISMATCH_MASK {
+            return Err(BQ40Z50Error::ChemSignatureMismatch {
+                expected: signature,
+                actual: info.signature,
+            });
+        }
+
+        Ok(info)
+    }
+}
@@ -1,27 +1,40 @@
+use crate::retry::RetryPolicy;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 /// Configurable settings
 pub struct Config {
-    /// Max number of bus retries if an error occurs.
-    pub max_bus_retries: usize,
+    /// 7-bit `SMBus` target address of the fuel gauge.
+    ///
+    /// Defaults to `0x0B`, the Smart Battery address. Used for every bus transfer as well as PEC byte derivation.
+    pub address: u8,
+    /// Retries and backoff applied to failed bus transfers, per kind of failure.
+    ///
+    /// Replaces `max_bus_retries`; `RetryPolicy::uniform(Retry::fixed(n, 10))` retries like `max_bus_retries: n` did.
+    pub retry: RetryPolicy,
     /// Verify data using PEC byte when reading.
     pub pec_read: bool,
     /// Append PEC byte when writing.
     pub pec_write: bool,
-    #[cfg(feature = "embassy-timeout")]
-    /// Timeout time
-    pub timeout: embassy_time::Duration,
+    /// Time a single bus transfer may take before it fails with [`Timeout`](crate::BQ40Z50Error::Timeout), measured
+    /// with the driver's delay. `None` waits for the bus indefinitely.
+    ///
+    /// Defaults to `None`, or to 100 ms with the `embassy-timeout` feature.
+    ///
+    /// Has no effect on the blocking drivers: a [`Blocking`](crate::Blocking) bus completes each transfer before the
+    /// delay is first polled, so a transfer can never run out of time.
+    pub timeout_ms: Option<u32>,
 }
 
 impl Config {
     #[must_use]
     pub const fn new() -> Self {
         Self {
-            max_bus_retries: crate::consts::DEFAULT_BUS_RETRIES,
+            address: crate::consts::BQ_ADDR,
+            retry: RetryPolicy::new(),
             pec_read: false,
             pec_write: false,
-            #[cfg(feature = "embassy-timeout")]
-            timeout: crate::consts::DEFAULT_TIMEOUT,
+            timeout_ms: crate::consts::DEFAULT_TIMEOUT_MS,
         }
     }
 }
@@ -32,6 +45,41 @@ impl Default for Config {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// BQ40Z50 chip revision
+pub enum Revision {
+    R1,
+    R3,
+    R4,
+    R5,
+}
+
+impl Revision {
+    /// Identify the revision from the major firmware version reported by `MAC_FIRMWARE_VERSION`.
+    #[must_use]
+    pub const fn from_major_version(major: u8) -> Option<Self> {
+        match major {
+            0x01 => Some(Self::R1),
+            0x03 => Some(Self::R3),
+            0x04 => Some(Self::R4),
+            0x05 => Some(Self::R5),
+            _ => None,
+        }
+    }
+
+    /// Major firmware version shipped on this revision.
+    #[must_use]
+    pub const fn major_version(self) -> u8 {
+        match self {
+            Self::R1 => 0x01,
+            Self::R3 => 0x03,
+            Self::R4 => 0x04,
+            Self::R5 => 0x05,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub(crate) enum CapacityModeState {
     Milliamps = 0,
@@ -39,7 +87,6 @@ pub(crate) enum CapacityModeState {
 }
 
 #[allow(clippy::struct_field_names)]
-#[cfg(any(feature = "r3", feature = "r4", feature = "r5"))]
 /// Charging Voltage Override config struct used in MAC command 0x00B0, not used in R1
 #[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ChargingVoltageOverride {
@@ -418,3 +465,929 @@ macro_rules! implement_embedded_batteries {
 }
 
 pub(crate) use implement_embedded_batteries;
+
+macro_rules! implement_driver_operations {
+    ($impl_type:ident, $revision:expr, $df:ident) => {
+        impl<I2C: I2cTrait, DELAY: DelayTrait> $impl_type<I2C, DELAY> {
+            /// Challenge the pack through `Authenticate()` and verify its SHA-1 digest against `key`.
+            ///
+            /// Use [`generate_challenge`](crate::generate_challenge) to create a fresh challenge for every call.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::AuthenticationFailed`] if the pack
+            /// returned the wrong digest.
+            pub async fn authenticate(
+                &mut self,
+                key: &crate::auth::AuthKey,
+                challenge: &crate::auth::AuthChallenge,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.authenticate(key, challenge).await
+            }
+
+            /// Read the current security mode from `OperationStatus`.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn security_state(&mut self) -> Result<crate::SecurityState, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_security_state().await
+            }
+
+            /// Seal the fuel gauge and verify it reports [`crate::SecurityState::Sealed`] afterwards.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge
+            /// did not seal.
+            pub async fn seal(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.seal().await
+            }
+
+            /// Unseal the fuel gauge and verify it reports [`crate::SecurityState::Unsealed`] afterwards.
+            ///
+            /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not
+            /// accept the key.
+            pub async fn unseal(&mut self, key_lower: u16, key_upper: u16) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.unseal(key_lower, key_upper).await
+            }
+
+            /// Send the full access key to an unsealed gauge and verify it reports [`crate::SecurityState::FullAccess`]
+            /// afterwards.
+            ///
+            /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::SecurityStateMismatch`] if the gauge is
+            /// sealed, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the key.
+            pub async fn enter_full_access(
+                &mut self,
+                key_lower: u16,
+                key_upper: u16,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.enter_full_access(key_lower, key_upper).await
+            }
+
+            /// Seal the gauge before its next bus transfer.
+            ///
+            /// For code that cannot await [`seal`](Self::seal), such as a `Drop` impl. The seal is not verified.
+            pub fn seal_before_next_transfer(&mut self) {
+                self.device.interface.seal_before_next_transfer();
+            }
+
+            /// Play a `FlashStream` (`.bq.fs` / `.df.fs`) file against the device, calling `progress` after every
+            /// command.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` with the line of the failing command if the stream does not parse, an I2C bus error
+            /// occurs, or a `C:` command read back different bytes.
+            pub async fn run_flash_stream(
+                &mut self,
+                contents: &str,
+                progress: impl FnMut(crate::FlashStreamProgress),
+            ) -> Result<(), crate::FlashStreamError<BQ40Z50Error<I2C::Error>>> {
+                self.device.interface.run_flash_stream(contents, progress).await
+            }
+
+            /// Read the requested `fields` of the standard SBS telemetry in one call.
+            ///
+            /// Capacities are reported in the units of the current `CAPACITY_MODE`. `timestamp` is called before the
+            /// first and after the last read, so [`Snapshot::bus_time`](crate::Snapshot::bus_time) measures the time
+            /// spent on the bus.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn read_snapshot(
+                &mut self,
+                fields: crate::SnapshotFields,
+                timestamp: impl FnMut() -> u64,
+            ) -> Result<crate::Snapshot, BQ40Z50Error<I2C::Error>> {
+                self.device
+                    .interface
+                    .read_snapshot(fields, self.capacity_mode_state.get(), timestamp)
+                    .await
+            }
+
+            /// Read the raw value of a 32-bit status register.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn read_status(
+                &mut self,
+                register: crate::StatusRegister,
+            ) -> Result<u32, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_status(register).await
+            }
+
+            /// Send `MAC_PF_DATA_RST` to clear the permanent failure flags and the device status captured at the time
+            /// of failure. Requires the fuel gauge to be in full access.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn reset_pf_data(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.reset_pf_data().await
+            }
+
+            /// Send `MAC_BLK_BOX_REC_RESET` to clear the black box recorder. Requires the fuel gauge to be in full
+            /// access.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn reset_black_box(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.reset_black_box().await
+            }
+
+            /// Read every `MAC_LIFETIME_DATA_BLOCK_n` recorded by this revision and decode them into one report, for
+            /// warranty analysis. Requires the fuel gauge to be unsealed.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn read_lifetime_data(
+                &mut self,
+            ) -> Result<crate::lifetime::LifetimeData, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_lifetime_data($revision).await
+            }
+
+            /// Run a calibration `step` against its reference, averaging `samples` readings, and write the new
+            /// coefficients to the `Calibration` data flash. Requires the fuel gauge to be unsealed.
+            ///
+            /// Returns the coefficients before and after calibration.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::Timeout`](crate::BQ40Z50Error::Timeout) if
+            /// the CCADC output stops refreshing,
+            /// [`BQ40Z50Error::UnexpectedResponse`](crate::BQ40Z50Error::UnexpectedResponse) if the readings do not
+            /// allow computing a coefficient, or [`BQ40Z50Error::InvalidDfValue`](crate::BQ40Z50Error::InvalidDfValue)
+            /// if a new coefficient is out of range.
+            pub async fn calibrate(
+                &mut self,
+                step: crate::calibration::CalibrationStep,
+                samples: u8,
+            ) -> Result<crate::calibration::CalibrationReport, BQ40Z50Error<I2C::Error>> {
+                self.device
+                    .interface
+                    .calibrate(crate::$df::PARAMS, step, samples)
+                    .await
+            }
+
+            /// Read `GaugingStatus()`, `GaugeStatus2()` and `GaugeStatus3()`, which track the Impedance Track learning
+            /// cycle.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn read_learning_status(
+                &mut self,
+            ) -> Result<crate::learning::LearningStatus, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_learning_status($revision).await
+            }
+
+            /// Read the chem ID and static chemistry data flash signature of the programmed chemistry.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn read_chemistry(&mut self) -> Result<crate::ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_chemistry().await
+            }
+
+            /// Write the chemistry tables of a chemistry image to data flash, then verify the gauge reports `chem_id`
+            /// and the static chemistry data flash `signature`. Requires the fuel gauge to be in full access.
+            ///
+            /// See [`ChemImage`](crate::ChemImage) for the format. The whole image is validated before anything is
+            /// written.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, the gauge is not in full access, the image does not parse,
+            /// or the gauge reports a different chem ID or signature.
+            pub async fn program_chemistry(
+                &mut self,
+                contents: &str,
+                chem_id: u16,
+                signature: u16,
+            ) -> Result<crate::ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.program_chemistry(contents, chem_id, signature).await
+            }
+
+            /// Read the instruction flash, static data flash, static chemistry data flash and all data flash
+            /// signatures, waiting for the gauge to compute each one.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn read_signatures(&mut self) -> Result<crate::Signatures, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_signatures().await
+            }
+
+            /// Read every flash signature and compare them against `expected`, e.g. recorded from a golden pack, to
+            /// detect which regions were tampered with or corrupted.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub async fn verify_signatures(
+                &mut self,
+                expected: crate::Signatures,
+            ) -> Result<crate::SignatureReport, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.verify_signatures(expected).await
+            }
+
+            #[doc = concat!(
+                "Read a data flash parameter from [`",
+                stringify!($df),
+                "`](crate::",
+                stringify!($df),
+                ") and decode it."
+            )]
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the data flash
+            /// holds a string longer than the parameter allows.
+            pub async fn read_df_param(
+                &mut self,
+                param: &crate::DfParam,
+            ) -> Result<crate::DfValue, BQ40Z50Error<I2C::Error>> {
+                self.device.interface.read_df_param(param).await
+            }
+
+            #[doc = concat!(
+                "Range-check and encode a data flash parameter from [`",
+                stringify!($df),
+                "`](crate::",
+                stringify!($df),
+                "), then write it."
+            )]
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::InvalidDfValue`] if `value` does not
+            /// match the parameter's type or is outside its range.
+            pub async fn write_df_param(
+                &mut self,
+                param: &crate::DfParam,
+                value: &crate::DfValue,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                self.device.interface.write_df_param(param, value).await
+            }
+        }
+    };
+}
+
+pub(crate) use implement_driver_operations;
+
+macro_rules! implement_blocking {
+    ($blocking_type:ident, $impl_type:ident) => {
+        use embedded_hal::delay::DelayNs as BlockingDelayTrait;
+        use embedded_hal::i2c::I2c as BlockingI2cTrait;
+
+        use crate::blocking::{Blocking, BlockingDeviceInterface, block_on};
+
+        #[doc = concat!("Blocking counterpart of [`", stringify!($impl_type), "`] for synchronous `embedded-hal` buses.")]
+        ///
+        /// Every operation is forwarded to the async driver, so PEC handling and bus retries behave identically.
+        pub struct $blocking_type<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> {
+            inner: $impl_type<Blocking<I2C>, Blocking<DELAY>>,
+        }
+
+        impl<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> $blocking_type<I2C, DELAY> {
+            pub fn new(i2c: I2C, delay: DELAY) -> Self {
+                $blocking_type {
+                    inner: $impl_type::new(Blocking(i2c), Blocking(delay)),
+                }
+            }
+
+            pub fn new_with_config(i2c: I2C, delay: DELAY, config: Config) -> Self {
+                $blocking_type {
+                    inner: $impl_type::new_with_config(Blocking(i2c), Blocking(delay), config),
+                }
+            }
+
+            /// Change interface config.
+            pub fn update_config(&mut self, config: Config) {
+                self.inner.update_config(config);
+            }
+
+            pub fn config(&self) -> Config {
+                self.inner.config()
+            }
+
+            /// Access the register level API using the blocking `device-driver` operations.
+            pub fn device(&mut self) -> &mut Device<BlockingDeviceInterface<I2C, DELAY>> {
+                &mut self.inner.device
+            }
+
+            /// Read the current security mode from `OperationStatus`.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn security_state(&mut self) -> Result<crate::SecurityState, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.security_state())
+            }
+
+            /// Seal the fuel gauge and verify it reports [`crate::SecurityState::Sealed`] afterwards.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge
+            /// did not seal.
+            pub fn seal(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.seal())
+            }
+
+            /// Unseal the fuel gauge and verify it reports [`crate::SecurityState::Unsealed`] afterwards.
+            ///
+            /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not
+            /// accept the key.
+            pub fn unseal(&mut self, key_lower: u16, key_upper: u16) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.unseal(key_lower, key_upper))
+            }
+
+            /// Send the full access key to an unsealed gauge and verify it reports [`crate::SecurityState::FullAccess`]
+            /// afterwards.
+            ///
+            /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::SecurityStateMismatch`] if the gauge is
+            /// sealed, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the key.
+            pub fn enter_full_access(
+                &mut self,
+                key_lower: u16,
+                key_upper: u16,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.enter_full_access(key_lower, key_upper))
+            }
+
+            /// Seal the gauge before its next bus transfer. The seal is not verified.
+            pub fn seal_before_next_transfer(&mut self) {
+                self.inner.seal_before_next_transfer();
+            }
+
+            /// Read MAC Register 0x0035 Security Keys.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_security_keys(
+                &mut self,
+                output_buf: &mut [u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_security_keys(output_buf))
+            }
+
+            /// Write MAC Register 0x0035 Security Keys.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn write_security_keys(
+                &mut self,
+                security_keys: &[u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.write_security_keys(security_keys))
+            }
+
+            /// Read MAC Register 0x0037 Authentication Key.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_authentication_key(
+                &mut self,
+                output_buf: &mut [u8; AUTH_KEY_DATA_LEN_BYTES as usize],
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_authentication_key(output_buf))
+            }
+
+            /// Write MAC Register 0x0037 Authentication Key.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn write_authentication_key(
+                &mut self,
+                auth_key: &[u8; AUTH_KEY_LEN_BYTES as usize],
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.write_authentication_key(auth_key))
+            }
+
+            /// Challenge the pack through `Authenticate()` and verify its SHA-1 digest against `key`.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::AuthenticationFailed`] if the pack
+            /// returned the wrong digest.
+            pub fn authenticate(
+                &mut self,
+                key: &crate::auth::AuthKey,
+                challenge: &crate::auth::AuthChallenge,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.authenticate(key, challenge))
+            }
+
+            /// Read data from an arbitrary register from the device.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            ///
+            /// # Safety
+            ///
+            /// This function should only be called with valid register addresses and data slice size.
+            #[allow(unsafe_code)]
+            pub unsafe fn read_register_unchecked(
+                &mut self,
+                reg_address: u8,
+                data: &mut [u8],
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                // SAFETY: The caller upholds the same contract as the async counterpart.
+                block_on(unsafe { self.inner.read_register_unchecked(reg_address, data) })
+            }
+
+            /// Write data to an arbitrary register on the device.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            ///
+            /// # Safety
+            ///
+            /// This function should only be called with valid register addresses and data slice size.
+            /// Ensure that the register is able to be written to.
+            #[allow(unsafe_code)]
+            pub unsafe fn write_register_unchecked(
+                &mut self,
+                reg_address: u8,
+                data: &[u8],
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                // SAFETY: The caller upholds the same contract as the async counterpart.
+                block_on(unsafe { self.inner.write_register_unchecked(reg_address, data) })
+            }
+
+            /// Seal the fuel gauge and verify it reports [`crate::SecurityState::Sealed`] afterwards.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge
+            /// did not seal.
+            #[deprecated(note = "use `seal`")]
+            pub fn seal_fg(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.seal())
+            }
+
+            /// Unseal the fuel gauge and verify it reports [`crate::SecurityState::Unsealed`] afterwards.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not
+            /// accept the key.
+            #[deprecated(note = "use `unseal`")]
+            pub fn unseal_fg(
+                &mut self,
+                unseal_key_lower: u16,
+                unseal_key_upper: u16,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.unseal(unseal_key_lower, unseal_key_upper))
+            }
+
+            /// Send access keys.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn send_access_key(
+                &mut self,
+                access_key_lower: u16,
+                access_key_upper: u16,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.send_access_key(access_key_lower, access_key_upper))
+            }
+
+            /// Write to the `MfgInfo` register.
+            ///
+            /// Requires fuel gauge to be unsealed. Call `unseal()` first, and then reseal with `seal()` after this command.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn write_mfg_info(&mut self, data: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.write_mfg_info(data))
+            }
+
+            /// Read from the `MfgInfo` register, including the leading size byte.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_mfg_info(&mut self, data: &mut [u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_mfg_info(data))
+            }
+
+            /// Read from the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_dataflash(&mut self, starting_address: u16, read: &mut [u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_dataflash(starting_address, read))
+            }
+
+            /// Write to the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn write_dataflash(&mut self, starting_address: u16, write: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.write_dataflash(starting_address, write))
+            }
+
+            /// Read a data flash parameter and decode it.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the data flash
+            /// holds a string longer than the parameter allows.
+            pub fn read_df_param(&mut self, param: &crate::DfParam) -> Result<crate::DfValue, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_df_param(param))
+            }
+
+            /// Range-check and encode a data flash parameter, then write it.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::InvalidDfValue`] if `value` does not
+            /// match the parameter's type or is outside its range.
+            pub fn write_df_param(
+                &mut self,
+                param: &crate::DfParam,
+                value: &crate::DfValue,
+            ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.write_df_param(param, value))
+            }
+
+            /// Play a `FlashStream` (`.bq.fs` / `.df.fs`) file against the device, calling `progress` after every
+            /// command.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` with the line of the failing command if the stream does not parse, an I2C bus error
+            /// occurs, or a `C:` command read back different bytes.
+            pub fn run_flash_stream(
+                &mut self,
+                contents: &str,
+                progress: impl FnMut(crate::FlashStreamProgress),
+            ) -> Result<(), crate::FlashStreamError<BQ40Z50Error<I2C::Error>>> {
+                block_on(self.inner.run_flash_stream(contents, progress))
+            }
+
+            /// Read the requested `fields` of the standard SBS telemetry in one call.
+            ///
+            /// Capacities are reported in the units of the current `CAPACITY_MODE`. `timestamp` is called before the
+            /// first and after the last read, so [`Snapshot::bus_time`](crate::Snapshot::bus_time) measures the time
+            /// spent on the bus.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_snapshot(
+                &mut self,
+                fields: crate::SnapshotFields,
+                timestamp: impl FnMut() -> u64,
+            ) -> Result<crate::Snapshot, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_snapshot(fields, timestamp))
+            }
+
+            /// Read the raw value of a 32-bit status register.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_status(&mut self, register: crate::StatusRegister) -> Result<u32, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_status(register))
+            }
+
+            /// Send `MAC_PF_DATA_RST` to clear the permanent failure flags and the device status captured at the time
+            /// of failure. Requires the fuel gauge to be in full access.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn reset_pf_data(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.reset_pf_data())
+            }
+
+            /// Send `MAC_BLK_BOX_REC_RESET` to clear the black box recorder. Requires the fuel gauge to be in full
+            /// access.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn reset_black_box(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.reset_black_box())
+            }
+
+            /// Read every `MAC_LIFETIME_DATA_BLOCK_n` recorded by this revision and decode them into one report, for
+            /// warranty analysis. Requires the fuel gauge to be unsealed.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_lifetime_data(&mut self) -> Result<crate::lifetime::LifetimeData, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_lifetime_data())
+            }
+
+            /// Run a calibration `step` against its reference, averaging `samples` readings, and write the new
+            /// coefficients to the `Calibration` data flash. Requires the fuel gauge to be unsealed.
+            ///
+            /// Returns the coefficients before and after calibration.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::Timeout`](crate::BQ40Z50Error::Timeout) if
+            /// the CCADC output stops refreshing,
+            /// [`BQ40Z50Error::UnexpectedResponse`](crate::BQ40Z50Error::UnexpectedResponse) if the readings do not
+            /// allow computing a coefficient, or [`BQ40Z50Error::InvalidDfValue`](crate::BQ40Z50Error::InvalidDfValue)
+            /// if a new coefficient is out of range.
+            pub fn calibrate(
+                &mut self,
+                step: crate::calibration::CalibrationStep,
+                samples: u8,
+            ) -> Result<crate::calibration::CalibrationReport, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.calibrate(step, samples))
+            }
+
+            /// Read `GaugingStatus()`, `GaugeStatus2()` and `GaugeStatus3()`, which track the Impedance Track learning
+            /// cycle.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_learning_status(
+                &mut self,
+            ) -> Result<crate::learning::LearningStatus, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_learning_status())
+            }
+
+            /// Read the chem ID and static chemistry data flash signature of the programmed chemistry.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_chemistry(&mut self) -> Result<crate::ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_chemistry())
+            }
+
+            /// Write the chemistry tables of a chemistry image to data flash, then verify the gauge reports `chem_id`
+            /// and the static chemistry data flash `signature`. Requires the fuel gauge to be in full access.
+            ///
+            /// See [`ChemImage`](crate::ChemImage) for the format. The whole image is validated before anything is
+            /// written.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs, the gauge is not in full access, the image does not parse,
+            /// or the gauge reports a different chem ID or signature.
+            pub fn program_chemistry(
+                &mut self,
+                contents: &str,
+                chem_id: u16,
+                signature: u16,
+            ) -> Result<crate::ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.program_chemistry(contents, chem_id, signature))
+            }
+
+            /// Read the instruction flash, static data flash, static chemistry data flash and all data flash
+            /// signatures, waiting for the gauge to compute each one.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn read_signatures(&mut self) -> Result<crate::Signatures, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.read_signatures())
+            }
+
+            /// Read every flash signature and compare them against `expected`, e.g. recorded from a golden pack, to
+            /// detect which regions were tampered with or corrupted.
+            ///
+            /// # Errors
+            ///
+            /// Will return `Err` if an I2C bus error occurs.
+            pub fn verify_signatures(
+                &mut self,
+                expected: crate::Signatures,
+            ) -> Result<crate::SignatureReport, BQ40Z50Error<I2C::Error>> {
+                block_on(self.inner.verify_signatures(expected))
+            }
+        }
+    };
+}
+
+pub(crate) use implement_blocking;
+
+macro_rules! implement_bq40z50 {
+    ($impl_type:ident, $revision:expr, $data_flash:expr, $mfg_info_c_access:ty, { $($revision_specific:tt)* }) => {
+        impl<I2C: I2cTrait, DELAY: DelayTrait> crate::traits::Bq40z50 for $impl_type<I2C, DELAY> {
+            const REVISION: crate::common::Revision = $revision;
+            const DATA_FLASH: &'static [crate::DfParam] = $data_flash;
+
+            type Device = Device<DeviceInterface<I2C, DELAY>>;
+            type MfgInfoCAccess = $mfg_info_c_access;
+
+            fn device(&mut self) -> &mut Self::Device {
+                &mut self.device
+            }
+
+            fn config(&self) -> Config {
+                $impl_type::config(self)
+            }
+
+            fn update_config(&mut self, config: Config) {
+                $impl_type::update_config(self, config);
+            }
+
+            async fn seal_fg(&mut self) -> Result<(), Self::Error> {
+                $impl_type::seal(self).await
+            }
+
+            async fn unseal_fg(&mut self, unseal_key_lower: u16, unseal_key_upper: u16) -> Result<(), Self::Error> {
+                $impl_type::unseal(self, unseal_key_lower, unseal_key_upper).await
+            }
+
+            async fn send_access_key(&mut self, access_key_lower: u16, access_key_upper: u16) -> Result<(), Self::Error> {
+                $impl_type::send_access_key(self, access_key_lower, access_key_upper).await
+            }
+
+            async fn security_state(&mut self) -> Result<crate::SecurityState, Self::Error> {
+                $impl_type::security_state(self).await
+            }
+
+            async fn seal(&mut self) -> Result<(), Self::Error> {
+                $impl_type::seal(self).await
+            }
+
+            async fn unseal(&mut self, key_lower: u16, key_upper: u16) -> Result<(), Self::Error> {
+                $impl_type::unseal(self, key_lower, key_upper).await
+            }
+
+            async fn enter_full_access(&mut self, key_lower: u16, key_upper: u16) -> Result<(), Self::Error> {
+                $impl_type::enter_full_access(self, key_lower, key_upper).await
+            }
+
+            fn seal_before_next_transfer(&mut self) {
+                $impl_type::seal_before_next_transfer(self);
+            }
+
+            async fn read_security_keys(
+                &mut self,
+                output_buf: &mut [u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+            ) -> Result<(), Self::Error> {
+                $impl_type::read_security_keys(self, output_buf).await
+            }
+
+            async fn write_security_keys(
+                &mut self,
+                security_keys: &[u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+            ) -> Result<(), Self::Error> {
+                $impl_type::write_security_keys(self, security_keys).await
+            }
+
+            async fn read_authentication_key(
+                &mut self,
+                output_buf: &mut [u8; AUTH_KEY_DATA_LEN_BYTES as usize],
+            ) -> Result<(), Self::Error> {
+                $impl_type::read_authentication_key(self, output_buf).await
+            }
+
+            async fn write_authentication_key(
+                &mut self,
+                auth_key: &[u8; AUTH_KEY_LEN_BYTES as usize],
+            ) -> Result<(), Self::Error> {
+                $impl_type::write_authentication_key(self, auth_key).await
+            }
+
+            async fn authenticate(
+                &mut self,
+                key: &crate::auth::AuthKey,
+                challenge: &crate::auth::AuthChallenge,
+            ) -> Result<(), Self::Error> {
+                $impl_type::authenticate(self, key, challenge).await
+            }
+
+            async fn write_mfg_info(&mut self, data: &[u8]) -> Result<(), Self::Error> {
+                $impl_type::write_mfg_info(self, data).await
+            }
+
+            async fn read_mfg_info(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+                $impl_type::read_mfg_info(self, data).await
+            }
+
+            async fn read_dataflash(&mut self, starting_address: u16, read: &mut [u8]) -> Result<(), Self::Error> {
+                $impl_type::read_dataflash(self, starting_address, read).await
+            }
+
+            async fn write_dataflash(&mut self, starting_address: u16, write: &[u8]) -> Result<(), Self::Error> {
+                $impl_type::write_dataflash(self, starting_address, write).await
+            }
+
+            async fn read_df_param(&mut self, param: &crate::DfParam) -> Result<crate::DfValue, Self::Error> {
+                $impl_type::read_df_param(self, param).await
+            }
+
+            async fn write_df_param(&mut self, param: &crate::DfParam, value: &crate::DfValue) -> Result<(), Self::Error> {
+                $impl_type::write_df_param(self, param, value).await
+            }
+
+            async fn run_flash_stream(
+                &mut self,
+                contents: &str,
+                progress: impl FnMut(crate::FlashStreamProgress),
+            ) -> Result<(), crate::FlashStreamError<Self::Error>> {
+                $impl_type::run_flash_stream(self, contents, progress).await
+            }
+
+            async fn read_snapshot(
+                &mut self,
+                fields: crate::SnapshotFields,
+                timestamp: impl FnMut() -> u64,
+            ) -> Result<crate::Snapshot, Self::Error> {
+                $impl_type::read_snapshot(self, fields, timestamp).await
+            }
+
+            async fn read_status(&mut self, register: crate::StatusRegister) -> Result<u32, Self::Error> {
+                $impl_type::read_status(self, register).await
+            }
+
+            async fn reset_pf_data(&mut self) -> Result<(), Self::Error> {
+                $impl_type::reset_pf_data(self).await
+            }
+
+            async fn reset_black_box(&mut self) -> Result<(), Self::Error> {
+                $impl_type::reset_black_box(self).await
+            }
+
+            async fn read_lifetime_data(&mut self) -> Result<crate::lifetime::LifetimeData, Self::Error> {
+                $impl_type::read_lifetime_data(self).await
+            }
+
+            async fn calibrate(
+                &mut self,
+                step: crate::calibration::CalibrationStep,
+                samples: u8,
+            ) -> Result<crate::calibration::CalibrationReport, Self::Error> {
+                $impl_type::calibrate(self, step, samples).await
+            }
+
+            async fn read_learning_status(&mut self) -> Result<crate::learning::LearningStatus, Self::Error> {
+                $impl_type::read_learning_status(self).await
+            }
+
+            async fn read_chemistry(&mut self) -> Result<crate::ChemistryInfo, Self::Error> {
+                $impl_type::read_chemistry(self).await
+            }
+
+            async fn program_chemistry(
+                &mut self,
+                contents: &str,
+                chem_id: u16,
+                signature: u16,
+            ) -> Result<crate::ChemistryInfo, Self::Error> {
+                $impl_type::program_chemistry(self, contents, chem_id, signature).await
+            }
+
+            async fn read_signatures(&mut self) -> Result<crate::Signatures, Self::Error> {
+                $impl_type::read_signatures(self).await
+            }
+
+            async fn verify_signatures(
+                &mut self,
+                expected: crate::Signatures,
+            ) -> Result<crate::SignatureReport, Self::Error> {
+                $impl_type::verify_signatures(self, expected).await
+            }
+
+            $($revision_specific)*
+        }
+    };
+}
+
+pub(crate) use implement_bq40z50;
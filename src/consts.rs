@@ -2,6 +2,12 @@ pub(crate) const LARGEST_REG_SIZE_BYTES: usize = 32;
 pub(crate) const LARGEST_CMD_SIZE_BYTES: usize = 32;
 pub(crate) const LARGEST_BUF_SIZE_BYTES: usize = 33;
 pub(crate) const LARGEST_DF_BLOCK_SIZE_BYTES: usize = 32;
+pub(crate) const DF_STRING_MAX_LEN: usize = 32;
+pub(crate) const FLASH_STREAM_MAX_BYTES: usize = 128;
+
+// Data flash spans 0x4000 to 0x5FFF
+pub(crate) const DF_START_ADDR: u32 = 0x4000;
+pub(crate) const DF_END_ADDR: u32 = 0x5FFF;
 
 pub(crate) const BQ_ADDR: u8 = 0x0B;
 pub(crate) const MAC_CMD_ADDR_SIZE_BYTES: u8 = 2;
@@ -19,6 +25,97 @@ pub(crate) const AUTH_KEY_LEN_BYTES: u8 = AUTH_KEY_DATA_LEN_BYTES + MAC_CMD_ADDR
 
 pub(crate) const MFG_INFO_CMD: u8 = 0x70;
 
+// Identification MAC commands, shared by every revision
+pub(crate) const DEVICE_TYPE_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0001u16.to_le_bytes();
+pub(crate) const DEVICE_TYPE_SIZE_BYTES: usize = 2;
+pub(crate) const FIRMWARE_VERSION_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0002u16.to_le_bytes();
+pub(crate) const FIRMWARE_VERSION_SIZE_BYTES: usize = 11;
+pub(crate) const BQ40Z50_DEVICE_TYPE: u16 = 0x4500;
+
+// SHA-1 authentication through the Authenticate() block command
+pub(crate) const AUTHENTICATE_CMD: u8 = 0x2F;
+pub(crate) const AUTH_CHALLENGE_LEN_BYTES: u8 = 20;
+pub(crate) const AUTH_DIGEST_LEN_BYTES: u8 = 20;
+pub(crate) const AUTH_RESPONSE_DELAY_MS: u32 = 250;
+
+// Status registers, shared by every revision, all 4 bytes
+pub(crate) const SAFETY_ALERT_REG: u8 = 0x50;
+pub(crate) const SAFETY_STATUS_REG: u8 = 0x51;
+pub(crate) const PF_ALERT_REG: u8 = 0x52;
+pub(crate) const PF_STATUS_REG: u8 = 0x53;
+pub(crate) const STATUS_REG_SIZE_BYTES: usize = 4;
+
+// Security mode transitions, shared by every revision
+pub(crate) const OPERATION_STATUS_REG: u8 = 0x54;
+pub(crate) const OPERATION_STATUS_SIZE_BYTES: usize = 4;
+pub(crate) const OPERATION_STATUS_SEC_SHIFT: u32 = 8;
+pub(crate) const OPERATION_STATUS_SEC_MASK: u32 = 0b11;
+pub(crate) const SEAL_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0030u16.to_le_bytes();
+pub(crate) const WRONG_KEY_LOCKOUT_MS: u32 = 4000;
+
+// Permanent failure diagnostics, shared by every revision
+pub(crate) const PF_DATA_RESET_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0029u16.to_le_bytes();
+pub(crate) const BLACK_BOX_RESET_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x002Au16.to_le_bytes();
+pub(crate) const BLACK_BOX_EVENTS: usize = 3;
+
+// Lifetime data blocks, MAC_LIFETIME_DATA_BLOCK_1 to _15 are consecutive, R5 adds _16 further up
+pub(crate) const LIFETIME_DATA_BLOCK_1_CMD: u16 = 0x0060;
+pub(crate) const LIFETIME_DATA_BLOCK_16_CMD: u16 = 0x007E;
+pub(crate) const LIFETIME_DATA_BLOCKS: usize = 16;
+
+// Calibration, MAC_CALIBRATION_MODE toggles the mode that enables the CCADC output commands
+pub(crate) const CALIBRATION_MODE_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x002Du16.to_le_bytes();
+pub(crate) const EXIT_CALIBRATION_OUTPUT_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0xF080u16.to_le_bytes();
+pub(crate) const OUTPUT_CCADC_CAL_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0xF081u16.to_le_bytes();
+pub(crate) const OUTPUT_SHORTED_CCADC_CAL_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0xF082u16.to_le_bytes();
+pub(crate) const CCADC_CAL_SIZE_BYTES: usize = 24;
+pub(crate) const CALIBRATION_POLL_MS: u32 = 50;
+pub(crate) const CALIBRATION_MAX_POLLS: usize = 100;
+// MAC_DA_STATUS_2 starts with the internal and TS1 to TS4 temperatures on every revision
+pub(crate) const DA_STATUS_2_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0072u16.to_le_bytes();
+pub(crate) const DA_STATUS_2_TEMPERATURES: usize = 5;
+pub(crate) const TEMPERATURE_UPDATE_MS: u32 = 1000;
+
+// Impedance Track learning status, sizes as in the device manifests
+pub(crate) const GAUGING_STATUS_REG: u8 = 0x56;
+pub(crate) const GAUGING_STATUS_SIZE_BYTES: usize = 4;
+pub(crate) const GAUGE_STATUS_2_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0074u16.to_le_bytes();
+pub(crate) const GAUGE_STATUS_2_SIZE_BYTES: usize = 32;
+pub(crate) const GAUGE_STATUS_3_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0075u16.to_le_bytes();
+// R5 only sends its PEC after 32 bytes, see `MAC_GAUGE_STATUS_3` in device_r5.yaml
+pub(crate) const GAUGE_STATUS_3_SIZE_BYTES: usize = 24;
+pub(crate) const GAUGE_STATUS_3_R5_SIZE_BYTES: usize = 32;
+
+// Chemistry identification, shared by every revision
+pub(crate) const CHEM_ID_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0006u16.to_le_bytes();
+pub(crate) const STATIC_CHEM_DF_SIG_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0008u16.to_le_bytes();
+pub(crate) const SIGNATURE_MISMATCH_MASK: u16 = 0x8000;
+
+// Flash signatures, shared by every revision, valid once the gauge has computed them
+pub(crate) const INSTRUCTION_FLASH_SIG_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0004u16.to_le_bytes();
+pub(crate) const STATIC_DF_SIG_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0005u16.to_le_bytes();
+pub(crate) const ALL_DF_SIG_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x0009u16.to_le_bytes();
+pub(crate) const SIGNATURE_REGIONS: usize = 4;
+pub(crate) const SIGNATURE_DELAY_MS: u32 = 250;
+
+// Standard SBS commands read by a telemetry snapshot, all 2-byte words
+pub(crate) const SBS_TEMPERATURE_CMD: u8 = 0x08;
+pub(crate) const SBS_VOLTAGE_CMD: u8 = 0x09;
+pub(crate) const SBS_CURRENT_CMD: u8 = 0x0A;
+pub(crate) const SBS_AVERAGE_CURRENT_CMD: u8 = 0x0B;
+pub(crate) const SBS_RELATIVE_STATE_OF_CHARGE_CMD: u8 = 0x0D;
+pub(crate) const SBS_ABSOLUTE_STATE_OF_CHARGE_CMD: u8 = 0x0E;
+pub(crate) const SBS_REMAINING_CAPACITY_CMD: u8 = 0x0F;
+pub(crate) const SBS_FULL_CHARGE_CAPACITY_CMD: u8 = 0x10;
+pub(crate) const SBS_RUN_TIME_TO_EMPTY_CMD: u8 = 0x11;
+pub(crate) const SBS_AVERAGE_TIME_TO_EMPTY_CMD: u8 = 0x12;
+pub(crate) const SBS_AVERAGE_TIME_TO_FULL_CMD: u8 = 0x13;
+pub(crate) const SBS_BATTERY_STATUS_CMD: u8 = 0x16;
+pub(crate) const SBS_CYCLE_COUNT_CMD: u8 = 0x17;
+// CellVoltage4 is 0x3C, counting up to CellVoltage1 at 0x3F
+pub(crate) const SBS_CELL_VOLTAGE_4_CMD: u8 = 0x3C;
+pub(crate) const SBS_CELL_COUNT: usize = 4;
+
 #[cfg(not(all(feature = "r1", not(any(feature = "r3", feature = "r4", feature = "r5")))))]
 pub(crate) const CHRG_VOLTAGE_OVERRIDE_CMD: [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] = 0x00B0u16.to_le_bytes();
 #[cfg(not(all(feature = "r1", not(any(feature = "r3", feature = "r4", feature = "r5")))))]
@@ -27,7 +124,6 @@ pub(crate) const CHRG_VOLTAGE_OVERRIDE_SIZE_BYTES: u8 = 10;
 pub(crate) const DEFAULT_BUS_RETRIES: usize = 3;
 pub(crate) const DEFAULT_ERROR_BACKOFF_DELAY_MS: u32 = 10;
 #[cfg(feature = "embassy-timeout")]
-pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
-
-#[cfg(feature = "embassy-timeout")]
-use embassy_time::Duration;
+pub(crate) const DEFAULT_TIMEOUT_MS: Option<u32> = Some(100);
+#[cfg(not(feature = "embassy-timeout"))]
+pub(crate) const DEFAULT_TIMEOUT_MS: Option<u32> = None;
@@ -0,0 +1,256 @@
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::DF_STRING_MAX_LEN;
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Encoding of a data flash parameter, as listed in the TRM data flash tables
+pub enum DfType {
+    /// Signed 8-bit integer
+    I1,
+    /// Signed 16-bit integer
+    I2,
+    /// Signed 32-bit integer
+    I4,
+    /// Unsigned 8-bit integer
+    U1,
+    /// Unsigned 16-bit integer
+    U2,
+    /// Unsigned 32-bit integer
+    U4,
+    /// 8-bit flags
+    H1,
+    /// 16-bit flags
+    H2,
+    /// 32-bit flags
+    H4,
+    /// IEEE 754 single precision float
+    F4,
+    /// Length-prefixed string holding up to the given number of characters
+    S(u8),
+}
+
+impl DfType {
+    /// Number of data flash bytes taken by a parameter of this type.
+    #[must_use]
+    pub const fn size_bytes(self) -> usize {
+        match self {
+            Self::I1 | Self::U1 | Self::H1 => 1,
+            Self::I2 | Self::U2 | Self::H2 => 2,
+            Self::I4 | Self::U4 | Self::H4 | Self::F4 => 4,
+            Self::S(max_len) => 1 + max_len as usize,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Valid values of a data flash parameter
+pub enum DfRange {
+    /// Inclusive bounds of an integer or flags parameter
+    Int { min: i64, max: i64 },
+    /// Inclusive bounds of a float parameter
+    Float { min: f32, max: f32 },
+    /// String parameters are only bounded by the length of their [`DfType::S`]
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// String value of a data flash parameter
+pub struct DfString {
+    len: u8,
+    bytes: [u8; DF_STRING_MAX_LEN],
+}
+
+impl DfString {
+    /// Create a string from raw bytes. Returns `None` if `bytes` is longer than 32 bytes.
+    #[must_use]
+    pub const fn new(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() > DF_STRING_MAX_LEN {
+            return None;
+        }
+
+        let mut buf = [0u8; DF_STRING_MAX_LEN];
+        let mut i = 0;
+        while i < bytes.len() {
+            buf[i] = bytes[i];
+            i += 1;
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        Some(Self {
+            len: bytes.len() as u8,
+            bytes: buf,
+        })
+    }
+
+    /// Raw bytes of the string.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+
+    /// The string as UTF-8, or `None` if the gauge holds bytes that are not valid UTF-8.
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        core::str::from_utf8(self.as_bytes()).ok()
+    }
+}
+
+impl<'a> TryFrom<&'a str> for DfString {
+    type Error = &'a str;
+
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        Self::new(value.as_bytes()).ok_or(value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Decoded value of a data flash parameter
+pub enum DfValue {
+    /// Value of an integer or flags parameter
+    Int(i64),
+    /// Value of a float parameter
+    Float(f32),
+    /// Value of a string parameter
+    Str(DfString),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Data flash parameter definition, as listed in the TRM data flash tables
+pub struct DfParam {
+    /// Class the parameter is listed under, e.g. `Calibration`
+    pub class: &'static str,
+    /// Subclass the parameter is listed under, e.g. `Voltage`
+    pub subclass: &'static str,
+    /// Name of the parameter, e.g. `Cell Gain`
+    pub name: &'static str,
+    /// Data flash address, between 0x4000 and 0x5FFF
+    pub address: u16,
+    /// Encoding of the parameter
+    pub ty: DfType,
+    /// Units of the parameter, empty if it has none
+    pub units: &'static str,
+    /// Values accepted by [`encode`](Self::encode)
+    pub range: DfRange,
+    /// Factory default value
+    pub default: DfValue,
+}
+
+impl DfParam {
+    /// Find a parameter by class, subclass and name in a revision's parameter table.
+    #[must_use]
+    pub fn lookup(params: &'static [DfParam], class: &str, subclass: &str, name: &str) -> Option<&'static DfParam> {
+        params
+            .iter()
+            .find(|p| p.class == class && p.subclass == subclass && p.name == name)
+    }
+
+    /// Encode `value` into `buf`, returning the number of bytes written.
+    ///
+    /// Returns `None` if `value` does not match the parameter's type, is outside its range, or `buf` is too small.
+    #[must_use]
+    pub fn encode(&self, value: &DfValue, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..self.ty.size_bytes())?;
+
+        match (self.ty, value) {
+            (DfType::S(max_len), DfValue::Str(s)) => {
+                let bytes = s.as_bytes();
+                if bytes.len() > max_len as usize {
+                    return None;
+                }
+                buf.fill(0);
+                buf[0] = u8::try_from(bytes.len()).ok()?;
+                buf[1..=bytes.len()].copy_from_slice(bytes);
+            }
+            (DfType::F4, DfValue::Float(f)) => {
+                let DfRange::Float { min, max } = self.range else {
+                    return None;
+                };
+                if !(min..=max).contains(f) {
+                    return None;
+                }
+                buf.copy_from_slice(&f.to_le_bytes());
+            }
+            (DfType::S(_) | DfType::F4, _) | (_, DfValue::Float(_) | DfValue::Str(_)) => return None,
+            (ty, DfValue::Int(i)) => {
+                if let DfRange::Int { min, max } = self.range {
+                    if !(min..=max).contains(i) {
+                        return None;
+                    }
+                }
+                match ty {
+                    DfType::I1 => buf.copy_from_slice(&i8::try_from(*i).ok()?.to_le_bytes()),
+                    DfType::I2 => buf.copy_from_slice(&i16::try_from(*i).ok()?.to_le_bytes()),
+                    DfType::I4 => buf.copy_from_slice(&i32::try_from(*i).ok()?.to_le_bytes()),
+                    DfType::U1 | DfType::H1 => buf.copy_from_slice(&u8::try_from(*i).ok()?.to_le_bytes()),
+                    DfType::U2 | DfType::H2 => buf.copy_from_slice(&u16::try_from(*i).ok()?.to_le_bytes()),
+                    DfType::U4 | DfType::H4 => buf.copy_from_slice(&u32::try_from(*i).ok()?.to_le_bytes()),
+                    DfType::F4 | DfType::S(_) => return None,
+                }
+            }
+        }
+
+        Some(self.ty.size_bytes())
+    }
+
+    /// Decode the parameter from its data flash bytes.
+    ///
+    /// Returns `None` if `buf` is too small or holds a string longer than the parameter allows.
+    #[must_use]
+    pub fn decode(&self, buf: &[u8]) -> Option<DfValue> {
+        let buf = buf.get(..self.ty.size_bytes())?;
+
+        Some(match self.ty {
+            DfType::I1 => DfValue::Int(i8::from_le_bytes(buf.try_into().ok()?).into()),
+            DfType::I2 => DfValue::Int(i16::from_le_bytes(buf.try_into().ok()?).into()),
+            DfType::I4 => DfValue::Int(i32::from_le_bytes(buf.try_into().ok()?).into()),
+            DfType::U1 | DfType::H1 => DfValue::Int(buf[0].into()),
+            DfType::U2 | DfType::H2 => DfValue::Int(u16::from_le_bytes(buf.try_into().ok()?).into()),
+            DfType::U4 | DfType::H4 => DfValue::Int(u32::from_le_bytes(buf.try_into().ok()?).into()),
+            DfType::F4 => DfValue::Float(f32::from_le_bytes(buf.try_into().ok()?)),
+            DfType::S(_) => DfValue::Str(DfString::new(buf[1..].get(..buf[0] as usize)?)?),
+        })
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read a data flash parameter and decode it according to its definition.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the data flash holds a
+    /// string longer than the parameter allows.
+    pub async fn read_df_param(&mut self, param: &DfParam) -> Result<DfValue, BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; 1 + DF_STRING_MAX_LEN];
+        let buf = buf.get_mut(..param.ty.size_bytes()).ok_or(BQ40Z50Error::DataTooLarge)?;
+
+        if self.config.pec_read {
+            self.mac_read_from_df_with_retries_pec(param.address, buf).await?;
+        } else {
+            self.mac_read_from_df_with_retries(param.address, buf).await?;
+        }
+
+        param.decode(buf).ok_or(BQ40Z50Error::UnexpectedResponse)
+    }
+
+    /// Range-check and encode `value` according to its definition, then write it to the data flash.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::InvalidDfValue`] if `value` does not match
+    /// the parameter's type or is outside its range.
+    pub async fn write_df_param(&mut self, param: &DfParam, value: &DfValue) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; 1 + DF_STRING_MAX_LEN];
+        let len = param.encode(value, &mut buf).ok_or(BQ40Z50Error::InvalidDfValue)?;
+
+        self.mac_write_to_df_with_retries(param.address, &buf[..len], self.config.pec_write)
+            .await
+    }
+}
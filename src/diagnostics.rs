@@ -0,0 +1,271 @@
+//! Permanent failure diagnostics, combining the live status registers with the data the gauge captured in data flash
+//! when the failure latched.
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{BLACK_BOX_EVENTS, BLACK_BOX_RESET_CMD, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, PF_DATA_RESET_CMD};
+use crate::dataflash::{DfParam, DfValue};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+use crate::status::{ActiveFlags, StatusRegister};
+use crate::traits::Bq40z50;
+
+const PF_DATA: &str = "PF Status";
+const PF_DATA_SUBCLASS: &str = "Device Status Data";
+const BLACK_BOX: &str = "Black Box";
+const BLACK_BOX_SUBCLASS: &str = "Safety Status";
+const BLACK_BOX_ENTRIES: [(&str, &str); BLACK_BOX_EVENTS] = [
+    ("1st Safety Status", "1st Time to Next Event"),
+    ("2nd Safety Status", "2nd Time to Next Event"),
+    ("3rd Safety Status", "3rd Time to Next Event"),
+];
+/// Largest data flash subclass the report reads in one go.
+const REGION_MAX_SIZE_BYTES: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Entry of the black box recorder, which keeps the last safety events before a permanent failure
+pub struct BlackBoxEvent {
+    /// `SafetyStatus()` at the time of the event
+    pub safety_status: u32,
+    /// Time until the following event, in seconds
+    pub time_to_next_event_s: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Device status the gauge captured in the `PF Status` data flash when the permanent failure latched
+pub struct PfCapture {
+    pub safety_alert: u32,
+    pub safety_status: u32,
+    pub pf_alert: u32,
+    pub pf_status: u32,
+    pub fuse_flag: u16,
+    /// Cell 1 to cell 4, in mV
+    pub cell_voltages: [i16; 4],
+    /// In mA
+    pub current: i16,
+    /// TS1 to TS4, in 0.1 K
+    pub ts_temperatures: [i16; 4],
+    /// In 0.1 K
+    pub fet_temperature: i16,
+    /// In 0.1 K
+    pub internal_temperature: i16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Permanent failure diagnostics report, see [`PfReport::read`]
+pub struct PfReport {
+    /// Live `PFStatus()`
+    pub pf_status: u32,
+    /// Live `PFAlert()`
+    pub pf_alert: u32,
+    /// Black box recorder entries, oldest first. Unused entries read as zero.
+    pub black_box: Option<[BlackBoxEvent; BLACK_BOX_EVENTS]>,
+    /// Device status captured at the time of failure
+    pub capture: Option<PfCapture>,
+}
+
+impl PfReport {
+    /// Read the live permanent failure registers, the black box recorder and the captured device status into one
+    /// report. Requires the fuel gauge to be unsealed to read the data flash.
+    ///
+    /// The data flash parts are `None` if the revision's [`DATA_FLASH`](Bq40z50::DATA_FLASH) table does not define
+    /// them.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read<T: Bq40z50>(bq: &mut T) -> Result<Self, T::Error> {
+        Ok(Self {
+            pf_status: bq.read_status(StatusRegister::PfStatus).await?,
+            pf_alert: bq.read_status(StatusRegister::PfAlert).await?,
+            black_box: read_black_box(bq).await?,
+            capture: read_capture(bq).await?,
+        })
+    }
+
+    /// Whether a permanent failure has latched.
+    #[must_use]
+    pub const fn latched(&self) -> bool {
+        self.pf_status != 0
+    }
+
+    /// Decode the latched permanent failures.
+    #[must_use]
+    pub const fn failures(&self) -> ActiveFlags {
+        ActiveFlags::new(StatusRegister::PfStatus, self.pf_status)
+    }
+}
+
+/// Data flash subclass read with a single data flash read, so the gauge cannot update it partway through and the
+/// parameters are consistent with each other.
+struct Region {
+    params: &'static [DfParam],
+    class: &'static str,
+    subclass: &'static str,
+    start: u16,
+    buf: [u8; REGION_MAX_SIZE_BYTES],
+}
+
+impl Region {
+    /// Read the span of every parameter the revision's data flash table defines in `class`/`subclass`, `None` if it
+    /// defines none or they span more than [`REGION_MAX_SIZE_BYTES`].
+    async fn read<T: Bq40z50>(
+        bq: &mut T,
+        class: &'static str,
+        subclass: &'static str,
+    ) -> Result<Option<Self>, T::Error> {
+        let params = T::DATA_FLASH;
+        let mut span: Option<(u16, usize)> = None;
+        for param in params.iter().filter(|p| p.class == class && p.subclass == subclass) {
+            let end = usize::from(param.address) + param.ty.size_bytes();
+            span = Some(span.map_or((param.address, end), |(start, prev_end)| {
+                (start.min(param.address), prev_end.max(end))
+            }));
+        }
+
+        let Some((start, end)) = span else {
+            return Ok(None);
+        };
+        let mut buf = [0u8; REGION_MAX_SIZE_BYTES];
+        let Some(read) = buf.get_mut(..end - usize::from(start)) else {
+            return Ok(None);
+        };
+        bq.read_dataflash(start, read).await?;
+
+        Ok(Some(Self {
+            params,
+            class,
+            subclass,
+            start,
+            buf,
+        }))
+    }
+
+    /// Decode an integer parameter of the region, `None` if the table does not define it.
+    fn int(&self, name: &str) -> Option<i64> {
+        let param = DfParam::lookup(self.params, self.class, self.subclass, name)?;
+        let offset = usize::from(param.address - self.start);
+
+        match param.decode(&self.buf[offset..])? {
+            DfValue::Int(value) => Some(value),
+            DfValue::Float(_) | DfValue::Str(_) => None,
+        }
+    }
+
+    /// Decode a captured 32-bit status register, stored as its `A` (lowest) to `D` bytes.
+    fn register(&self, bytes: [&str; 4]) -> Option<u32> {
+        bytes.into_iter().enumerate().try_fold(0, |value, (i, name)| {
+            let byte = u8::try_from(self.int(name)?).ok()?;
+            Some(value | u32::from(byte) << (8 * i))
+        })
+    }
+
+    fn i16(&self, name: &str) -> Option<i16> {
+        i16::try_from(self.int(name)?).ok()
+    }
+}
+
+async fn read_capture<T: Bq40z50>(bq: &mut T) -> Result<Option<PfCapture>, T::Error> {
+    let Some(region) = Region::read(bq, PF_DATA, PF_DATA_SUBCLASS).await? else {
+        return Ok(None);
+    };
+
+    Ok(decode_capture(&region))
+}
+
+fn decode_capture(region: &Region) -> Option<PfCapture> {
+    Some(PfCapture {
+        safety_alert: region.register(["Safety Alert A", "Safety Alert B", "Safety Alert C", "Safety Alert D"])?,
+        safety_status: region.register([
+            "Safety Status A",
+            "Safety Status B",
+            "Safety Status C",
+            "Safety Status D",
+        ])?,
+        pf_alert: region.register(["PF Alert A", "PF Alert B", "PF Alert C", "PF Alert D"])?,
+        pf_status: region.register(["PF Status A", "PF Status B", "PF Status C", "PF Status D"])?,
+        fuse_flag: u16::try_from(region.int("Fuse Flag")?).ok()?,
+        cell_voltages: [
+            region.i16("Cell 1 Voltage")?,
+            region.i16("Cell 2 Voltage")?,
+            region.i16("Cell 3 Voltage")?,
+            region.i16("Cell 4 Voltage")?,
+        ],
+        current: region.i16("Current")?,
+        ts_temperatures: [
+            region.i16("TS1 Temperature")?,
+            region.i16("TS2 Temperature")?,
+            region.i16("TS3 Temperature")?,
+            region.i16("TS4 Temperature")?,
+        ],
+        fet_temperature: region.i16("FET Temperature")?,
+        internal_temperature: region.i16("Internal Temperature")?,
+    })
+}
+
+async fn read_black_box<T: Bq40z50>(bq: &mut T) -> Result<Option<[BlackBoxEvent; BLACK_BOX_EVENTS]>, T::Error> {
+    let Some(region) = Region::read(bq, BLACK_BOX, BLACK_BOX_SUBCLASS).await? else {
+        return Ok(None);
+    };
+
+    let mut events = [BlackBoxEvent::default(); BLACK_BOX_EVENTS];
+    for (event, (status_name, time_name)) in events.iter_mut().zip(BLACK_BOX_ENTRIES) {
+        let (Some(safety_status), Some(time_to_next_event_s)) = (region.int(status_name), region.int(time_name))
+        else {
+            return Ok(None);
+        };
+        let (Ok(safety_status), Ok(time_to_next_event_s)) =
+            (u32::try_from(safety_status), u8::try_from(time_to_next_event_s))
+        else {
+            return Ok(None);
+        };
+        *event = BlackBoxEvent {
+            safety_status,
+            time_to_next_event_s,
+        };
+    }
+
+    Ok(Some(events))
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Send `MAC_PF_DATA_RST` to clear the permanent failure flags and the captured device status.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn reset_pf_data(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.mac_write_with_retries(
+            &[
+                MAC_CMD,
+                MAC_CMD_ADDR_SIZE_BYTES,
+                PF_DATA_RESET_CMD[0],
+                PF_DATA_RESET_CMD[1],
+            ],
+            self.config.pec_write,
+        )
+        .await
+    }
+
+    /// Send `MAC_BLK_BOX_REC_RESET` to clear the black box recorder.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn reset_black_box(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.mac_write_with_retries(
+            &[
+                MAC_CMD,
+                MAC_CMD_ADDR_SIZE_BYTES,
+                BLACK_BOX_RESET_CMD[0],
+                BLACK_BOX_RESET_CMD[1],
+            ],
+            self.config.pec_write,
+        )
+        .await
+    }
+}
@@ -0,0 +1,748 @@
+use embedded_batteries_async::smart_battery::{
+    self, BatteryModeFields, BatteryStatusFields, CapacityModeSignedValue, CapacityModeValue, DeciKelvin, SmartBattery,
+    SpecificationInfoFields,
+};
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::auth::{AuthChallenge, AuthKey};
+use crate::common::{ChargingVoltageOverride, Config, Revision};
+use crate::consts::{
+    AUTH_KEY_DATA_LEN_BYTES, AUTH_KEY_LEN_BYTES, BQ40Z50_DEVICE_TYPE, DEVICE_TYPE_CMD, DEVICE_TYPE_SIZE_BYTES,
+    FIRMWARE_VERSION_CMD, FIRMWARE_VERSION_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, SECURITY_KEYS_DATA_LEN_BYTES,
+};
+use crate::dataflash::{DfParam, DfValue};
+use crate::error::BQ40Z50Error;
+use crate::flashstream::{FlashStreamError, FlashStreamProgress};
+use crate::interface::DeviceInterface;
+use crate::security::SecurityState;
+use crate::traits::Bq40z50;
+#[cfg(feature = "r1")]
+use crate::versions::r1::Bq40z50R1;
+#[cfg(feature = "r3")]
+use crate::versions::r3::Bq40z50R3;
+#[cfg(feature = "r4")]
+use crate::versions::r4::Bq40z50R4;
+#[cfg(feature = "r5")]
+use crate::versions::r5::Bq40z50R5;
+
+/// Identify the revision from the `MAC_DEVICE_TYPE` and raw `MAC_FIRMWARE_VERSION` responses.
+///
+/// `DeviceType()` returns the IC part number as a little endian word. `FirmwareVersion()` returns the block
+/// `ddDD vvVV bbBB TT zzZZ RR EE`, where the device number, version and build number are big endian words and the high
+/// byte of the version is the major firmware version.
+fn identify<E>(
+    device_type: u16,
+    firmware_version: &[u8; FIRMWARE_VERSION_SIZE_BYTES],
+) -> Result<Revision, BQ40Z50Error<E>> {
+    let version = u16::from_be_bytes([firmware_version[2], firmware_version[3]]);
+
+    if device_type != BQ40Z50_DEVICE_TYPE {
+        return Err(BQ40Z50Error::UnknownRevision {
+            device_type,
+            firmware_version: version,
+        });
+    }
+
+    Revision::from_major_version(version.to_be_bytes()[0]).ok_or(BQ40Z50Error::UnknownRevision {
+        device_type,
+        firmware_version: version,
+    })
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Identify the chip revision by reading `MAC_DEVICE_TYPE` and `MAC_FIRMWARE_VERSION`.
+    ///
+    /// Both commands live at the same address on every revision, so this works before the revision is known.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnknownRevision`] if the device is not a
+    /// supported BQ40Z50 revision.
+    pub async fn detect_revision(&mut self) -> Result<Revision, BQ40Z50Error<I2C::Error>> {
+        let mut device_type = [0u8; DEVICE_TYPE_SIZE_BYTES];
+        self.mac_read_with_retries(
+            &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, DEVICE_TYPE_CMD[0], DEVICE_TYPE_CMD[1]],
+            &mut device_type,
+            self.config.pec_read,
+        )
+        .await?;
+
+        let mut firmware_version = [0u8; FIRMWARE_VERSION_SIZE_BYTES];
+        self.mac_read_with_retries(
+            &[
+                MAC_CMD,
+                MAC_CMD_ADDR_SIZE_BYTES,
+                FIRMWARE_VERSION_CMD[0],
+                FIRMWARE_VERSION_CMD[1],
+            ],
+            &mut firmware_version,
+            self.config.pec_read,
+        )
+        .await?;
+
+        identify(u16::from_le_bytes(device_type), &firmware_version)
+    }
+}
+
+/// Driver for whichever BQ40Z50 revision is found on the bus at runtime.
+///
+/// Created with [`AnyBq40z50::probe`], which identifies the gauge and wraps the matching revision driver. Only the
+/// revisions enabled through cargo features can be selected. Match on the variants to reach revision-specific APIs.
+pub enum AnyBq40z50<I2C: I2cTrait, DELAY: DelayTrait> {
+    #[cfg(feature = "r1")]
+    R1(Bq40z50R1<I2C, DELAY>),
+    #[cfg(feature = "r3")]
+    R3(Bq40z50R3<I2C, DELAY>),
+    #[cfg(feature = "r4")]
+    R4(Bq40z50R4<I2C, DELAY>),
+    #[cfg(feature = "r5")]
+    R5(Bq40z50R5<I2C, DELAY>),
+}
+
+macro_rules! dispatch {
+    ($self:expr, $bq:ident => $body:expr) => {
+        match $self {
+            #[cfg(feature = "r1")]
+            AnyBq40z50::R1($bq) => $body,
+            #[cfg(feature = "r3")]
+            AnyBq40z50::R3($bq) => $body,
+            #[cfg(feature = "r4")]
+            AnyBq40z50::R4($bq) => $body,
+            #[cfg(feature = "r5")]
+            AnyBq40z50::R5($bq) => $body,
+        }
+    };
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> AnyBq40z50<I2C, DELAY> {
+    /// Probe the gauge on the bus and create the driver for its revision.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::UnknownRevision`] if the device is not a
+    /// supported BQ40Z50 revision, or [`BQ40Z50Error::Unsupported`] if the detected revision's feature is disabled.
+    /// The bus and delay are handed back alongside the error.
+    #[allow(clippy::type_complexity)]
+    pub async fn probe(i2c: I2C, delay: DELAY, config: Config) -> Result<Self, (BQ40Z50Error<I2C::Error>, I2C, DELAY)> {
+        let mut interface = DeviceInterface::new_with_config(i2c, delay, config);
+        let revision = interface.detect_revision().await;
+        let DeviceInterface { i2c, delay, config, .. } = interface;
+
+        match revision {
+            #[cfg(feature = "r1")]
+            Ok(Revision::R1) => Ok(Self::R1(Bq40z50R1::new_with_config(i2c, delay, config))),
+            #[cfg(feature = "r3")]
+            Ok(Revision::R3) => Ok(Self::R3(Bq40z50R3::new_with_config(i2c, delay, config))),
+            #[cfg(feature = "r4")]
+            Ok(Revision::R4) => Ok(Self::R4(Bq40z50R4::new_with_config(i2c, delay, config))),
+            #[cfg(feature = "r5")]
+            Ok(Revision::R5) => Ok(Self::R5(Bq40z50R5::new_with_config(i2c, delay, config))),
+            #[allow(unreachable_patterns)]
+            Ok(_) => Err((BQ40Z50Error::Unsupported, i2c, delay)),
+            Err(e) => Err((e, i2c, delay)),
+        }
+    }
+
+    /// Revision of the wrapped driver.
+    pub fn revision(&self) -> Revision {
+        match self {
+            #[cfg(feature = "r1")]
+            Self::R1(_) => Revision::R1,
+            #[cfg(feature = "r3")]
+            Self::R3(_) => Revision::R3,
+            #[cfg(feature = "r4")]
+            Self::R4(_) => Revision::R4,
+            #[cfg(feature = "r5")]
+            Self::R5(_) => Revision::R5,
+        }
+    }
+
+    pub fn config(&self) -> Config {
+        dispatch!(self, bq => Bq40z50::config(bq))
+    }
+
+    /// Change interface config.
+    pub fn update_config(&mut self, config: Config) {
+        dispatch!(self, bq => Bq40z50::update_config(bq, config));
+    }
+
+    /// Seal the fuel gauge and verify it reports [`SecurityState::Sealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge did not
+    /// seal.
+    #[deprecated(note = "use `seal`")]
+    pub async fn seal_fg(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::seal(bq).await)
+    }
+
+    /// Unseal the fuel gauge and verify it reports [`SecurityState::Unsealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the
+    /// key.
+    #[deprecated(note = "use `unseal`")]
+    pub async fn unseal_fg(
+        &mut self,
+        unseal_key_lower: u16,
+        unseal_key_upper: u16,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::unseal(bq, unseal_key_lower, unseal_key_upper).await)
+    }
+
+    /// Send access keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn send_access_key(
+        &mut self,
+        access_key_lower: u16,
+        access_key_upper: u16,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::send_access_key(bq, access_key_lower, access_key_upper).await)
+    }
+
+    /// Read the current security mode from `OperationStatus`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn security_state(&mut self) -> Result<SecurityState, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::security_state(bq).await)
+    }
+
+    /// Seal the fuel gauge and verify it reports [`SecurityState::Sealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge did not
+    /// seal.
+    pub async fn seal(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::seal(bq).await)
+    }
+
+    /// Unseal the fuel gauge and verify it reports [`SecurityState::Unsealed`] afterwards.
+    ///
+    /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the
+    /// key.
+    pub async fn unseal(&mut self, key_lower: u16, key_upper: u16) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::unseal(bq, key_lower, key_upper).await)
+    }
+
+    /// Send the full access key to an unsealed gauge and verify it reports [`SecurityState::FullAccess`] afterwards.
+    ///
+    /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::SecurityStateMismatch`] if the gauge is sealed, or
+    /// [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the key.
+    pub async fn enter_full_access(&mut self, key_lower: u16, key_upper: u16) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::enter_full_access(bq, key_lower, key_upper).await)
+    }
+
+    /// Seal the gauge before its next bus transfer.
+    ///
+    /// For code that cannot await [`seal`](Self::seal), such as a `Drop` impl. The seal is not verified.
+    pub fn seal_before_next_transfer(&mut self) {
+        dispatch!(self, bq => Bq40z50::seal_before_next_transfer(bq));
+    }
+
+    /// Read MAC Register 0x0035 Security Keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_security_keys(
+        &mut self,
+        output_buf: &mut [u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_security_keys(bq, output_buf).await)
+    }
+
+    /// Write MAC Register 0x0035 Security Keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_security_keys(
+        &mut self,
+        security_keys: &[u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::write_security_keys(bq, security_keys).await)
+    }
+
+    /// Read MAC Register 0x0037 Authentication Key.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_authentication_key(
+        &mut self,
+        output_buf: &mut [u8; AUTH_KEY_DATA_LEN_BYTES as usize],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_authentication_key(bq, output_buf).await)
+    }
+
+    /// Write MAC Register 0x0037 Authentication Key.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_authentication_key(
+        &mut self,
+        auth_key: &[u8; AUTH_KEY_LEN_BYTES as usize],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::write_authentication_key(bq, auth_key).await)
+    }
+
+    /// Challenge the pack through `Authenticate()` and verify its SHA-1 digest against `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::AuthenticationFailed`] if the pack returned
+    /// the wrong digest.
+    pub async fn authenticate(
+        &mut self,
+        key: &AuthKey,
+        challenge: &AuthChallenge,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::authenticate(bq, key, challenge).await)
+    }
+
+    /// Write to the `MfgInfo` register. Requires the fuel gauge to be unsealed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_mfg_info(&mut self, data: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::write_mfg_info(bq, data).await)
+    }
+
+    /// Read from the `MfgInfo` register, including the leading size byte.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_mfg_info(&mut self, data: &mut [u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_mfg_info(bq, data).await)
+    }
+
+    /// Write to the `MfgInfoC` MAC register.
+    ///
+    /// The access keys are only sent on revisions which require them (R5) and are ignored otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::Unsupported`] on revisions without `MfgInfoC`.
+    #[cfg_attr(not(feature = "r5"), allow(unused_variables))]
+    pub async fn write_mfg_info_c(
+        &mut self,
+        access_key_lower: u16,
+        access_key_upper: u16,
+        data: &[u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        match self {
+            #[cfg(feature = "r1")]
+            Self::R1(bq) => Bq40z50::write_mfg_info_c(bq, (), data).await,
+            #[cfg(feature = "r3")]
+            Self::R3(bq) => Bq40z50::write_mfg_info_c(bq, (), data).await,
+            #[cfg(feature = "r4")]
+            Self::R4(bq) => Bq40z50::write_mfg_info_c(bq, (), data).await,
+            #[cfg(feature = "r5")]
+            Self::R5(bq) => Bq40z50::write_mfg_info_c(bq, (access_key_lower, access_key_upper), data).await,
+        }
+    }
+
+    /// Read from the `MfgInfoC` MAC register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::Unsupported`] on revisions without `MfgInfoC`.
+    pub async fn read_mfg_info_c(&mut self, data: &mut [u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_mfg_info_c(bq, data).await)
+    }
+
+    /// Write to the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::Unsupported`] on R1.
+    pub async fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::write_charging_voltage_override(bq, override_struct).await)
+    }
+
+    /// Read from the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::Unsupported`] on R1.
+    pub async fn read_charging_voltage_override(
+        &mut self,
+    ) -> Result<ChargingVoltageOverride, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_charging_voltage_override(bq).await)
+    }
+
+    /// Read from the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_dataflash(
+        &mut self,
+        starting_address: u16,
+        read: &mut [u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_dataflash(bq, starting_address, read).await)
+    }
+
+    /// Write to the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_dataflash(
+        &mut self,
+        starting_address: u16,
+        write: &[u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::write_dataflash(bq, starting_address, write).await)
+    }
+
+    /// Data flash parameters defined for the detected revision.
+    pub fn data_flash(&self) -> &'static [DfParam] {
+        match self {
+            #[cfg(feature = "r1")]
+            Self::R1(_) => crate::df_r1::PARAMS,
+            #[cfg(feature = "r3")]
+            Self::R3(_) => crate::df_r3::PARAMS,
+            #[cfg(feature = "r4")]
+            Self::R4(_) => crate::df_r4::PARAMS,
+            #[cfg(feature = "r5")]
+            Self::R5(_) => crate::df_r5::PARAMS,
+        }
+    }
+
+    /// Read a data flash parameter and decode it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the data flash holds a
+    /// string longer than the parameter allows.
+    pub async fn read_df_param(&mut self, param: &DfParam) -> Result<DfValue, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_df_param(bq, param).await)
+    }
+
+    /// Range-check and encode a data flash parameter, then write it. Requires the fuel gauge to be unsealed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::InvalidDfValue`] if `value` does not match
+    /// the parameter's type or is outside its range.
+    pub async fn write_df_param(&mut self, param: &DfParam, value: &DfValue) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::write_df_param(bq, param, value).await)
+    }
+
+    /// Play a `FlashStream` (`.bq.fs` / `.df.fs`) file against the device, calling `progress` after every command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with the line of the failing command if the stream does not parse, an I2C bus error occurs,
+    /// or a `C:` command read back different bytes.
+    pub async fn run_flash_stream(
+        &mut self,
+        contents: &str,
+        progress: impl FnMut(FlashStreamProgress),
+    ) -> Result<(), FlashStreamError<BQ40Z50Error<I2C::Error>>> {
+        dispatch!(self, bq => Bq40z50::run_flash_stream(bq, contents, progress).await)
+    }
+
+    /// Read the requested `fields` of the standard SBS telemetry in one call.
+    ///
+    /// Capacities are reported in the units of the current `CAPACITY_MODE`. `timestamp` is called before the first and
+    /// after the last read, so [`Snapshot::bus_time`](crate::Snapshot::bus_time) measures the time spent on the bus.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_snapshot(
+        &mut self,
+        fields: crate::SnapshotFields,
+        timestamp: impl FnMut() -> u64,
+    ) -> Result<crate::Snapshot, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_snapshot(bq, fields, timestamp).await)
+    }
+
+    /// Read the raw value of a 32-bit status register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_status(&mut self, register: crate::StatusRegister) -> Result<u32, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_status(bq, register).await)
+    }
+
+    /// Send `MAC_PF_DATA_RST` to clear the permanent failure flags and the device status captured at the time of
+    /// failure. Requires the fuel gauge to be in full access.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn reset_pf_data(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::reset_pf_data(bq).await)
+    }
+
+    /// Send `MAC_BLK_BOX_REC_RESET` to clear the black box recorder. Requires the fuel gauge to be in full access.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn reset_black_box(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::reset_black_box(bq).await)
+    }
+
+    /// Read every `MAC_LIFETIME_DATA_BLOCK_n` recorded by this revision and decode them into one report, for warranty
+    /// analysis. Requires the fuel gauge to be unsealed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_lifetime_data(&mut self) -> Result<crate::lifetime::LifetimeData, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_lifetime_data(bq).await)
+    }
+
+    /// Run a calibration `step` against its reference, averaging `samples` readings, and write the new coefficients to
+    /// the `Calibration` data flash. Requires the fuel gauge to be unsealed.
+    ///
+    /// Returns the coefficients before and after calibration.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::Timeout`](crate::BQ40Z50Error::Timeout) if the
+    /// CCADC output stops refreshing, [`BQ40Z50Error::UnexpectedResponse`](crate::BQ40Z50Error::UnexpectedResponse) if
+    /// the readings do not allow computing a coefficient, or
+    /// [`BQ40Z50Error::InvalidDfValue`](crate::BQ40Z50Error::InvalidDfValue) if a new coefficient is out of range.
+    pub async fn calibrate(
+        &mut self,
+        step: crate::calibration::CalibrationStep,
+        samples: u8,
+    ) -> Result<crate::calibration::CalibrationReport, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::calibrate(bq, step, samples).await)
+    }
+
+    /// Read `GaugingStatus()`, `GaugeStatus2()` and `GaugeStatus3()`, which track the Impedance Track learning cycle.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_learning_status(&mut self) -> Result<crate::learning::LearningStatus, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_learning_status(bq).await)
+    }
+
+    /// Read the chem ID and static chemistry data flash signature of the programmed chemistry.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_chemistry(&mut self) -> Result<crate::ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_chemistry(bq).await)
+    }
+
+    /// Write the chemistry tables of a chemistry image to data flash, then verify the gauge reports `chem_id` and the
+    /// static chemistry data flash `signature`. Requires the fuel gauge to be in full access.
+    ///
+    /// See [`ChemImage`](crate::ChemImage) for the format. The whole image is validated before anything is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, the gauge is not in full access, the image does not parse, or the
+    /// gauge reports a different chem ID or signature.
+    pub async fn program_chemistry(
+        &mut self,
+        contents: &str,
+        chem_id: u16,
+        signature: u16,
+    ) -> Result<crate::ChemistryInfo, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::program_chemistry(bq, contents, chem_id, signature).await)
+    }
+
+    /// Read the instruction flash, static data flash, static chemistry data flash and all data flash signatures,
+    /// waiting for the gauge to compute each one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_signatures(&mut self) -> Result<crate::Signatures, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::read_signatures(bq).await)
+    }
+
+    /// Read every flash signature and compare them against `expected`, e.g. recorded from a golden pack, to detect
+    /// which regions were tampered with or corrupted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn verify_signatures(
+        &mut self,
+        expected: crate::Signatures,
+    ) -> Result<crate::SignatureReport, BQ40Z50Error<I2C::Error>> {
+        dispatch!(self, bq => Bq40z50::verify_signatures(bq, expected).await)
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> smart_battery::ErrorType for AnyBq40z50<I2C, DELAY> {
+    type Error = BQ40Z50Error<I2C::Error>;
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> SmartBattery for AnyBq40z50<I2C, DELAY> {
+    async fn remaining_capacity_alarm(&mut self) -> Result<CapacityModeValue, Self::Error> {
+        dispatch!(self, bq => bq.remaining_capacity_alarm().await)
+    }
+
+    async fn set_remaining_capacity_alarm(&mut self, capacity: CapacityModeValue) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.set_remaining_capacity_alarm(capacity).await)
+    }
+
+    async fn remaining_time_alarm(&mut self) -> Result<smart_battery::Minutes, Self::Error> {
+        dispatch!(self, bq => bq.remaining_time_alarm().await)
+    }
+
+    async fn set_remaining_time_alarm(&mut self, time: smart_battery::Minutes) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.set_remaining_time_alarm(time).await)
+    }
+
+    async fn battery_mode(&mut self) -> Result<BatteryModeFields, Self::Error> {
+        dispatch!(self, bq => bq.battery_mode().await)
+    }
+
+    async fn set_battery_mode(&mut self, flags: BatteryModeFields) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.set_battery_mode(flags).await)
+    }
+
+    async fn at_rate(&mut self) -> Result<CapacityModeSignedValue, Self::Error> {
+        dispatch!(self, bq => bq.at_rate().await)
+    }
+
+    async fn set_at_rate(&mut self, rate: CapacityModeSignedValue) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.set_at_rate(rate).await)
+    }
+
+    async fn at_rate_time_to_full(&mut self) -> Result<smart_battery::Minutes, Self::Error> {
+        dispatch!(self, bq => bq.at_rate_time_to_full().await)
+    }
+
+    async fn at_rate_time_to_empty(&mut self) -> Result<smart_battery::Minutes, Self::Error> {
+        dispatch!(self, bq => bq.at_rate_time_to_empty().await)
+    }
+
+    async fn at_rate_ok(&mut self) -> Result<bool, Self::Error> {
+        dispatch!(self, bq => bq.at_rate_ok().await)
+    }
+
+    async fn temperature(&mut self) -> Result<DeciKelvin, Self::Error> {
+        dispatch!(self, bq => bq.temperature().await)
+    }
+
+    async fn voltage(&mut self) -> Result<smart_battery::MilliVolts, Self::Error> {
+        dispatch!(self, bq => bq.voltage().await)
+    }
+
+    async fn current(&mut self) -> Result<smart_battery::MilliAmpsSigned, Self::Error> {
+        dispatch!(self, bq => bq.current().await)
+    }
+
+    async fn average_current(&mut self) -> Result<smart_battery::MilliAmpsSigned, Self::Error> {
+        dispatch!(self, bq => bq.average_current().await)
+    }
+
+    async fn max_error(&mut self) -> Result<smart_battery::Percent, Self::Error> {
+        dispatch!(self, bq => bq.max_error().await)
+    }
+
+    async fn relative_state_of_charge(&mut self) -> Result<smart_battery::Percent, Self::Error> {
+        dispatch!(self, bq => bq.relative_state_of_charge().await)
+    }
+
+    async fn absolute_state_of_charge(&mut self) -> Result<smart_battery::Percent, Self::Error> {
+        dispatch!(self, bq => bq.absolute_state_of_charge().await)
+    }
+
+    async fn remaining_capacity(&mut self) -> Result<CapacityModeValue, Self::Error> {
+        dispatch!(self, bq => bq.remaining_capacity().await)
+    }
+
+    async fn full_charge_capacity(&mut self) -> Result<CapacityModeValue, Self::Error> {
+        dispatch!(self, bq => bq.full_charge_capacity().await)
+    }
+
+    async fn run_time_to_empty(&mut self) -> Result<smart_battery::Minutes, Self::Error> {
+        dispatch!(self, bq => bq.run_time_to_empty().await)
+    }
+
+    async fn average_time_to_empty(&mut self) -> Result<smart_battery::Minutes, Self::Error> {
+        dispatch!(self, bq => bq.average_time_to_empty().await)
+    }
+
+    async fn average_time_to_full(&mut self) -> Result<smart_battery::Minutes, Self::Error> {
+        dispatch!(self, bq => bq.average_time_to_full().await)
+    }
+
+    async fn battery_status(&mut self) -> Result<BatteryStatusFields, Self::Error> {
+        dispatch!(self, bq => bq.battery_status().await)
+    }
+
+    async fn cycle_count(&mut self) -> Result<smart_battery::Cycles, Self::Error> {
+        dispatch!(self, bq => bq.cycle_count().await)
+    }
+
+    async fn design_capacity(&mut self) -> Result<CapacityModeValue, Self::Error> {
+        dispatch!(self, bq => bq.design_capacity().await)
+    }
+
+    async fn design_voltage(&mut self) -> Result<smart_battery::MilliVolts, Self::Error> {
+        dispatch!(self, bq => bq.design_voltage().await)
+    }
+
+    async fn specification_info(&mut self) -> Result<SpecificationInfoFields, Self::Error> {
+        dispatch!(self, bq => bq.specification_info().await)
+    }
+
+    async fn manufacture_date(&mut self) -> Result<smart_battery::ManufactureDate, Self::Error> {
+        dispatch!(self, bq => bq.manufacture_date().await)
+    }
+
+    async fn serial_number(&mut self) -> Result<u16, Self::Error> {
+        dispatch!(self, bq => bq.serial_number().await)
+    }
+
+    async fn manufacturer_name(&mut self, name: &mut [u8]) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.manufacturer_name(name).await)
+    }
+
+    async fn device_name(&mut self, name: &mut [u8]) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.device_name(name).await)
+    }
+
+    async fn device_chemistry(&mut self, chemistry: &mut [u8]) -> Result<(), Self::Error> {
+        dispatch!(self, bq => bq.device_chemistry(chemistry).await)
+    }
+
+    async fn charging_current(&mut self) -> Result<embedded_batteries_async::charger::MilliAmps, Self::Error> {
+        dispatch!(self, bq => bq.charging_current().await)
+    }
+
+    async fn charging_voltage(&mut self) -> Result<smart_battery::MilliVolts, Self::Error> {
+        dispatch!(self, bq => bq.charging_voltage().await)
+    }
+}
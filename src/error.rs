@@ -1,19 +1,54 @@
+use crate::security::SecurityState;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 /// BQ40Z50 Errors
+#[non_exhaustive]
 pub enum BQ40Z50Error<I2cError> {
     I2c(I2cError),
     BatteryStatus(embedded_batteries_async::smart_battery::ErrorCode),
     Timeout,
     Pec,
     DataTooLarge,
-}
-
-#[cfg(feature = "embassy-timeout")]
-impl<I2cError> From<embassy_time::TimeoutError> for BQ40Z50Error<I2cError> {
-    fn from(_value: embassy_time::TimeoutError) -> Self {
-        BQ40Z50Error::Timeout
-    }
+    /// The operation is not available on this chip revision.
+    Unsupported,
+    /// The device did not identify as a supported BQ40Z50 revision.
+    UnknownRevision {
+        device_type: u16,
+        firmware_version: u16,
+    },
+    /// The authentication digest returned by the pack did not match the expected digest.
+    AuthenticationFailed,
+    /// The gauge did not accept a security key and remained in `state`.
+    KeyRejected {
+        state: SecurityState,
+    },
+    /// The gauge is in `actual` security mode where `expected` was required.
+    SecurityStateMismatch {
+        expected: SecurityState,
+        actual: SecurityState,
+    },
+    /// The gauge returned data that does not match the documented format.
+    UnexpectedResponse,
+    /// The value does not match the data flash parameter's type or is outside its range.
+    InvalidDfValue,
+    /// A `FlashStream` `C:` command read back bytes that differ from the expected ones.
+    CompareMismatch,
+    /// The chemistry image is malformed at the given 1-based line.
+    InvalidImage {
+        line: usize,
+    },
+    /// The gauge reports chem ID `actual` after programming a chemistry with chem ID `expected`.
+    ChemIdMismatch {
+        expected: u16,
+        actual: u16,
+    },
+    /// The static chemistry data flash signature does not match the programmed chemistry, or the gauge flags it as
+    /// mismatched.
+    ChemSignatureMismatch {
+        expected: u16,
+        actual: u16,
+    },
 }
 
 impl<E: embedded_hal_async::i2c::Error> embedded_batteries_async::smart_battery::Error for BQ40Z50Error<E> {
@@ -21,7 +56,20 @@ impl<E: embedded_hal_async::i2c::Error> embedded_batteries_async::smart_battery:
         match self {
             Self::I2c(_) => embedded_batteries_async::smart_battery::ErrorKind::CommError,
             Self::BatteryStatus(e) => embedded_batteries_async::smart_battery::ErrorKind::BatteryStatus(*e),
-            Self::Timeout | Self::Pec | Self::DataTooLarge => embedded_batteries_async::smart_battery::ErrorKind::Other,
+            Self::Timeout
+            | Self::Pec
+            | Self::DataTooLarge
+            | Self::Unsupported
+            | Self::UnknownRevision { .. }
+            | Self::AuthenticationFailed
+            | Self::KeyRejected { .. }
+            | Self::SecurityStateMismatch { .. }
+            | Self::UnexpectedResponse
+            | Self::InvalidDfValue
+            | Self::CompareMismatch
+            | Self::InvalidImage { .. }
+            | Self::ChemIdMismatch { .. }
+            | Self::ChemSignatureMismatch { .. } => embedded_batteries_async::smart_battery::ErrorKind::Other,
         }
     }
 }
@@ -0,0 +1,163 @@
+//! Bus-level fault injection for exercising the retry and PEC handling of the transport.
+//!
+//! [`FaultInjector`] wraps an I2C bus, such as a [`SimulatedGauge`](crate::sim::SimulatedGauge), and disturbs the
+//! transfers listed in its schedule. Transfers are counted from 0, one per I2C call (a `write_read` is one transfer),
+//! so a test lists exactly which attempt fails and how:
+//!
+//! ```
+//! # use bq40z50_rx::Revision;
+//! # use bq40z50_rx::fault::{Fault, FaultInjector};
+//! # use bq40z50_rx::sim::SimulatedGauge;
+//! # struct NoDelay;
+//! # impl embedded_hal_async::delay::DelayNs for NoDelay {
+//! #     async fn delay_ns(&mut self, _ns: u32) {}
+//! # }
+//! // The first transfer is not acknowledged, the PEC byte of the second one is flipped.
+//! let bus = FaultInjector::new(SimulatedGauge::new(Revision::R5), NoDelay)
+//!     .with_fault(0, Fault::Nack)
+//!     .with_fault(1, Fault::CorruptByte { index: 2, mask: 0x01 });
+//! ```
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::{
+    Error as _, ErrorKind, ErrorType, I2c as I2cTrait, NoAcknowledgeSource, Operation, SevenBitAddress,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Disturbance applied to a single transfer
+pub enum Fault {
+    /// The target does not acknowledge its address, the transfer never reaches it.
+    Nack,
+    /// The target acknowledges its address but not the data, the transfer never reaches it.
+    NackData,
+    /// Another controller wins arbitration, the transfer never reaches the target.
+    ArbitrationLoss,
+    /// XOR byte `index` of the data read during the transfer with `mask`.
+    CorruptByte { index: usize, mask: u8 },
+    /// The target stops driving the bus after `len` bytes, the remaining bytes read as `0xFF`.
+    Truncate { len: usize },
+    /// Stall the transfer for `ms` before passing it on.
+    Delay { ms: u32 },
+}
+
+/// I2C bus wrapper injecting [`Fault`]s into scheduled transfers.
+///
+/// Errors of the wrapped bus are passed on as their [`ErrorKind`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultInjector<I2C, DELAY> {
+    i2c: I2C,
+    delay: DELAY,
+    schedule: Vec<(usize, Fault)>,
+    transfers: usize,
+}
+
+impl<I2C, DELAY> FaultInjector<I2C, DELAY> {
+    /// Wrap `i2c`, using `delay` to stall transfers scheduled with [`Fault::Delay`].
+    #[must_use]
+    pub const fn new(i2c: I2C, delay: DELAY) -> Self {
+        Self {
+            i2c,
+            delay,
+            schedule: Vec::new(),
+            transfers: 0,
+        }
+    }
+
+    /// Schedule `fault` for the transfer numbered `transfer`.
+    #[must_use]
+    pub fn with_fault(mut self, transfer: usize, fault: Fault) -> Self {
+        self.inject(transfer, fault);
+        self
+    }
+
+    /// Schedule `fault` for the transfer numbered `transfer`. Several faults can target the same transfer.
+    pub fn inject(&mut self, transfer: usize, fault: Fault) {
+        self.schedule.push((transfer, fault));
+    }
+
+    /// Schedule `fault` for the next `count` transfers.
+    pub fn inject_next(&mut self, count: usize, fault: Fault) {
+        for transfer in self.transfers..self.transfers + count {
+            self.inject(transfer, fault);
+        }
+    }
+
+    /// Number of transfers seen so far, which is also the number of the next transfer.
+    #[must_use]
+    pub const fn transfers(&self) -> usize {
+        self.transfers
+    }
+
+    /// Number of scheduled faults that have not been applied yet.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.schedule.iter().filter(|(transfer, _)| *transfer >= self.transfers).count()
+    }
+
+    #[must_use]
+    pub const fn inner(&self) -> &I2C {
+        &self.i2c
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I2C {
+        &mut self.i2c
+    }
+
+    #[must_use]
+    pub fn into_inner(self) -> I2C {
+        self.i2c
+    }
+}
+
+impl<I2C: I2cTrait, DELAY> ErrorType for FaultInjector<I2C, DELAY> {
+    type Error = ErrorKind;
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> I2cTrait for FaultInjector<I2C, DELAY> {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let transfer = self.transfers;
+        self.transfers += 1;
+        let faults: Vec<Fault> = self
+            .schedule
+            .iter()
+            .filter(|(at, _)| *at == transfer)
+            .map(|(_, fault)| *fault)
+            .collect();
+
+        for fault in &faults {
+            match *fault {
+                Fault::Nack => return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+                Fault::NackData => return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)),
+                Fault::ArbitrationLoss => return Err(ErrorKind::ArbitrationLoss),
+                Fault::Delay { ms } => self.delay.delay_ms(ms).await,
+                Fault::CorruptByte { .. } | Fault::Truncate { .. } => (),
+            }
+        }
+
+        self.i2c
+            .transaction(address, operations)
+            .await
+            .map_err(|e| e.kind())?;
+
+        // Read bytes are numbered across every read operation of the transfer
+        let read_bytes = operations.iter_mut().flat_map(|operation| match operation {
+            Operation::Read(read) => read.iter_mut(),
+            Operation::Write(_) => [].iter_mut(),
+        });
+        for (i, byte) in read_bytes.enumerate() {
+            for fault in &faults {
+                match *fault {
+                    Fault::CorruptByte { index, mask } if index == i => *byte ^= mask,
+                    Fault::Truncate { len } if i >= len => *byte = 0xFF,
+                    _ => (),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+//! Parser and player for TI `FlashStream` (`.bq.fs` / `.df.fs`) files.
+//!
+//! A `FlashStream` is a text file with one command per line, all values in hex except the delay:
+//!
+//! - `W: AA DD DD ..` writes the bytes `DD ..` to the 8-bit bus address `AA`
+//! - `R: AA RR NN` reads `NN` bytes from register `RR`
+//! - `C: AA RR DD ..` reads register `RR` and compares it against the bytes `DD ..`
+//! - `X: MS` waits `MS` milliseconds (decimal)
+//!
+//! Blank lines and lines starting with `;` are ignored.
+
+use core::convert::Infallible;
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::FLASH_STREAM_MAX_BYTES;
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Bytes of a single `FlashStream` command
+pub struct FlashStreamBytes {
+    len: usize,
+    bytes: [u8; FLASH_STREAM_MAX_BYTES],
+}
+
+impl FlashStreamBytes {
+    /// The bytes of the command.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// A single `FlashStream` command. Addresses are 7-bit, converted from the 8-bit addresses used in the file.
+pub enum FlashStreamCommand {
+    /// `W:` write `data` to the device at `address`
+    Write { address: u8, data: FlashStreamBytes },
+    /// `R:` read `len` bytes from `register`
+    Read { address: u8, register: u8, len: usize },
+    /// `C:` read `register` and compare against `expected`
+    Compare {
+        address: u8,
+        register: u8,
+        expected: FlashStreamBytes,
+    },
+    /// `X:` wait `ms` milliseconds
+    Delay { ms: u32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Error while parsing or playing a `FlashStream`, with the 1-based line of the offending command
+pub enum FlashStreamError<E> {
+    /// The command does not parse
+    Syntax { line: usize },
+    /// The command failed on the device
+    Command { line: usize, error: E },
+}
+
+impl<E> FlashStreamError<E> {
+    /// 1-based line of the offending command.
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Syntax { line } | Self::Command { line, .. } => *line,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Progress of a `FlashStream` being played
+pub struct FlashStreamProgress {
+    /// 1-based line of the command that just completed
+    pub line: usize,
+    /// Number of commands completed so far
+    pub commands_done: usize,
+    /// Number of commands in the stream
+    pub commands_total: usize,
+}
+
+/// Iterator over the commands of a `FlashStream`, yielding the 1-based line of each command.
+pub struct FlashStream<'a> {
+    lines: core::iter::Enumerate<core::str::Lines<'a>>,
+}
+
+impl<'a> FlashStream<'a> {
+    /// Parse the `FlashStream` held in `contents`. Commands are parsed lazily while iterating.
+    #[must_use]
+    pub fn new(contents: &'a str) -> Self {
+        Self {
+            lines: contents.lines().enumerate(),
+        }
+    }
+
+    /// Parse the whole stream and count its commands.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with the line of the first command that fails to parse.
+    pub fn validate(contents: &'a str) -> Result<usize, FlashStreamError<Infallible>> {
+        FlashStream::new(contents).try_fold(0, |count, command| command.map(|_| count + 1))
+    }
+}
+
+impl Iterator for FlashStream<'_> {
+    type Item = Result<(usize, FlashStreamCommand), FlashStreamError<Infallible>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (i, line) in self.lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') {
+                continue;
+            }
+
+            return Some(
+                parse_command(line)
+                    .map(|command| (i + 1, command))
+                    .ok_or(FlashStreamError::Syntax { line: i + 1 }),
+            );
+        }
+
+        None
+    }
+}
+
+fn parse_command(line: &str) -> Option<FlashStreamCommand> {
+    let (op, args) = line.split_once(':')?;
+
+    if op.trim().eq_ignore_ascii_case("X") {
+        let ms = args.trim().parse().ok()?;
+        return Some(FlashStreamCommand::Delay { ms });
+    }
+
+    let mut bytes = FlashStreamBytes {
+        len: 0,
+        bytes: [0u8; FLASH_STREAM_MAX_BYTES],
+    };
+    for token in args.split_whitespace() {
+        *bytes.bytes.get_mut(bytes.len)? = u8::from_str_radix(token, 16).ok()?;
+        bytes.len += 1;
+    }
+
+    // [ 8-bit address | ... ]
+    let &[address, ..] = bytes.as_bytes() else {
+        return None;
+    };
+    let address = address >> 1;
+    let mut data = bytes;
+    data.len -= 1;
+    data.bytes.copy_within(1.., 0);
+
+    match (op.trim(), data.as_bytes()) {
+        ("W" | "w", [_, ..]) => Some(FlashStreamCommand::Write { address, data }),
+        ("R" | "r", &[register, len]) => Some(FlashStreamCommand::Read {
+            address,
+            register,
+            len: len.into(),
+        }),
+        ("C" | "c", [register, _, ..]) => {
+            let register = *register;
+            let mut expected = data;
+            expected.len -= 1;
+            expected.bytes.copy_within(1.., 0);
+            Some(FlashStreamCommand::Compare {
+                address,
+                register,
+                expected,
+            })
+        }
+        _ => None,
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Play a `FlashStream` against the device, calling `progress` after every command.
+    ///
+    /// The whole stream is parsed before the first command is sent, so a malformed file never leaves the gauge
+    /// partially programmed. Commands are sent as raw bytes to the address given in the stream, e.g. to reach the
+    /// gauge in ROM mode: the address and PEC settings from [`Config`](crate::Config) do not apply, bus retries do.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`FlashStreamError::Syntax`] if the stream does not parse, or [`FlashStreamError::Command`] if an
+    /// I2C bus error occurs or a `C:` command read back different bytes ([`BQ40Z50Error::CompareMismatch`]).
+    pub async fn run_flash_stream(
+        &mut self,
+        contents: &str,
+        mut progress: impl FnMut(FlashStreamProgress),
+    ) -> Result<(), FlashStreamError<BQ40Z50Error<I2C::Error>>> {
+        let commands_total = FlashStream::validate(contents).map_err(|e| FlashStreamError::Syntax { line: e.line() })?;
+
+        for (commands_done, command) in FlashStream::new(contents).enumerate() {
+            let (line, command) = command.map_err(|e| FlashStreamError::Syntax { line: e.line() })?;
+            self.run_flash_stream_command(&command)
+                .await
+                .map_err(|error| FlashStreamError::Command { line, error })?;
+
+            progress(FlashStreamProgress {
+                line,
+                commands_done: commands_done + 1,
+                commands_total,
+            });
+        }
+
+        Ok(())
+    }
+
+    async fn run_flash_stream_command(&mut self, command: &FlashStreamCommand) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; FLASH_STREAM_MAX_BYTES];
+
+        match command {
+            FlashStreamCommand::Write { address, data } => {
+                let mut retries = 0;
+                while let Err(e) = self.bus_write_to(*address, data.as_bytes()).await {
+                    self.retry_after(&mut retries, e).await?;
+                }
+                Ok(())
+            }
+            FlashStreamCommand::Read { address, register, len } => {
+                let read = buf.get_mut(..*len).ok_or(BQ40Z50Error::DataTooLarge)?;
+                self.flash_stream_read(*address, *register, read).await
+            }
+            FlashStreamCommand::Compare {
+                address,
+                register,
+                expected,
+            } => {
+                let read = &mut buf[..expected.as_bytes().len()];
+                self.flash_stream_read(*address, *register, read).await?;
+                if read != expected.as_bytes() {
+                    return Err(BQ40Z50Error::CompareMismatch);
+                }
+                Ok(())
+            }
+            FlashStreamCommand::Delay { ms } => {
+                self.delay.delay_ms(*ms).await;
+                Ok(())
+            }
+        }
+    }
+
+    async fn flash_stream_read(
+        &mut self,
+        address: u8,
+        register: u8,
+        read: &mut [u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        let mut retries = 0;
+        while let Err(e) = self.bus_write_read_to(address, &[register], read).await {
+            self.retry_after(&mut retries, e).await?;
+        }
+        Ok(())
+    }
+}
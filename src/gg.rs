@@ -0,0 +1,321 @@
+//! Import and export of data flash configurations in the bqStudio `.gg.csv` format.
+//!
+//! A `.gg.csv` file starts with `*` comment lines, followed by one row per parameter:
+//!
+//! ```text
+//! * Texas Instruments Data Flash File
+//! * Field Order: Class name, Subclass name, Parameter name, Parameter Value, Display Units
+//! "Calibration","Voltage","Cell Gain","12101",""
+//! ```
+//!
+//! Rows are mapped onto the data flash parameters of a revision by class, subclass and name, so a file exported by
+//! [`export`] can be checked with [`diff`] and programmed back with [`program`]. The revision tables do not cover the
+//! full data flash map, so rows of a bqStudio export naming other parameters are set aside by [`GgFile::resolve`]
+//! rather than programmed, and [`export`] only writes the parameters in the table.
+
+use core::fmt;
+
+use crate::dataflash::{DfParam, DfType, DfValue};
+use crate::traits::Bq40z50;
+
+const FILE_TITLE: &str = "Texas Instruments Data Flash File";
+const FIELD_ORDER: &str = "Field Order: Class name, Subclass name, Parameter name, Parameter Value, Display Units";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error while reading a `.gg.csv` file, with the 1-based line it occurred on
+pub enum GgError {
+    /// The row is not a valid CSV row of five fields.
+    Syntax { line: usize },
+    /// The value does not match the parameter's type or is outside its range.
+    InvalidValue { line: usize },
+}
+
+impl fmt::Display for GgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Syntax { line } => write!(f, "line {line}: expected five comma separated fields"),
+            Self::InvalidValue { line } => write!(f, "line {line}: value does not fit the data flash parameter"),
+        }
+    }
+}
+
+impl std::error::Error for GgError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// One parameter row of a `.gg.csv` file
+pub struct GgEntry {
+    pub class: String,
+    pub subclass: String,
+    pub name: String,
+    pub value: String,
+    pub units: String,
+    /// 1-based line of the row, or 0 for rows that were not parsed from a file
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// Contents of a `.gg.csv` file
+pub struct GgFile {
+    /// Comment lines of the header, without the leading `*`
+    pub header: Vec<String>,
+    pub entries: Vec<GgEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A row of a `.gg.csv` file resolved against a revision's data flash parameters
+pub struct GgSetting {
+    pub param: &'static DfParam,
+    pub value: DfValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Rows of a `.gg.csv` file resolved against a revision's data flash parameters
+pub struct GgResolution {
+    /// Rows naming a parameter of the revision's table, with their decoded values
+    pub settings: Vec<GgSetting>,
+    /// Rows naming a parameter missing from the revision's table, which are not programmed
+    pub unknown: Vec<GgEntry>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A parameter whose value on the gauge differs from the value in the file
+pub struct GgChange {
+    pub param: &'static DfParam,
+    pub current: DfValue,
+    pub new: DfValue,
+}
+
+impl fmt::Display for GgChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {} -> {}",
+            self.param.class,
+            self.param.subclass,
+            self.param.name,
+            format_value(self.param, &self.current),
+            format_value(self.param, &self.new)
+        )
+    }
+}
+
+impl GgFile {
+    /// Parse the contents of a `.gg.csv` file.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`GgError::Syntax`] if a row does not hold exactly five fields.
+    pub fn parse(contents: &str) -> Result<Self, GgError> {
+        let mut file = GgFile::default();
+
+        for (i, line) in contents.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(comment) = line.strip_prefix('*') {
+                file.header.push(comment.trim().into());
+                continue;
+            }
+
+            let Some([class, subclass, name, value, units]) = split_row(line) else {
+                return Err(GgError::Syntax { line: line_number });
+            };
+            file.entries.push(GgEntry {
+                class,
+                subclass,
+                name,
+                value,
+                units,
+                line: line_number,
+            });
+        }
+
+        Ok(file)
+    }
+
+    /// Map every row onto a parameter of `params` and decode its value.
+    ///
+    /// Rows naming a parameter missing from `params` are returned in [`GgResolution::unknown`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`GgError::InvalidValue`] if the value of a known parameter does not fit it.
+    pub fn resolve(&self, params: &'static [DfParam]) -> Result<GgResolution, GgError> {
+        let mut resolution = GgResolution {
+            settings: Vec::new(),
+            unknown: Vec::new(),
+        };
+
+        for entry in &self.entries {
+            let line = entry.line;
+            let Some(param) = DfParam::lookup(params, &entry.class, &entry.subclass, &entry.name) else {
+                resolution.unknown.push(entry.clone());
+                continue;
+            };
+            let value = parse_value(param, entry.value.trim()).ok_or(GgError::InvalidValue { line })?;
+
+            // Reject values the gauge would not accept before anything is written.
+            let mut buf = [0u8; 256];
+            param.encode(&value, &mut buf).ok_or(GgError::InvalidValue { line })?;
+
+            resolution.settings.push(GgSetting { param, value });
+        }
+
+        Ok(resolution)
+    }
+}
+
+impl fmt::Display for GgFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for comment in &self.header {
+            writeln!(f, "* {comment}")?;
+        }
+        for entry in &self.entries {
+            writeln!(
+                f,
+                "{},{},{},{},{}",
+                quote(&entry.class),
+                quote(&entry.subclass),
+                quote(&entry.name),
+                quote(&entry.value),
+                quote(&entry.units)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Read every data flash parameter defined for the gauge's revision into a `.gg.csv` file.
+///
+/// Only the parameters of the revision's table ([`Bq40z50::DATA_FLASH`]) are exported, not the full data flash map.
+///
+/// # Errors
+///
+/// Will return `Err` if an I2C bus error occurs.
+pub async fn export<T: Bq40z50>(bq: &mut T) -> Result<GgFile, T::Error> {
+    let mut file = GgFile {
+        header: vec![
+            FILE_TITLE.into(),
+            format!("Device: bq40z50-{:?}", T::REVISION),
+            FIELD_ORDER.into(),
+        ],
+        entries: Vec::with_capacity(T::DATA_FLASH.len()),
+    };
+
+    for param in T::DATA_FLASH {
+        let value = bq.read_df_param(param).await?;
+        file.entries.push(GgEntry {
+            class: param.class.into(),
+            subclass: param.subclass.into(),
+            name: param.name.into(),
+            value: format_value(param, &value),
+            units: param.units.into(),
+            line: 0,
+        });
+    }
+
+    Ok(file)
+}
+
+/// Read the current value of every setting from the gauge and list the ones [`program`] would change.
+///
+/// # Errors
+///
+/// Will return `Err` if an I2C bus error occurs.
+pub async fn diff<T: Bq40z50>(bq: &mut T, settings: &[GgSetting]) -> Result<Vec<GgChange>, T::Error> {
+    let mut changes = Vec::new();
+
+    for setting in settings {
+        let current = bq.read_df_param(setting.param).await?;
+        if current != setting.value {
+            changes.push(GgChange {
+                param: setting.param,
+                current,
+                new: setting.value,
+            });
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Write the new value of every change to the data flash. Requires the fuel gauge to be unsealed.
+///
+/// # Errors
+///
+/// Will return `Err` if an I2C bus error occurs. Changes before the failing one have already been written.
+pub async fn program<T: Bq40z50>(bq: &mut T, changes: &[GgChange]) -> Result<(), T::Error> {
+    for change in changes {
+        bq.write_df_param(change.param, &change.new).await?;
+    }
+
+    Ok(())
+}
+
+fn format_value(param: &DfParam, value: &DfValue) -> String {
+    match (param.ty, value) {
+        (DfType::H1, DfValue::Int(i)) => format!("0x{i:02X}"),
+        (DfType::H2, DfValue::Int(i)) => format!("0x{i:04X}"),
+        (DfType::H4, DfValue::Int(i)) => format!("0x{i:08X}"),
+        (_, DfValue::Int(i)) => format!("{i}"),
+        (_, DfValue::Float(f)) => format!("{f}"),
+        (_, DfValue::Str(s)) => String::from_utf8_lossy(s.as_bytes()).into_owned(),
+    }
+}
+
+fn parse_value(param: &DfParam, value: &str) -> Option<DfValue> {
+    match param.ty {
+        DfType::F4 => value.parse().ok().map(DfValue::Float),
+        DfType::S(_) => value.try_into().ok().map(DfValue::Str),
+        _ => {
+            let int = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+                Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+                None => value.parse().ok()?,
+            };
+            Some(DfValue::Int(int))
+        }
+    }
+}
+
+fn quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+/// Split a CSV row into its five fields, unquoting them.
+fn split_row(line: &str) -> Option<[String; 5]> {
+    let mut fields = Vec::with_capacity(5);
+    let mut chars = line.chars().peekable();
+
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => break,
+                    c => field.push(c),
+                }
+            }
+        }
+        while let Some(&c) = chars.peek() {
+            if c == ',' {
+                break;
+            }
+            field.push(c);
+            chars.next();
+        }
+        fields.push(field);
+
+        if chars.next().is_none() {
+            break;
+        }
+    }
+
+    fields.try_into().ok()
+}
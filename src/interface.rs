@@ -1,14 +1,15 @@
 use core::hash::Hasher;
 
-#[cfg(feature = "embassy-timeout")]
-use embassy_time::with_timeout;
+use embedded_hal::delay::DelayNs as BlockingDelayTrait;
+use embedded_hal::i2c::I2c as BlockingI2cTrait;
 use embedded_hal_async::delay::DelayNs as DelayTrait;
 use embedded_hal_async::i2c::I2c as I2cTrait;
 
+use crate::blocking::{BlockingDeviceInterface, block_on};
 use crate::common::Config;
 use crate::consts::{
-    BQ_ADDR, DEFAULT_ERROR_BACKOFF_DELAY_MS, LARGEST_BUF_SIZE_BYTES, LARGEST_CMD_SIZE_BYTES,
-    LARGEST_DF_BLOCK_SIZE_BYTES, LARGEST_REG_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BITS, MAC_CMD_ADDR_SIZE_BYTES,
+    LARGEST_BUF_SIZE_BYTES, LARGEST_CMD_SIZE_BYTES, LARGEST_DF_BLOCK_SIZE_BYTES, LARGEST_REG_SIZE_BYTES, MAC_CMD,
+    MAC_CMD_ADDR_SIZE_BITS, MAC_CMD_ADDR_SIZE_BYTES,
 };
 use crate::error::BQ40Z50Error;
 
@@ -18,6 +19,10 @@ pub struct DeviceInterface<I2C: I2cTrait, DELAY: DelayTrait> {
     pub i2c: I2C,
     pub delay: DELAY,
     pub config: Config,
+    /// Set after the gauge rejected a security key, until the lockout has been waited out.
+    pub(crate) key_lockout: bool,
+    /// Set when the gauge must be sealed before the next bus transfer.
+    pub(crate) seal_pending: bool,
 }
 
 impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
@@ -27,12 +32,20 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
             i2c,
             delay,
             config: Config::new(),
+            key_lockout: false,
+            seal_pending: false,
         }
     }
 
     #[must_use]
     pub const fn new_with_config(i2c: I2C, delay: DELAY, config: Config) -> Self {
-        DeviceInterface { i2c, delay, config }
+        DeviceInterface {
+            i2c,
+            delay,
+            config,
+            key_lockout: false,
+            seal_pending: false,
+        }
     }
 }
 
@@ -74,7 +87,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
             if use_pec {
                 // Add PEC at the end.
                 let mut pec = smbus_pec::Pec::new();
-                pec.write(&[BQ_ADDR << 1]);
+                pec.write(&[self.config.address << 1]);
                 pec.write(&output_buf[..output_buf_end_idx]);
                 // Safe cast as SMBUS PEC is a u8, returned value is u64 because of the Hasher trait.
                 output_buf[output_buf_end_idx] = pec.finish() as u8;
@@ -92,21 +105,15 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
     }
 }
 
-#[cfg(not(feature = "embassy-timeout"))]
 impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
     async fn write_with_retries_internal(&mut self, write: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
+        let mut retries = 0;
 
         // Because the BQ40Z50's registers vary in size, we pass in a slice of
         // the appropriate size so we do not accidentally write to the register
         // at address + 1 when writing to a 1 byte register
-        while let Err(e) = self.i2c.write(BQ_ADDR, write).await {
-            if retries == 0 {
-                return Err(BQ40Z50Error::I2c(e));
-            }
-            retries -= 1;
-            // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-            self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
+        while let Err(e) = self.bus_write(write).await {
+            self.retry_after(&mut retries, e).await?;
         }
 
         Ok(())
@@ -122,7 +129,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         if use_pec {
             let mut pec = smbus_pec::Pec::default();
             // Device Addr + Write Bit (0)
-            pec.write_u8(BQ_ADDR << 1);
+            pec.write_u8(self.config.address << 1);
             pec.write(write);
 
             // Write one more byte (PEC)
@@ -143,20 +150,13 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         mut read: &mut [u8],
         use_pec: bool,
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
+        let mut retries = 0;
         // Read buffer with one extra space at the end, in case we use PEC, and one extra space in the front for `mfg_info`
         let mut read_buf = [0u8; 1 + LARGEST_REG_SIZE_BYTES + 1];
-        let mut pec = smbus_pec::Pec::default();
 
         let read_len = read.len();
 
         let read_buf_ref = if use_pec {
-            // Device Addr + Write Bit (0)
-            pec.write_u8(BQ_ADDR << 1);
-            pec.write(write);
-            // Device Addr + Read Bit (1)
-            pec.write_u8(BQ_ADDR << 1 | 0x01);
-
             // Read one more byte (PEC)
             &mut read_buf[..=read_len]
         } else {
@@ -164,30 +164,27 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         };
 
         loop {
-            let res = self.i2c.write_read(BQ_ADDR, write, read_buf_ref).await;
+            let res = self.bus_write_read(write, read_buf_ref).await;
 
             if let Err(e) = res {
-                if retries == 0 {
-                    return Err(BQ40Z50Error::I2c(e));
-                }
-                retries -= 1;
-                // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
+                self.retry_after(&mut retries, e).await?;
                 continue;
             }
 
             if use_pec {
+                // Start from a fresh PEC every attempt, so a failed attempt does not spoil the next one
+                let mut pec = smbus_pec::Pec::default();
+                // Device Addr + Write Bit (0)
+                pec.write_u8(self.config.address << 1);
+                pec.write(write);
+                // Device Addr + Read Bit (1)
+                pec.write_u8(self.config.address << 1 | 0x01);
                 let recvd_pec = read_buf_ref[read_len];
                 pec.write(&read_buf_ref[..read_len]);
 
                 // Check PEC
                 if recvd_pec != pec.finish().try_into().unwrap() {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::Pec);
-                    }
-                    retries -= 1;
-                    // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
+                    self.retry_after(&mut retries, BQ40Z50Error::Pec).await?;
                     continue;
                 }
                 // If all is good, copy bytes we read into read.
@@ -198,382 +195,56 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         }
     }
 
-    #[allow(clippy::range_plus_one)]
-    pub(crate) async fn mac_read_with_retries(
+    /// `SMBus` block read of `command` into `read`, returning the payload length reported by the gauge.
+    ///
+    /// Bytes of `read` past the payload are zeroed.
+    pub(crate) async fn block_read_with_retries(
         &mut self,
-        write: &[u8],
+        command: u8,
         read: &mut [u8],
         use_pec: bool,
-    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
-        // Read buffer with one extra space at the end, in case we use PEC
-        // Response looks like [ Length (1 byte) | Command (2 bytes) | Data (output.len() bytes)]
-        let mut read_buf = [0u8; 1 + MAC_CMD_ADDR_SIZE_BYTES as usize + LARGEST_CMD_SIZE_BYTES + 1];
-        // Write buffer with one extra space at the end, in case we use PEC
-        // [ MAC_CMD (0x44) | CMD_SIZE | CMD_LSB | CMD_MSB | PEC ]
-        let mut write_buf = [0u8; 1 + 2 + MAC_CMD_ADDR_SIZE_BYTES as usize];
-
-        let write_buf_ref: &[u8];
-        let read_buf_ref: &mut [u8];
-
-        if use_pec {
-            let mut pec = smbus_pec::Pec::default();
-            pec.write_u8(BQ_ADDR << 1);
-            pec.write(write);
-
-            // Compute PEC for the Write Block
-            write_buf[..write.len()].copy_from_slice(write);
-            // Infalliable because the underlying crate is guaranteed to return a u8
-            write_buf[write.len()] = pec.finish().try_into().unwrap();
-            // Include everything we want to write plus the PEC byte
-            write_buf_ref = &write_buf[..=write.len()];
-            read_buf_ref = &mut read_buf[..1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len() + 1];
-        } else {
-            write_buf_ref = write;
-            read_buf_ref = &mut read_buf[..1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len()];
-        }
+    ) -> Result<usize, BQ40Z50Error<I2C::Error>> {
+        let mut retries = 0;
+        // Response looks like [ Length (1 byte) | Data (Length bytes) | PEC ], but the length is only known once read,
+        // so read as much as `read` can hold, with one extra space at the end in case we use PEC.
+        let mut read_buf = [0u8; 1 + LARGEST_REG_SIZE_BYTES + 1];
+        let max_len = core::cmp::min(read.len(), LARGEST_REG_SIZE_BYTES);
+        let read_buf_ref = &mut read_buf[..1 + max_len + usize::from(use_pec)];
 
-        // Loop until no bus errors or max bus retries are hit.
         loop {
-            // Block write intended register.
-            let res = self.i2c.write(BQ_ADDR, write_buf_ref).await;
+            let res = self.bus_write_read(&[command], read_buf_ref).await;
 
             if let Err(e) = res {
-                if retries == 0 {
-                    return Err(BQ40Z50Error::I2c(e));
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
+                self.retry_after(&mut retries, e).await?;
                 continue;
             }
 
-            // For read only commands.
-            // Block read using I2C write_read, sending 0x44 as the command.
-            let res = self.i2c.write_read(BQ_ADDR, &[write[0]], read_buf_ref).await;
-
-            if let Err(e) = res {
-                if retries == 0 {
-                    return Err(BQ40Z50Error::I2c(e));
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
-                continue;
+            let len = usize::from(read_buf_ref[0]);
+            if len > max_len {
+                return Err(BQ40Z50Error::DataTooLarge);
             }
 
             if use_pec {
                 let mut pec = smbus_pec::Pec::default();
-                pec.write_u8(BQ_ADDR << 1);
-                pec.write_u8(MAC_CMD);
-                pec.write_u8(BQ_ADDR << 1 | 0x01);
-
-                let recvd_pec = read_buf_ref[1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len()];
-                pec.write(&read_buf_ref[..1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len()]);
+                // Device Addr + Write Bit (0)
+                pec.write_u8(self.config.address << 1);
+                pec.write_u8(command);
+                // Device Addr + Read Bit (1)
+                pec.write_u8(self.config.address << 1 | 0x01);
+                // The gauge sends its PEC right after the payload, anything read past it is not part of the transfer
+                pec.write(&read_buf_ref[..=len]);
 
                 // Check PEC
-                if recvd_pec != pec.finish().try_into().unwrap() {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::Pec);
-                    }
-                    retries -= 1;
-                    // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    continue;
-                }
-            }
-
-            read.copy_from_slice(
-                &read_buf_ref
-                    [(1 + MAC_CMD_ADDR_SIZE_BYTES as usize)..(1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len())],
-            );
-
-            return Ok(());
-        }
-    }
-
-    pub(crate) async fn mac_read_from_df_with_retries(
-        &mut self,
-        starting_address: u16,
-        read: &mut [u8],
-    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
-        let starting_address = starting_address.to_le_bytes();
-
-        // Loop until no bus errors or max bus retries are hit.
-        loop {
-            // Block write intended register.
-            let res = self
-                .i2c
-                .write(
-                    BQ_ADDR,
-                    &[
-                        MAC_CMD,
-                        MAC_CMD_ADDR_SIZE_BYTES,
-                        starting_address[0],
-                        starting_address[1],
-                    ],
-                )
-                .await;
-
-            if let Err(e) = res {
-                if retries == 0 {
-                    return Err(BQ40Z50Error::I2c(e));
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
-                continue;
-            }
-
-            // Read in 32 byte chunks. The FG supports an auto-increment on the address during a DF read.
-            // If an SMBus read block is sent, the gauge will return 32 bytes of DF data,
-            // and if a subsequent SMBus read block is sent with command 0x44,
-            // the gauge returns another 32 bytes of DF data starting at the starting address + 32.
-            let mut bytes_left_to_read = read.len();
-            while bytes_left_to_read > 0 {
-                // Largest single read block is 1 byte size + 2 bytes starting address + 32 bytes data.
-                let mut output_buf = [0u8; 1 + LARGEST_DF_BLOCK_SIZE_BYTES + MAC_CMD_ADDR_SIZE_BYTES as usize];
-                // Determine how many bytes to read from the bus, ideally we want to minimize time reading from DF
-                // so if we can read less than 32 bytes of DF data, do it.
-                let output_buf_end_idx = core::cmp::min(
-                    output_buf.len(),
-                    bytes_left_to_read + MAC_CMD_ADDR_SIZE_BYTES as usize + 1,
-                );
-
-                let res = self
-                    .i2c
-                    .write_read(BQ_ADDR, &[MAC_CMD], &mut output_buf[..output_buf_end_idx])
-                    .await;
-
-                if let Err(e) = res {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::I2c(e));
-                    }
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    retries -= 1;
-                    continue;
-                }
-
-                let start_idx = read.len() - bytes_left_to_read;
-                let end_idx = start_idx + output_buf_end_idx - MAC_CMD_ADDR_SIZE_BYTES as usize - 1;
-                read[start_idx..end_idx]
-                    .copy_from_slice(&output_buf[(MAC_CMD_ADDR_SIZE_BYTES as usize + 1)..output_buf_end_idx]);
-                bytes_left_to_read = bytes_left_to_read.saturating_sub(LARGEST_DF_BLOCK_SIZE_BYTES);
-            }
-
-            return Ok(());
-        }
-    }
-
-    pub(crate) async fn mac_read_from_df_with_retries_pec(
-        &mut self,
-        starting_address: u16,
-        read: &mut [u8],
-    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
-        let starting_address = starting_address.to_le_bytes();
-
-        let pec = smbus_pec::pec(&[
-            BQ_ADDR << 1,
-            MAC_CMD,
-            MAC_CMD_ADDR_SIZE_BYTES,
-            starting_address[0],
-            starting_address[1],
-        ]);
-
-        // Loop until no bus errors or max bus retries are hit.
-        loop {
-            // Block write intended register.
-            let res = self
-                .i2c
-                .write(
-                    BQ_ADDR,
-                    &[
-                        MAC_CMD,
-                        MAC_CMD_ADDR_SIZE_BYTES,
-                        starting_address[0],
-                        starting_address[1],
-                        pec,
-                    ],
-                )
-                .await;
-
-            if let Err(e) = res {
-                if retries == 0 {
-                    return Err(BQ40Z50Error::I2c(e));
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
-                continue;
-            }
-
-            // Read in 32 byte chunks. The FG supports an auto-increment on the address during a DF read.
-            // If an SMBus read block is sent, the gauge will return 32 bytes of DF data,
-            // and if a subsequent SMBus read block is sent with command 0x44,
-            // the gauge returns another 32 bytes of DF data starting at the starting address + 32.
-            let mut bytes_left_to_read = read.len();
-            while bytes_left_to_read > 0 {
-                // Largest single read block is 1 byte size + 2 bytes starting address + 32 bytes data + 1 PEC byte.
-                let mut output_buf = [0u8; 1 + LARGEST_DF_BLOCK_SIZE_BYTES + MAC_CMD_ADDR_SIZE_BYTES as usize + 1];
-
-                // For PEC, we need to read in 32 byte chunks, even if we have <32 bytes left to read.
-                let output_buf_end_idx = output_buf.len();
-
-                let res = self
-                    .i2c
-                    .write_read(BQ_ADDR, &[MAC_CMD], &mut output_buf[..output_buf_end_idx])
-                    .await;
-
-                if let Err(e) = res {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::I2c(e));
-                    }
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    retries -= 1;
-                    continue;
-                }
-
-                let recvd_pec = output_buf[output_buf_end_idx - 1];
-                let mut pec = smbus_pec::Pec::new();
-                pec.write(&[BQ_ADDR << 1, MAC_CMD, BQ_ADDR << 1 | 0x01]);
-                // Omit PEC
-                pec.write(&output_buf[..output_buf_end_idx - 1]);
-                let pec = pec.finish();
-
-                if u64::from(recvd_pec) != pec {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::Pec);
-                    }
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    retries -= 1;
+                if u64::from(read_buf_ref[1 + len]) != pec.finish() {
+                    self.retry_after(&mut retries, BQ40Z50Error::Pec).await?;
                     continue;
                 }
-
-                let start_idx = read.len() - bytes_left_to_read;
-
-                let end_idx = start_idx + core::cmp::min(bytes_left_to_read, 32);
-                read[start_idx..end_idx].copy_from_slice(
-                    &output_buf[(MAC_CMD_ADDR_SIZE_BYTES as usize + 1)
-                        ..MAC_CMD_ADDR_SIZE_BYTES as usize + 1 + (end_idx - start_idx)],
-                );
-                bytes_left_to_read = bytes_left_to_read.saturating_sub(LARGEST_DF_BLOCK_SIZE_BYTES);
-            }
-
-            return Ok(());
-        }
-    }
-}
-
-#[cfg(feature = "embassy-timeout")]
-impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
-    async fn write_with_retries_internal(&mut self, write: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
-
-        // Because the BQ40Z50's registers vary in size, we pass in a slice of
-        // the appropriate size so we do not accidentally write to the register
-        // at address + 1 when writing to a 1 byte register
-        loop {
-            let res = match with_timeout(self.config.timeout, self.i2c.write(BQ_ADDR, write)).await {
-                Err(_) => Err(BQ40Z50Error::Timeout),
-                Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                Ok(Ok(())) => return Ok(()),
-            };
-
-            if retries == 0 {
-                // Return error
-                return res;
-            }
-            retries -= 1;
-            // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-            self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-        }
-    }
-
-    pub(crate) async fn write_with_retries(
-        &mut self,
-        write: &[u8],
-        use_pec: bool,
-    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut write_buf = [0u8; 1 + LARGEST_REG_SIZE_BYTES + 6];
-        let write_buf_ref: &[u8];
-        if use_pec {
-            let mut pec = smbus_pec::Pec::default();
-            // Device Addr + Write Bit (0)
-            pec.write_u8(BQ_ADDR << 1);
-            pec.write(write);
-
-            // Write one more byte (PEC)
-            write_buf[..write.len()].copy_from_slice(write);
-            write_buf[write.len()] = pec.finish().try_into().unwrap();
-
-            // Include everything we want to write plus the PEC byte
-            write_buf_ref = &write_buf[..=write.len()];
-        } else {
-            write_buf_ref = write;
-        }
-        self.write_with_retries_internal(write_buf_ref).await
-    }
-
-    pub(crate) async fn read_with_retries(
-        &mut self,
-        write: &[u8],
-        mut read: &mut [u8],
-        use_pec: bool,
-    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
-        // Read buffer with one extra space at the end, in case we use PEC, and one extra space in the front for `mfg_info`
-        let mut read_buf = [0u8; 1 + LARGEST_REG_SIZE_BYTES + 1];
-        let mut pec = smbus_pec::Pec::default();
-
-        let read_len = read.len();
-
-        let read_buf_ref = if use_pec {
-            // Device Addr + Write Bit (0)
-            pec.write_u8(BQ_ADDR << 1);
-            pec.write(write);
-            // Device Addr + Read Bit (1)
-            pec.write_u8(BQ_ADDR << 1 | 0x01);
-
-            // Read one more byte (PEC)
-            &mut read_buf[..=read_len]
-        } else {
-            &mut read
-        };
-
-        loop {
-            let res = match with_timeout(self.config.timeout, self.i2c.write_read(BQ_ADDR, write, read_buf_ref)).await {
-                Err(_) => Err(BQ40Z50Error::Timeout),
-                Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                Ok(Ok(())) => Ok(()),
-            };
-
-            if let Err(e) = res {
-                if retries == 0 {
-                    return Err(e);
-                }
-                retries -= 1;
-                // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                continue;
             }
 
-            if use_pec {
-                let recvd_pec = read_buf_ref[read_len];
-                pec.write(&read_buf_ref[..read_len]);
+            read[..len].copy_from_slice(&read_buf_ref[1..=len]);
+            read[len..].fill(0);
 
-                // Check PEC
-                if recvd_pec != pec.finish().try_into().unwrap() {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::Pec);
-                    }
-                    retries -= 1;
-                    // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    continue;
-                }
-                // If all is good, copy bytes we read into read.
-                read.copy_from_slice(&read_buf[..read_len]);
-            }
-
-            return Ok(());
+            return Ok(len);
         }
     }
 
@@ -584,7 +255,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         read: &mut [u8],
         use_pec: bool,
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
+        let mut retries = 0;
         // Read buffer with one extra space at the end, in case we use PEC
         // Response looks like [ Length (1 byte) | Command (2 bytes) | Data (output.len() bytes)]
         let mut read_buf = [0u8; 1 + MAC_CMD_ADDR_SIZE_BYTES as usize + LARGEST_CMD_SIZE_BYTES + 1];
@@ -597,7 +268,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
 
         if use_pec {
             let mut pec = smbus_pec::Pec::default();
-            pec.write_u8(BQ_ADDR << 1);
+            pec.write_u8(self.config.address << 1);
             pec.write(write);
 
             // Compute PEC for the Write Block
@@ -615,60 +286,34 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         // Loop until no bus errors or max bus retries are hit.
         loop {
             // Block write intended register.
-            let res = match with_timeout(self.config.timeout, self.i2c.write(BQ_ADDR, write_buf_ref)).await {
-                Err(_) => Err(BQ40Z50Error::Timeout),
-                Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                Ok(Ok(())) => Ok(()),
-            };
-
-            if res.is_err() {
-                if retries == 0 {
-                    return res;
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
+            let res = self.bus_write(write_buf_ref).await;
+
+            if let Err(e) = res {
+                self.retry_after(&mut retries, e).await?;
                 continue;
             }
 
             // For read only commands.
             // Block read using I2C write_read, sending 0x44 as the command.
-            let res = match with_timeout(
-                self.config.timeout,
-                self.i2c.write_read(BQ_ADDR, &[write[0]], read_buf_ref),
-            )
-            .await
-            {
-                Err(_) => Err(BQ40Z50Error::Timeout),
-                Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                Ok(Ok(())) => Ok(()),
-            };
-
-            if res.is_err() {
-                if retries == 0 {
-                    return res;
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
+            let res = self.bus_write_read(&[write[0]], read_buf_ref).await;
+
+            if let Err(e) = res {
+                self.retry_after(&mut retries, e).await?;
                 continue;
             }
 
             if use_pec {
                 let mut pec = smbus_pec::Pec::default();
-                pec.write_u8(BQ_ADDR << 1);
+                pec.write_u8(self.config.address << 1);
                 pec.write_u8(MAC_CMD);
-                pec.write_u8(BQ_ADDR << 1 | 0x01);
+                pec.write_u8(self.config.address << 1 | 0x01);
 
                 let recvd_pec = read_buf_ref[1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len()];
                 pec.write(&read_buf_ref[..1 + MAC_CMD_ADDR_SIZE_BYTES as usize + read.len()]);
 
                 // Check PEC
                 if recvd_pec != pec.finish().try_into().unwrap() {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::Pec);
-                    }
-                    retries -= 1;
-                    // Delay 10ms since the fuel gauge might be "thinking" from a previous command
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
+                    self.retry_after(&mut retries, BQ40Z50Error::Pec).await?;
                     continue;
                 }
             }
@@ -687,37 +332,23 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         starting_address: u16,
         read: &mut [u8],
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
+        let mut retries = 0;
         let starting_address = starting_address.to_le_bytes();
 
         // Loop until no bus errors or max bus retries are hit.
-        loop {
+        'retry: loop {
             // Block write intended register.
-            let res = match with_timeout(
-                self.config.timeout,
-                self.i2c.write(
-                    BQ_ADDR,
-                    &[
-                        MAC_CMD,
-                        MAC_CMD_ADDR_SIZE_BYTES,
-                        starting_address[0],
-                        starting_address[1],
-                    ],
-                ),
-            )
-            .await
-            {
-                Err(_) => Err(BQ40Z50Error::Timeout),
-                Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                Ok(Ok(())) => Ok(()),
-            };
-
-            if res.is_err() {
-                if retries == 0 {
-                    return res;
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
+            let res = self
+                .bus_write(&[
+                    MAC_CMD,
+                    MAC_CMD_ADDR_SIZE_BYTES,
+                    starting_address[0],
+                    starting_address[1],
+                ])
+                .await;
+
+            if let Err(e) = res {
+                self.retry_after(&mut retries, e).await?;
                 continue;
             }
 
@@ -736,25 +367,14 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
                     bytes_left_to_read + MAC_CMD_ADDR_SIZE_BYTES as usize + 1,
                 );
 
-                let res = match with_timeout(
-                    self.config.timeout,
-                    self.i2c
-                        .write_read(BQ_ADDR, &[MAC_CMD], &mut output_buf[..output_buf_end_idx]),
-                )
-                .await
-                {
-                    Err(_) => Err(BQ40Z50Error::Timeout),
-                    Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                    Ok(Ok(())) => Ok(()),
-                };
-
-                if res.is_err() {
-                    if retries == 0 {
-                        return res;
-                    }
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    retries -= 1;
-                    continue;
+                let res = self
+                    .bus_write_read(&[MAC_CMD], &mut output_buf[..output_buf_end_idx])
+                    .await;
+
+                if let Err(e) = res {
+                    self.retry_after(&mut retries, e).await?;
+                    // The gauge may already have moved on to the next block, start over from the starting address.
+                    continue 'retry;
                 }
 
                 let start_idx = read.len() - bytes_left_to_read;
@@ -773,11 +393,11 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         starting_address: u16,
         read: &mut [u8],
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        let mut retries = self.config.max_bus_retries;
+        let mut retries = 0;
         let starting_address = starting_address.to_le_bytes();
 
         let pec = smbus_pec::pec(&[
-            BQ_ADDR << 1,
+            self.config.address << 1,
             MAC_CMD,
             MAC_CMD_ADDR_SIZE_BYTES,
             starting_address[0],
@@ -785,34 +405,20 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
         ]);
 
         // Loop until no bus errors or max bus retries are hit.
-        loop {
+        'retry: loop {
             // Block write intended register.
-            let res = match with_timeout(
-                self.config.timeout,
-                self.i2c.write(
-                    BQ_ADDR,
-                    &[
-                        MAC_CMD,
-                        MAC_CMD_ADDR_SIZE_BYTES,
-                        starting_address[0],
-                        starting_address[1],
-                        pec,
-                    ],
-                ),
-            )
-            .await
-            {
-                Err(_) => Err(BQ40Z50Error::Timeout),
-                Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                Ok(Ok(())) => Ok(()),
-            };
-
-            if res.is_err() {
-                if retries == 0 {
-                    return res;
-                }
-                self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                retries -= 1;
+            let res = self
+                .bus_write(&[
+                    MAC_CMD,
+                    MAC_CMD_ADDR_SIZE_BYTES,
+                    starting_address[0],
+                    starting_address[1],
+                    pec,
+                ])
+                .await;
+
+            if let Err(e) = res {
+                self.retry_after(&mut retries, e).await?;
                 continue;
             }
 
@@ -828,44 +434,30 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
                 // For PEC, we need to read in 32 byte chunks, even if we have <32 bytes left to read.
                 let output_buf_end_idx = output_buf.len();
 
-                let res = match with_timeout(
-                    self.config.timeout,
-                    self.i2c
-                        .write_read(BQ_ADDR, &[MAC_CMD], &mut output_buf[..output_buf_end_idx]),
-                )
-                .await
-                {
-                    Err(_) => Err(BQ40Z50Error::Timeout),
-                    Ok(Err(bus_err)) => Err(BQ40Z50Error::I2c(bus_err)),
-                    Ok(Ok(())) => Ok(()),
-                };
-
-                if res.is_err() {
-                    if retries == 0 {
-                        return res;
-                    }
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    retries -= 1;
-                    continue;
+                let res = self
+                    .bus_write_read(&[MAC_CMD], &mut output_buf[..output_buf_end_idx])
+                    .await;
+
+                if let Err(e) = res {
+                    self.retry_after(&mut retries, e).await?;
+                    // The gauge may already have moved on to the next block, start over from the starting address.
+                    continue 'retry;
                 }
 
                 let recvd_pec = output_buf[output_buf_end_idx - 1];
                 let mut pec = smbus_pec::Pec::new();
-                pec.write(&[BQ_ADDR << 1, MAC_CMD, BQ_ADDR << 1 | 0x01]);
+                pec.write(&[self.config.address << 1, MAC_CMD, self.config.address << 1 | 0x01]);
                 // Omit PEC
                 pec.write(&output_buf[..output_buf_end_idx - 1]);
                 let pec = pec.finish();
 
                 if u64::from(recvd_pec) != pec {
-                    if retries == 0 {
-                        return Err(BQ40Z50Error::Pec);
-                    }
-                    self.delay.delay_ms(DEFAULT_ERROR_BACKOFF_DELAY_MS).await;
-                    retries -= 1;
-                    continue;
+                    self.retry_after(&mut retries, BQ40Z50Error::Pec).await?;
+                    continue 'retry;
                 }
 
                 let start_idx = read.len() - bytes_left_to_read;
+
                 let end_idx = start_idx + core::cmp::min(bytes_left_to_read, 32);
                 read[start_idx..end_idx].copy_from_slice(
                     &output_buf[(MAC_CMD_ADDR_SIZE_BYTES as usize + 1)
@@ -962,8 +554,8 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> device_driver::AsyncBufferInterface for D
     type AddressType = u8;
 
     async fn read(&mut self, address: Self::AddressType, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        // Don't use PEC for these types of registers, because we don't know the size of the data.
-        self.read_with_retries(&[address], buf, false).await.map(|()| buf.len())
+        // Buffers are SMBus blocks, led by their length
+        self.block_read_with_retries(address, buf, self.config.pec_read).await
     }
 
     async fn write(&mut self, address: Self::AddressType, buf: &[u8]) -> Result<usize, Self::Error> {
@@ -983,3 +575,70 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> device_driver::AsyncBufferInterface for D
         Ok(())
     }
 }
+
+impl<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> device_driver::RegisterInterface
+    for BlockingDeviceInterface<I2C, DELAY>
+{
+    type Error = BQ40Z50Error<I2C::Error>;
+    type AddressType = u8;
+
+    fn write_register(&mut self, address: Self::AddressType, size_bits: u32, data: &[u8]) -> Result<(), Self::Error> {
+        block_on(device_driver::AsyncRegisterInterface::write_register(
+            self, address, size_bits, data,
+        ))
+    }
+
+    fn read_register(
+        &mut self,
+        address: Self::AddressType,
+        size_bits: u32,
+        data: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        block_on(device_driver::AsyncRegisterInterface::read_register(
+            self, address, size_bits, data,
+        ))
+    }
+}
+
+impl<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> device_driver::CommandInterface
+    for BlockingDeviceInterface<I2C, DELAY>
+{
+    type Error = BQ40Z50Error<I2C::Error>;
+    type AddressType = u32;
+
+    fn dispatch_command(
+        &mut self,
+        address: Self::AddressType,
+        size_bits_in: u32,
+        input: &[u8],
+        size_bits_out: u32,
+        output: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        block_on(device_driver::AsyncCommandInterface::dispatch_command(
+            self,
+            address,
+            size_bits_in,
+            input,
+            size_bits_out,
+            output,
+        ))
+    }
+}
+
+impl<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> device_driver::BufferInterface
+    for BlockingDeviceInterface<I2C, DELAY>
+{
+    type AddressType = u8;
+
+    fn read(&mut self, address: Self::AddressType, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        block_on(device_driver::AsyncBufferInterface::read(self, address, buf))
+    }
+
+    fn write(&mut self, address: Self::AddressType, buf: &[u8]) -> Result<usize, Self::Error> {
+        block_on(device_driver::AsyncBufferInterface::write(self, address, buf))
+    }
+
+    fn flush(&mut self, address: Self::AddressType) -> Result<(), Self::Error> {
+        block_on(device_driver::AsyncBufferInterface::flush(self, address))
+    }
+}
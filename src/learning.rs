@@ -0,0 +1,445 @@
+//! Impedance Track learning cycle, tracking a new chemistry through charge, relax, discharge and relax until the
+//! gauge has learned its `QMax` and resistance tables.
+//!
+//! [`LearningCycle::poll`] reads `GaugingStatus()`, `GaugeStatus2()` and `GaugeStatus3()`, advances through the
+//! [`LearningPhase`]s and tells the test rig which [`LearningAction`] to apply to the pack. When a step does not
+//! produce the expected update, the cycle stops with a [`LearningFailure`] naming the cause.
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::common::Revision;
+use crate::consts::{
+    GAUGE_STATUS_2_CMD, GAUGE_STATUS_2_SIZE_BYTES, GAUGE_STATUS_3_CMD, GAUGE_STATUS_3_R5_SIZE_BYTES,
+    GAUGE_STATUS_3_SIZE_BYTES, GAUGING_STATUS_REG, GAUGING_STATUS_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES,
+    SBS_CELL_COUNT,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+use crate::traits::Bq40z50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// `GaugingStatus()` flags
+pub struct GaugingStatus(pub u32);
+
+impl GaugingStatus {
+    const fn bit(self, bit: u32) -> bool {
+        self.0 & (1 << bit) != 0
+    }
+
+    /// `FD`, fully discharged
+    #[must_use]
+    pub const fn fully_discharged(self) -> bool {
+        self.bit(0)
+    }
+
+    /// `FC`, fully charged
+    #[must_use]
+    pub const fn fully_charged(self) -> bool {
+        self.bit(1)
+    }
+
+    /// `DSG`, discharging or relaxing
+    #[must_use]
+    pub const fn discharging(self) -> bool {
+        self.bit(6)
+    }
+
+    /// `REST`, the open circuit voltage was taken after relaxing
+    #[must_use]
+    pub const fn rest(self) -> bool {
+        self.bit(8)
+    }
+
+    /// `R_DIS`, resistance updates are disabled
+    #[must_use]
+    pub const fn resistance_updates_disabled(self) -> bool {
+        self.bit(10)
+    }
+
+    /// `VOK`, the voltages are OK for a `QMax` update
+    #[must_use]
+    pub const fn voltage_ok(self) -> bool {
+        self.bit(11)
+    }
+
+    /// `QEN`, `QMax` updates are enabled
+    #[must_use]
+    pub const fn qmax_updates_enabled(self) -> bool {
+        self.bit(12)
+    }
+
+    /// `VDQ`, the discharge is qualified for learning
+    #[must_use]
+    pub const fn discharge_qualified(self) -> bool {
+        self.bit(16)
+    }
+
+    /// `QMax`, `QMax` was updated
+    #[must_use]
+    pub const fn qmax_updated(self) -> bool {
+        self.bit(17)
+    }
+
+    /// `RX`, the resistance table was updated
+    #[must_use]
+    pub const fn resistance_updated(self) -> bool {
+        self.bit(18)
+    }
+
+    /// `OCVFR`, the open circuit voltage was taken in the flat region of the chemistry, so the depth of discharge is
+    /// not valid for learning
+    #[must_use]
+    pub const fn ocv_in_flat_region(self) -> bool {
+        self.bit(20)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// `Update Status` of the learning cycle, as reported by `GaugeStatus2()`. Reads `0x04` when learning starts, `0x05`
+/// once `QMax` is learned, `0x06` once the resistance table is learned and `0x0E` at the end of a successful cycle.
+pub struct UpdateStatus(pub u8);
+
+impl UpdateStatus {
+    /// `QMax` was learned
+    #[must_use]
+    pub const fn qmax_learned(self) -> bool {
+        self.0 & 0b11 != 0
+    }
+
+    /// `QMax` and the resistance table were learned
+    #[must_use]
+    pub const fn resistance_learned(self) -> bool {
+        self.0 & 0b11 == 0b10
+    }
+
+    /// `ITEN`, Impedance Track gauging is enabled
+    #[must_use]
+    pub const fn it_enabled(self) -> bool {
+        self.0 & (1 << 2) != 0
+    }
+
+    /// `QMax` was updated in the field, after the learning cycle
+    #[must_use]
+    pub const fn qmax_field_updated(self) -> bool {
+        self.0 & (1 << 3) != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Learning status read by [`read_learning_status`](crate::Bq40z50::read_learning_status)
+pub struct LearningStatus {
+    pub gauging_status: GaugingStatus,
+    pub update_status: UpdateStatus,
+    /// Time since the last state change of the gauge, in seconds
+    pub state_time_s: u32,
+    /// Depth of discharge of cell 1 to cell 4 at the last open circuit voltage reading
+    pub dod0: [u16; SBS_CELL_COUNT],
+    /// Charge passed since the last `DOD0` update, in mAh
+    pub dod0_passed_charge: i16,
+    /// Time since the last `DOD0` update, in hours / 16
+    pub dod0_time: u16,
+    /// `QMax` of cell 1 to cell 4, in mAh
+    pub qmax: [u16; SBS_CELL_COUNT],
+    /// Charge passed since the last `QMax` update, in mAh
+    pub qmax_passed_charge: i16,
+    /// Time since the last `QMax` update, in hours / 16
+    pub qmax_time: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Step of a learning cycle
+pub enum LearningPhase {
+    /// Checking that Impedance Track gauging is enabled
+    Start,
+    /// Discharging to empty, until `FD` is set
+    DischargeToEmpty,
+    /// Relaxing after the discharge, until the open circuit voltage is taken
+    RelaxDischarged,
+    /// Charging to full, until `FC` is set
+    ChargeToFull,
+    /// Relaxing after the charge, until `QMax` is learned
+    RelaxCharged,
+    /// Discharging to empty, until `FD` is set, while the resistance table is learned
+    DischargeForResistance,
+    /// Relaxing after the discharge, until the second `QMax` update ends the cycle
+    RelaxFinal,
+    /// The cycle completed, the data flash can be exported as the golden image
+    Complete,
+    /// The cycle stopped
+    Failed(LearningFailure),
+}
+
+impl LearningPhase {
+    /// What the test rig should do to the pack during this phase.
+    #[must_use]
+    pub const fn action(self) -> LearningAction {
+        match self {
+            Self::DischargeToEmpty | Self::DischargeForResistance => LearningAction::Discharge,
+            Self::ChargeToFull => LearningAction::Charge,
+            Self::RelaxDischarged | Self::RelaxCharged | Self::RelaxFinal => LearningAction::Relax,
+            Self::Start | Self::Complete | Self::Failed(_) => LearningAction::None,
+        }
+    }
+
+    const fn is_relax(self) -> bool {
+        matches!(self, Self::RelaxDischarged | Self::RelaxCharged | Self::RelaxFinal)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// What the test rig should do to the pack
+pub enum LearningAction {
+    /// Nothing, the cycle has not started or is over
+    None,
+    /// Discharge at the learning rate, typically C/5
+    Discharge,
+    /// Charge with the standard charge profile
+    Charge,
+    /// Keep the pack at rest, with no current flowing
+    Relax,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Cause of a failed learning cycle
+pub enum LearningFailure {
+    /// `ITEN` is clear, gauging must be enabled with `MAC_GAUGING` before learning
+    GaugingNotEnabled,
+    /// `REST` was not set within [`LearningConfig::relax_timeout_s`]
+    RelaxTimeNotMet,
+    /// The charge or discharge did not complete within [`LearningConfig::step_timeout_s`]
+    StepTimeout,
+    /// The open circuit voltage was taken in the flat region of the chemistry, relax at a lower or higher state of
+    /// charge
+    DodNotValid,
+    /// The pack relaxed after charging but `QMax` was not updated, usually because too little charge passed
+    QmaxNotUpdated,
+    /// Resistance updates were disabled during the learning discharge
+    ResistanceUpdatesDisabled,
+    /// The pack relaxed after the learning discharge but the resistance table was not learned
+    ResistanceNotUpdated,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Time limits of a learning cycle, in seconds
+pub struct LearningConfig {
+    /// Longest relaxation before giving up on `REST`
+    pub relax_timeout_s: u32,
+    /// Longest charge or discharge
+    pub step_timeout_s: u32,
+}
+
+impl LearningConfig {
+    /// Allow 6 hours of relaxation, 5 of which the gauge may need after a discharge, and 12 hours per charge or
+    /// discharge.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            relax_timeout_s: 6 * 60 * 60,
+            step_timeout_s: 12 * 60 * 60,
+        }
+    }
+}
+
+impl Default for LearningConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Learning progress returned by [`LearningCycle::poll`]
+pub struct LearningProgress {
+    pub phase: LearningPhase,
+    pub action: LearningAction,
+    /// Time spent in the current phase, in seconds
+    pub phase_elapsed_s: u64,
+    pub status: LearningStatus,
+}
+
+/// Impedance Track learning cycle state machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LearningCycle {
+    config: LearningConfig,
+    phase: LearningPhase,
+    failed_in: Option<LearningPhase>,
+    phase_started_s: u64,
+    /// `DOD0` when the current phase started, to detect a new open circuit voltage reading
+    phase_dod0: [u16; SBS_CELL_COUNT],
+}
+
+impl LearningCycle {
+    /// Create a learning cycle in the [`LearningPhase::Start`] phase.
+    #[must_use]
+    pub const fn new(config: LearningConfig) -> Self {
+        Self {
+            config,
+            phase: LearningPhase::Start,
+            failed_in: None,
+            phase_started_s: 0,
+            phase_dod0: [0; SBS_CELL_COUNT],
+        }
+    }
+
+    /// Current phase.
+    #[must_use]
+    pub const fn phase(&self) -> LearningPhase {
+        self.phase
+    }
+
+    /// Phase the cycle failed in, `None` unless it [`Failed`](LearningPhase::Failed).
+    #[must_use]
+    pub const fn failed_in(&self) -> Option<LearningPhase> {
+        self.failed_in
+    }
+
+    /// Read the learning status at `now_s` and advance to the next phase once the current one is done.
+    ///
+    /// Call this periodically, e.g. every few seconds, and apply the returned [`LearningAction`] to the pack. Once
+    /// the cycle is [`Complete`](LearningPhase::Complete) or [`Failed`](LearningPhase::Failed) it no longer changes.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn poll<T: Bq40z50>(&mut self, bq: &mut T, now_s: u64) -> Result<LearningProgress, T::Error> {
+        let status = bq.read_learning_status().await?;
+        self.advance(&status, now_s);
+
+        Ok(LearningProgress {
+            phase: self.phase,
+            action: self.phase.action(),
+            phase_elapsed_s: now_s.saturating_sub(self.phase_started_s),
+            status,
+        })
+    }
+
+    fn enter(&mut self, phase: LearningPhase, status: &LearningStatus, now_s: u64) {
+        if let LearningPhase::Failed(_) = phase {
+            self.failed_in = Some(self.phase);
+        }
+        self.phase = phase;
+        self.phase_started_s = now_s;
+        self.phase_dod0 = status.dod0;
+    }
+
+    fn advance(&mut self, status: &LearningStatus, now_s: u64) {
+        let gauging = status.gauging_status;
+        let update = status.update_status;
+        let elapsed_s = now_s.saturating_sub(self.phase_started_s);
+        // A new open circuit voltage reading updates DOD0 once the pack has rested
+        let ocv_taken = gauging.rest() && status.dod0 != self.phase_dod0;
+
+        let next = match self.phase {
+            LearningPhase::Start if update.it_enabled() => LearningPhase::DischargeToEmpty,
+            LearningPhase::Start => LearningPhase::Failed(LearningFailure::GaugingNotEnabled),
+            LearningPhase::DischargeToEmpty if gauging.fully_discharged() => LearningPhase::RelaxDischarged,
+            LearningPhase::ChargeToFull if gauging.fully_charged() => LearningPhase::RelaxCharged,
+            LearningPhase::DischargeForResistance if gauging.resistance_updates_disabled() => {
+                LearningPhase::Failed(LearningFailure::ResistanceUpdatesDisabled)
+            }
+            LearningPhase::DischargeForResistance if gauging.fully_discharged() => LearningPhase::RelaxFinal,
+            phase if phase.is_relax() && ocv_taken && gauging.ocv_in_flat_region() => {
+                LearningPhase::Failed(LearningFailure::DodNotValid)
+            }
+            LearningPhase::RelaxDischarged if ocv_taken => LearningPhase::ChargeToFull,
+            LearningPhase::RelaxCharged if update.qmax_learned() => LearningPhase::DischargeForResistance,
+            LearningPhase::RelaxCharged if ocv_taken => LearningPhase::Failed(LearningFailure::QmaxNotUpdated),
+            LearningPhase::RelaxFinal if update.resistance_learned() && update.qmax_field_updated() => {
+                LearningPhase::Complete
+            }
+            LearningPhase::RelaxFinal if ocv_taken && !update.resistance_learned() => {
+                LearningPhase::Failed(LearningFailure::ResistanceNotUpdated)
+            }
+            phase if phase.is_relax() && elapsed_s > u64::from(self.config.relax_timeout_s) => {
+                LearningPhase::Failed(LearningFailure::RelaxTimeNotMet)
+            }
+            LearningPhase::DischargeToEmpty | LearningPhase::ChargeToFull | LearningPhase::DischargeForResistance
+                if elapsed_s > u64::from(self.config.step_timeout_s) =>
+            {
+                LearningPhase::Failed(LearningFailure::StepTimeout)
+            }
+            phase => phase,
+        };
+
+        if next != self.phase {
+            self.enter(next, status, now_s);
+        }
+    }
+}
+
+/// Size of `GaugeStatus3()` as returned by `revision`, in bytes.
+const fn gauge_status_3_size(revision: Revision) -> usize {
+    match revision {
+        Revision::R5 => GAUGE_STATUS_3_R5_SIZE_BYTES,
+        _ => GAUGE_STATUS_3_SIZE_BYTES,
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read `GaugingStatus()`, `GaugeStatus2()` and `GaugeStatus3()` as laid out by `revision`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub(crate) async fn read_learning_status(
+        &mut self,
+        revision: Revision,
+    ) -> Result<LearningStatus, BQ40Z50Error<I2C::Error>> {
+        let mut gauging_status = [0u8; GAUGING_STATUS_SIZE_BYTES];
+        self.read_with_retries(&[GAUGING_STATUS_REG], &mut gauging_status, self.config.pec_read)
+            .await?;
+
+        let mut gauge_status_2 = [0u8; GAUGE_STATUS_2_SIZE_BYTES];
+        self.mac_read_with_retries(
+            &[
+                MAC_CMD,
+                MAC_CMD_ADDR_SIZE_BYTES,
+                GAUGE_STATUS_2_CMD[0],
+                GAUGE_STATUS_2_CMD[1],
+            ],
+            &mut gauge_status_2,
+            self.config.pec_read,
+        )
+        .await?;
+
+        let mut gauge_status_3 = [0u8; GAUGE_STATUS_3_R5_SIZE_BYTES];
+        self.mac_read_with_retries(
+            &[
+                MAC_CMD,
+                MAC_CMD_ADDR_SIZE_BYTES,
+                GAUGE_STATUS_3_CMD[0],
+                GAUGE_STATUS_3_CMD[1],
+            ],
+            &mut gauge_status_3[..gauge_status_3_size(revision)],
+            self.config.pec_read,
+        )
+        .await?;
+
+        let word = |data: &[u8], at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let signed_word = |data: &[u8], at: usize| i16::from_le_bytes([data[at], data[at + 1]]);
+        Ok(LearningStatus {
+            gauging_status: GaugingStatus(u32::from_le_bytes(gauging_status)),
+            update_status: UpdateStatus(gauge_status_2[1]),
+            state_time_s: u32::from_le_bytes([
+                gauge_status_2[6],
+                gauge_status_2[7],
+                gauge_status_2[8],
+                gauge_status_2[9],
+            ]),
+            dod0: core::array::from_fn(|cell| word(&gauge_status_2, 10 + 2 * cell)),
+            dod0_passed_charge: signed_word(&gauge_status_2, 18),
+            dod0_time: word(&gauge_status_2, 22),
+            qmax: core::array::from_fn(|cell| word(&gauge_status_3, 2 * cell)),
+            qmax_passed_charge: signed_word(&gauge_status_3, 16),
+            qmax_time: word(&gauge_status_3, 18),
+        })
+    }
+}
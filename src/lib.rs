@@ -17,27 +17,76 @@
 //! [`Datasheet R5`]: https://www.ti.com/lit/ug/sluucn4b/sluucn4b.pdf
 
 #![doc = include_str!("../README.md")]
-#![cfg_attr(not(test), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![allow(missing_docs)]
 
+mod auth;
+mod blocking;
+mod calibration;
+mod chemistry;
 mod common;
 mod consts;
+mod dataflash;
+mod diagnostics;
+#[cfg(any(feature = "r1", feature = "r3", feature = "r4", feature = "r5"))]
+mod dynamic;
 mod error;
+#[cfg(feature = "std")]
+pub mod fault;
+mod flashstream;
+#[cfg(feature = "std")]
+pub mod gg;
 mod interface;
+mod learning;
+mod lifetime;
+mod monitor;
+mod retry;
+mod security;
+mod session;
+mod signature;
+#[cfg(feature = "std")]
+pub mod sim;
+mod snapshot;
+mod status;
 mod tests;
+mod timeout;
+mod traits;
 mod versions;
 
 // Re-export types for public use
-#[cfg(any(feature = "r3", feature = "r4", feature = "r5"))]
-pub use common::ChargingVoltageOverride;
-pub use common::Config;
+pub use auth::{AuthChallenge, AuthDigest, AuthKey, expected_digest, generate_challenge};
+pub use blocking::{Blocking, BlockingDeviceInterface};
+pub use calibration::{CalibrationChange, CalibrationReport, CalibrationStep, TemperatureSensor};
+pub use chemistry::{ChemImage, ChemRecord, ChemistryInfo};
+pub use common::{ChargingVoltageOverride, Config, Revision};
+pub use dataflash::{DfParam, DfRange, DfString, DfType, DfValue};
+pub use diagnostics::{BlackBoxEvent, PfCapture, PfReport};
+#[cfg(any(feature = "r1", feature = "r3", feature = "r4", feature = "r5"))]
+pub use dynamic::AnyBq40z50;
 pub use error::BQ40Z50Error;
+pub use flashstream::{FlashStream, FlashStreamBytes, FlashStreamCommand, FlashStreamError, FlashStreamProgress};
+pub use learning::{
+    GaugingStatus, LearningAction, LearningConfig, LearningCycle, LearningFailure, LearningPhase, LearningProgress,
+    LearningStatus, UpdateStatus,
+};
+pub use lifetime::{
+    EventCount, LIFETIME_EVENTS, LifetimeData, LifetimeEvent, ModeTemperatures, RSOC_BANDS, ResetCounts,
+    TEMPERATURE_BANDS, TemperatureBand, TemperatureExtremes,
+};
+pub use monitor::{Monitor, MonitorConfig, MonitorEvent};
+pub use retry::{Backoff, Retry, RetryPolicy};
+pub use security::SecurityState;
+pub use session::UnsealedSession;
+pub use signature::{SignatureRegion, SignatureReport, Signatures};
+pub use snapshot::{Snapshot, SnapshotFields};
+pub use status::{ActiveFlag, ActiveFlags, Recovery, Severity, StatusFlag, StatusRegister};
+pub use traits::Bq40z50;
 #[cfg(feature = "r1")]
-pub use versions::r1::Bq40z50R1;
+pub use versions::r1::{Bq40z50R1, Bq40z50R1Blocking};
 #[cfg(feature = "r3")]
-pub use versions::r3::Bq40z50R3;
+pub use versions::r3::{Bq40z50R3, Bq40z50R3Blocking};
 #[cfg(feature = "r4")]
-pub use versions::r4::Bq40z50R4;
+pub use versions::r4::{Bq40z50R4, Bq40z50R4Blocking};
 #[cfg(feature = "r5")]
-pub use versions::r5::Bq40z50R5;
+pub use versions::r5::{Bq40z50R5, Bq40z50R5Blocking};
 pub use versions::*;
@@ -0,0 +1,474 @@
+//! Lifetime data collected by the gauge across every `MAC_LIFETIME_DATA_BLOCK_n` command, decoded per revision.
+//!
+//! The number and layout of the blocks differ between revisions: R1 has blocks 1 to 5, R3 adds the time spent in
+//! every temperature and state of charge band (blocks 6 to 12), R4 replaces the lifetime temperatures of block 1 with
+//! per-mode temperatures (blocks 13 to 15) and R5 adds the time spent below the under temperature threshold (block 16).
+//! Fields a revision does not record are `None`.
+
+use embedded_batteries_async::smart_battery::MilliVolts;
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::common::Revision;
+use crate::consts::{
+    LARGEST_CMD_SIZE_BYTES, LIFETIME_DATA_BLOCK_1_CMD, LIFETIME_DATA_BLOCK_16_CMD, LIFETIME_DATA_BLOCKS, MAC_CMD,
+    MAC_CMD_ADDR_SIZE_BYTES, SBS_CELL_COUNT,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+/// Number of temperature bands, see [`TemperatureBand`]
+pub const TEMPERATURE_BANDS: usize = 7;
+/// Number of relative state of charge bands, `A` (lowest) to `H`
+pub const RSOC_BANDS: usize = 8;
+/// Number of events counted in blocks 4 and 5, see [`LifetimeEvent`]
+pub const LIFETIME_EVENTS: usize = 16;
+
+/// R1 counts time in units of 2 hours
+const R1_TIME_UNIT_S: u32 = 2 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Temperature band delimited by the `JEITA` thresholds `T1` to `T6`
+pub enum TemperatureBand {
+    /// Under temperature, below `T1`
+    UnderTemperature,
+    /// Low temperature, `T1` to `T2`
+    Low,
+    /// Standard temperature low, `T2` to `T3`
+    StandardLow,
+    /// Recommended temperature, `T3` to `T4`
+    Recommended,
+    /// Standard temperature high, `T4` to `T5`
+    StandardHigh,
+    /// High temperature, `T5` to `T6`
+    High,
+    /// Over temperature, above `T6`
+    OverTemperature,
+}
+
+impl TemperatureBand {
+    /// Every band, coldest first, in the order of the lifetime data histograms
+    pub const ALL: [Self; TEMPERATURE_BANDS] = [
+        Self::UnderTemperature,
+        Self::Low,
+        Self::StandardLow,
+        Self::Recommended,
+        Self::StandardHigh,
+        Self::High,
+        Self::OverTemperature,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Event counted in lifetime data blocks 4 and 5, in block order
+pub enum LifetimeEvent {
+    CellOvervoltage,
+    CellUndervoltage,
+    OvercurrentDischarge1,
+    OvercurrentDischarge2,
+    OvercurrentCharge1,
+    OvercurrentCharge2,
+    OverloadDischarge,
+    ShortCircuitDischarge,
+    ShortCircuitCharge,
+    OvertemperatureCharge,
+    OvertemperatureDischarge,
+    OvertemperatureFet,
+    ValidChargeTermination,
+    QmaxUpdate,
+    RaUpdate,
+    RaDisable,
+}
+
+impl LifetimeEvent {
+    /// Every event, in block order
+    pub const ALL: [Self; LIFETIME_EVENTS] = [
+        Self::CellOvervoltage,
+        Self::CellUndervoltage,
+        Self::OvercurrentDischarge1,
+        Self::OvercurrentDischarge2,
+        Self::OvercurrentCharge1,
+        Self::OvercurrentCharge2,
+        Self::OverloadDischarge,
+        Self::ShortCircuitDischarge,
+        Self::ShortCircuitCharge,
+        Self::OvertemperatureCharge,
+        Self::OvertemperatureDischarge,
+        Self::OvertemperatureFet,
+        Self::ValidChargeTermination,
+        Self::QmaxUpdate,
+        Self::RaUpdate,
+        Self::RaDisable,
+    ];
+
+    /// Whether the event is a safety protection trip, as opposed to a gauging event.
+    #[must_use]
+    pub const fn is_protection(self) -> bool {
+        !matches!(
+            self,
+            Self::ValidChargeTermination | Self::QmaxUpdate | Self::RaUpdate | Self::RaDisable
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Occurrences of a [`LifetimeEvent`]
+pub struct EventCount {
+    pub count: u16,
+    /// `CycleCount()` at the last occurrence
+    pub last_cycle: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Extreme temperatures, in °C
+pub struct TemperatureExtremes {
+    pub max_cell: i8,
+    pub min_cell: i8,
+    pub max_delta_cell: i8,
+    pub max_internal: i8,
+    pub min_internal: i8,
+    pub max_fet: i8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Extreme temperatures recorded while relaxing, charging or discharging, in °C
+pub struct ModeTemperatures {
+    pub extremes: TemperatureExtremes,
+    /// TS1 to TS4
+    pub max_ts: [i8; 4],
+    /// TS1 to TS4
+    pub min_ts: [i8; 4],
+    /// External `TMP468` sensors 1 to 8
+    pub max_tmp468: [i8; 8],
+    /// External `TMP468` sensors 1 to 8
+    pub min_tmp468: [i8; 8],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Reset counters, recorded up to R3
+pub struct ResetCounts {
+    pub shutdowns: u8,
+    pub partial_resets: u8,
+    pub full_resets: u8,
+    pub watchdog_resets: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Lifetime data read by [`read_lifetime_data`](crate::Bq40z50::read_lifetime_data).
+///
+/// Currents are in mA, with discharge negative, and times in seconds.
+pub struct LifetimeData {
+    pub revision: Revision,
+    /// Bit `n - 1` is set if `MAC_LIFETIME_DATA_BLOCK_n` was read
+    pub blocks_read: u16,
+    /// Cell 1 to cell 4
+    pub max_cell_voltage: [MilliVolts; SBS_CELL_COUNT],
+    /// Cell 1 to cell 4
+    pub min_cell_voltage: [MilliVolts; SBS_CELL_COUNT],
+    pub max_delta_cell_voltage: MilliVolts,
+    pub max_charge_current: i16,
+    pub max_discharge_current: i16,
+    pub max_avg_discharge_current: i16,
+    /// In cW
+    pub max_avg_discharge_power: i16,
+    /// Lifetime extremes, recorded up to R3
+    pub temperatures: Option<TemperatureExtremes>,
+    /// Recorded up to R3
+    pub resets: Option<ResetCounts>,
+    /// Minimum `FullChargeCapacity()` used for the state of health, in mAh, recorded from R4
+    pub min_fcc_soh_mah: Option<u16>,
+    /// Minimum `FullChargeCapacity()` used for the state of health, in cWh, recorded from R4
+    pub min_fcc_soh_cwh: Option<u16>,
+    /// Cell 1 to cell 4
+    pub cell_balancing_time_s: [u32; SBS_CELL_COUNT],
+    pub total_runtime_s: u32,
+    /// Indexed by [`LifetimeEvent`]
+    pub events: [EventCount; LIFETIME_EVENTS],
+    /// Time spent in every temperature band, recorded by R1 only, see [`LifetimeData::temperature_histogram`]
+    pub band_time_s: Option<[u32; TEMPERATURE_BANDS]>,
+    /// Time spent in every temperature band, split by relative state of charge band, recorded from R3
+    pub band_rsoc_time_s: Option<[[u32; RSOC_BANDS]; TEMPERATURE_BANDS]>,
+    /// Time spent below the under temperature threshold by relative state of charge band, recorded by R5
+    pub below_band_rsoc_time_s: Option<[u32; RSOC_BANDS]>,
+    /// Recorded from R4
+    pub relax_temperatures: Option<ModeTemperatures>,
+    /// Recorded from R4
+    pub charge_temperatures: Option<ModeTemperatures>,
+    /// Recorded from R4
+    pub discharge_temperatures: Option<ModeTemperatures>,
+}
+
+impl LifetimeData {
+    const fn new(revision: Revision) -> Self {
+        Self {
+            revision,
+            blocks_read: 0,
+            max_cell_voltage: [0; SBS_CELL_COUNT],
+            min_cell_voltage: [0; SBS_CELL_COUNT],
+            max_delta_cell_voltage: 0,
+            max_charge_current: 0,
+            max_discharge_current: 0,
+            max_avg_discharge_current: 0,
+            max_avg_discharge_power: 0,
+            temperatures: None,
+            resets: None,
+            min_fcc_soh_mah: None,
+            min_fcc_soh_cwh: None,
+            cell_balancing_time_s: [0; SBS_CELL_COUNT],
+            total_runtime_s: 0,
+            events: [EventCount {
+                count: 0,
+                last_cycle: 0,
+            }; LIFETIME_EVENTS],
+            band_time_s: None,
+            band_rsoc_time_s: None,
+            below_band_rsoc_time_s: None,
+            relax_temperatures: None,
+            charge_temperatures: None,
+            discharge_temperatures: None,
+        }
+    }
+
+    /// Whether `MAC_LIFETIME_DATA_BLOCK_n` was read, `n` counting from 1.
+    #[must_use]
+    pub const fn block_read(&self, n: usize) -> bool {
+        n >= 1 && n <= LIFETIME_DATA_BLOCKS && self.blocks_read & (1 << (n - 1)) != 0
+    }
+
+    /// Occurrences of `event`.
+    #[must_use]
+    pub const fn event(&self, event: LifetimeEvent) -> EventCount {
+        self.events[event as usize]
+    }
+
+    /// Total number of safety protection trips, see [`LifetimeEvent::is_protection`].
+    #[must_use]
+    pub fn protection_events(&self) -> u32 {
+        LifetimeEvent::ALL
+            .into_iter()
+            .filter(|event| event.is_protection())
+            .map(|event| u32::from(self.event(event).count))
+            .sum()
+    }
+
+    /// Time spent in every temperature band, in the order of [`TemperatureBand::ALL`], summed across the state of
+    /// charge bands from R3.
+    #[must_use]
+    pub fn temperature_histogram(&self) -> Option<[u32; TEMPERATURE_BANDS]> {
+        if let Some(band_time_s) = self.band_time_s {
+            return Some(band_time_s);
+        }
+
+        self.band_rsoc_time_s
+            .map(|bands| bands.map(|rsoc| rsoc.into_iter().fold(0, u32::saturating_add)))
+    }
+
+    /// Time spent in every relative state of charge band, `A` (lowest) to `H`, summed across the temperature bands.
+    /// `None` before R3.
+    #[must_use]
+    pub fn rsoc_histogram(&self) -> Option<[u32; RSOC_BANDS]> {
+        let bands = self.band_rsoc_time_s?;
+        let mut histogram = [0u32; RSOC_BANDS];
+        for rsoc in bands {
+            for (total, time) in histogram.iter_mut().zip(rsoc) {
+                *total = total.saturating_add(time);
+            }
+        }
+
+        Some(histogram)
+    }
+
+    /// Time spent in `band`.
+    #[must_use]
+    pub fn time_in_band(&self, band: TemperatureBand) -> Option<u32> {
+        self.temperature_histogram().map(|histogram| histogram[band as usize])
+    }
+
+    /// Decode block `n`, `n` counting from 1, laid out as `revision` records it.
+    fn decode_block(&mut self, n: usize, data: &[u8]) {
+        let mut block = Block(data);
+        match n {
+            1 => {
+                for voltage in self.max_cell_voltage.iter_mut().chain(&mut self.min_cell_voltage) {
+                    *voltage = block.u16();
+                }
+                self.max_delta_cell_voltage = block.u16();
+                self.max_charge_current = block.i16();
+                self.max_discharge_current = block.i16();
+                self.max_avg_discharge_current = block.i16();
+                self.max_avg_discharge_power = block.i16();
+                if matches!(self.revision, Revision::R1 | Revision::R3) {
+                    self.temperatures = Some(block.extremes());
+                }
+            }
+            2 => {
+                if matches!(self.revision, Revision::R1 | Revision::R3) {
+                    self.resets = Some(ResetCounts {
+                        shutdowns: block.u8(),
+                        partial_resets: block.u8(),
+                        full_resets: block.u8(),
+                        watchdog_resets: block.u8(),
+                    });
+                } else {
+                    self.min_fcc_soh_mah = Some(block.u16());
+                    self.min_fcc_soh_cwh = Some(block.u16());
+                }
+                for time in &mut self.cell_balancing_time_s {
+                    *time = if self.revision == Revision::R1 {
+                        u32::from(block.u8()) * R1_TIME_UNIT_S
+                    } else {
+                        block.u32()
+                    };
+                }
+            }
+            3 if self.revision == Revision::R1 => {
+                self.total_runtime_s = u32::from(block.u16()) * R1_TIME_UNIT_S;
+                self.band_time_s = Some([(); TEMPERATURE_BANDS].map(|()| u32::from(block.u16()) * R1_TIME_UNIT_S));
+            }
+            3 => self.total_runtime_s = block.u32(),
+            4 | 5 => {
+                for event in &mut self.events[(n - 4) * LIFETIME_EVENTS / 2..][..LIFETIME_EVENTS / 2] {
+                    *event = EventCount {
+                        count: block.u16(),
+                        last_cycle: block.u16(),
+                    };
+                }
+            }
+            6..=12 => {
+                self.band_rsoc_time_s
+                    .get_or_insert([[0; RSOC_BANDS]; TEMPERATURE_BANDS])[n - 6] = block.rsoc_times();
+            }
+            13..=15 => {
+                let temperatures = Some(ModeTemperatures {
+                    extremes: block.extremes(),
+                    max_ts: [(); 4].map(|()| block.i8()),
+                    min_ts: [(); 4].map(|()| block.i8()),
+                    max_tmp468: [(); 8].map(|()| block.i8()),
+                    min_tmp468: [(); 8].map(|()| block.i8()),
+                });
+                match n {
+                    13 => self.relax_temperatures = temperatures,
+                    14 => self.charge_temperatures = temperatures,
+                    _ => self.discharge_temperatures = temperatures,
+                }
+            }
+            _ => self.below_band_rsoc_time_s = Some(block.rsoc_times()),
+        }
+
+        self.blocks_read |= 1 << (n - 1);
+    }
+}
+
+/// Number of lifetime data blocks recorded by `revision`.
+const fn block_count(revision: Revision) -> usize {
+    match revision {
+        Revision::R1 => 5,
+        Revision::R3 => 12,
+        Revision::R4 => 15,
+        Revision::R5 => 16,
+    }
+}
+
+/// Size of block `n` as recorded by `revision`, in bytes.
+const fn block_size(revision: Revision, n: usize) -> usize {
+    match (revision, n) {
+        (Revision::R1 | Revision::R3, 1) => 32,
+        (_, 1) => 26,
+        (Revision::R1, 2) => 8,
+        (_, 2) => 20,
+        (Revision::R1, 3) => 16,
+        (_, 3) => 4,
+        (_, 13..=15) => 30,
+        _ => 32,
+    }
+}
+
+/// Little endian reader over a lifetime data block
+struct Block<'a>(&'a [u8]);
+
+impl Block<'_> {
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (bytes, rest) = self.0.split_at(N);
+        self.0 = rest;
+        // Infallible because split_at returned exactly N bytes
+        bytes.try_into().unwrap()
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+
+    fn i8(&mut self) -> i8 {
+        i8::from_le_bytes(self.take())
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+
+    fn i16(&mut self) -> i16 {
+        i16::from_le_bytes(self.take())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+
+    fn extremes(&mut self) -> TemperatureExtremes {
+        TemperatureExtremes {
+            max_cell: self.i8(),
+            min_cell: self.i8(),
+            max_delta_cell: self.i8(),
+            max_internal: self.i8(),
+            min_internal: self.i8(),
+            max_fet: self.i8(),
+        }
+    }
+
+    fn rsoc_times(&mut self) -> [u32; RSOC_BANDS] {
+        [(); RSOC_BANDS].map(|()| self.u32())
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read every lifetime data block recorded by `revision`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub(crate) async fn read_lifetime_data(
+        &mut self,
+        revision: Revision,
+    ) -> Result<LifetimeData, BQ40Z50Error<I2C::Error>> {
+        let mut lifetime = LifetimeData::new(revision);
+        let mut data = [0u8; LARGEST_CMD_SIZE_BYTES];
+
+        for n in 1..=block_count(revision) {
+            let cmd = if n == LIFETIME_DATA_BLOCKS {
+                LIFETIME_DATA_BLOCK_16_CMD
+            } else {
+                // Infallible because there are at most 16 blocks
+                LIFETIME_DATA_BLOCK_1_CMD + u16::try_from(n - 1).unwrap()
+            }
+            .to_le_bytes();
+            let data = &mut data[..block_size(revision, n)];
+
+            self.mac_read_with_retries(
+                &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, cmd[0], cmd[1]],
+                data,
+                self.config.pec_read,
+            )
+            .await?;
+            lifetime.decode_block(n, data);
+        }
+
+        Ok(lifetime)
+    }
+}
@@ -0,0 +1,252 @@
+//! Polling monitor raising typed events when the gauge's status registers change.
+//!
+//! [`Monitor::run`] replaces the hand-written polling loop: it reads `BatteryStatus()`, `SafetyAlert()`,
+//! `PFStatus()` and `OperationStatus()` at the rates set in [`MonitorConfig`], compares them with the previous values
+//! and calls back with a [`MonitorEvent`] for every change. The callback can forward events into a channel, e.g. with
+//! `try_send`. Applications with their own scheduler call [`Monitor::poll`] instead.
+
+use core::convert::Infallible;
+
+use embedded_batteries_async::smart_battery::BatteryStatusFields;
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+
+use crate::consts::{OPERATION_STATUS_SEC_MASK, OPERATION_STATUS_SEC_SHIFT};
+use crate::security::SecurityState;
+use crate::snapshot::SnapshotFields;
+use crate::status::StatusRegister;
+use crate::traits::Bq40z50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Polling interval of every register watched by a [`Monitor`], in milliseconds. An interval of 0 disables polling
+/// the register.
+pub struct MonitorConfig {
+    pub battery_status_ms: u32,
+    pub safety_alert_ms: u32,
+    pub pf_status_ms: u32,
+    pub operation_status_ms: u32,
+}
+
+impl MonitorConfig {
+    /// Poll `BatteryStatus()` and `SafetyAlert()` every second, `PFStatus()` and `OperationStatus()` every 5 seconds.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            battery_status_ms: 1000,
+            safety_alert_ms: 1000,
+            pf_status_ms: 5000,
+            operation_status_ms: 5000,
+        }
+    }
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Change detected by a [`Monitor`]. Flag bits can be decoded with [`ActiveFlags`](crate::ActiveFlags).
+pub enum MonitorEvent {
+    /// `DSG` cleared in `BatteryStatus()`
+    ChargeStarted,
+    /// `FC` set in `BatteryStatus()`
+    FullyCharged,
+    /// `TDA` set in `BatteryStatus()`
+    TerminateDischargeAlarm,
+    /// Bits newly set in `SafetyAlert()`
+    SafetyFaultRaised { bits: u32 },
+    /// Bits newly cleared in `SafetyAlert()`
+    SafetyFaultCleared { bits: u32 },
+    /// Bits newly set in `PFStatus()`
+    PermanentFailure { bits: u32 },
+    /// Security mode reported by `OperationStatus()` changed
+    SecurityStateChanged {
+        previous: SecurityState,
+        current: SecurityState,
+    },
+}
+
+/// Watches the gauge's status registers and reports changes as [`MonitorEvent`]s.
+///
+/// The first read of `BatteryStatus()` and `OperationStatus()` only records their value. Safety and permanent failure
+/// bits are compared against a clear register, so faults already present when monitoring starts are reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monitor {
+    config: MonitorConfig,
+    /// Time at which each register is due next, in the order of [`REGISTERS`]
+    next_due_ms: [u64; 4],
+    battery_status: Option<BatteryStatusFields>,
+    safety_alert: u32,
+    pf_status: u32,
+    security_state: Option<SecurityState>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Watched {
+    BatteryStatus,
+    SafetyAlert,
+    PfStatus,
+    OperationStatus,
+}
+
+const REGISTERS: [Watched; 4] = [
+    Watched::BatteryStatus,
+    Watched::SafetyAlert,
+    Watched::PfStatus,
+    Watched::OperationStatus,
+];
+
+impl Monitor {
+    /// Create a monitor. Every enabled register is due on the first poll.
+    #[must_use]
+    pub const fn new(config: MonitorConfig) -> Self {
+        Self {
+            config,
+            next_due_ms: [0; 4],
+            battery_status: None,
+            safety_alert: 0,
+            pf_status: 0,
+            security_state: None,
+        }
+    }
+
+    /// Current monitor config.
+    #[must_use]
+    pub const fn config(&self) -> MonitorConfig {
+        self.config
+    }
+
+    /// Read every register due at `now_ms` and call `on_event` for each change.
+    ///
+    /// Returns the time at which the next register is due, or `u64::MAX` if every register is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs. Registers read before the failing one keep their new value.
+    pub async fn poll<T: Bq40z50>(
+        &mut self,
+        bq: &mut T,
+        now_ms: u64,
+        mut on_event: impl FnMut(MonitorEvent),
+    ) -> Result<u64, T::Error> {
+        for (i, register) in REGISTERS.into_iter().enumerate() {
+            let interval_ms = self.interval_ms(register);
+            if interval_ms == 0 || self.next_due_ms[i] > now_ms {
+                continue;
+            }
+
+            self.read(bq, register, &mut on_event).await?;
+            self.next_due_ms[i] = now_ms + u64::from(interval_ms);
+        }
+
+        Ok(self.next_due_ms())
+    }
+
+    /// Poll the gauge forever, sleeping on `delay` until the next register is due.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn run<T: Bq40z50, D: DelayTrait>(
+        &mut self,
+        bq: &mut T,
+        delay: &mut D,
+        mut on_event: impl FnMut(MonitorEvent),
+    ) -> Result<Infallible, T::Error> {
+        let mut now_ms = 0;
+        loop {
+            let next_ms = self.poll(bq, now_ms, &mut on_event).await?;
+            let sleep_ms = u32::try_from(next_ms - now_ms).unwrap_or(u32::MAX);
+            delay.delay_ms(sleep_ms).await;
+            now_ms += u64::from(sleep_ms);
+        }
+    }
+
+    fn interval_ms(&self, register: Watched) -> u32 {
+        match register {
+            Watched::BatteryStatus => self.config.battery_status_ms,
+            Watched::SafetyAlert => self.config.safety_alert_ms,
+            Watched::PfStatus => self.config.pf_status_ms,
+            Watched::OperationStatus => self.config.operation_status_ms,
+        }
+    }
+
+    fn next_due_ms(&self) -> u64 {
+        REGISTERS
+            .into_iter()
+            .zip(self.next_due_ms)
+            .filter(|&(register, _)| self.interval_ms(register) != 0)
+            .map(|(_, due)| due)
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    async fn read<T: Bq40z50>(
+        &mut self,
+        bq: &mut T,
+        register: Watched,
+        on_event: &mut impl FnMut(MonitorEvent),
+    ) -> Result<(), T::Error> {
+        match register {
+            Watched::BatteryStatus => {
+                // Read through the snapshot, which unlike `battery_status` does not fail on a non-zero error code.
+                let snapshot = bq.read_snapshot(SnapshotFields::BATTERY_STATUS, || 0).await?;
+                let Some(current) = snapshot.battery_status else {
+                    return Ok(());
+                };
+                if let Some(previous) = self.battery_status.replace(current) {
+                    if previous.discharging() && !current.discharging() {
+                        on_event(MonitorEvent::ChargeStarted);
+                    }
+                    if !previous.fully_charged() && current.fully_charged() {
+                        on_event(MonitorEvent::FullyCharged);
+                    }
+                    if !previous.terminate_discharge_alarm() && current.terminate_discharge_alarm() {
+                        on_event(MonitorEvent::TerminateDischargeAlarm);
+                    }
+                }
+            }
+            Watched::SafetyAlert => {
+                let current = bq.read_status(StatusRegister::SafetyAlert).await?;
+                let previous = core::mem::replace(&mut self.safety_alert, current);
+                if current & !previous != 0 {
+                    on_event(MonitorEvent::SafetyFaultRaised {
+                        bits: current & !previous,
+                    });
+                }
+                if previous & !current != 0 {
+                    on_event(MonitorEvent::SafetyFaultCleared {
+                        bits: previous & !current,
+                    });
+                }
+            }
+            Watched::PfStatus => {
+                let current = bq.read_status(StatusRegister::PfStatus).await?;
+                let previous = core::mem::replace(&mut self.pf_status, current);
+                if current & !previous != 0 {
+                    on_event(MonitorEvent::PermanentFailure {
+                        bits: current & !previous,
+                    });
+                }
+            }
+            Watched::OperationStatus => {
+                let operation_status = bq.read_status(StatusRegister::OperationStatus).await?;
+                let sec = (operation_status >> OPERATION_STATUS_SEC_SHIFT) & OPERATION_STATUS_SEC_MASK;
+                // Infallible because the mask keeps only two bits, the reserved encoding is ignored
+                let Some(current) = SecurityState::from_sec_bits(sec.try_into().unwrap()) else {
+                    return Ok(());
+                };
+                if let Some(previous) = self.security_state.replace(current) {
+                    if previous != current {
+                        on_event(MonitorEvent::SecurityStateChanged { previous, current });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! Retry and backoff behavior of failed bus transfers.
+
+use embedded_hal::i2c::ErrorKind;
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{DEFAULT_BUS_RETRIES, DEFAULT_ERROR_BACKOFF_DELAY_MS};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Time to wait before retrying a failed transfer
+pub enum Backoff {
+    /// Retry immediately
+    None,
+    /// Wait the same time before every retry
+    Fixed { ms: u32 },
+    /// Wait `initial_ms` before the first retry and double the wait for every further retry, up to `max_ms`
+    Exponential { initial_ms: u32, max_ms: u32 },
+}
+
+impl Backoff {
+    /// Time to wait before retry number `retry`, counted from 0.
+    #[must_use]
+    pub fn delay_ms(self, retry: usize) -> u32 {
+        match self {
+            Self::None => 0,
+            Self::Fixed { ms } => ms,
+            Self::Exponential { initial_ms, max_ms } => {
+                let factor = u32::try_from(retry)
+                    .ok()
+                    .and_then(|retry| 1u32.checked_shl(retry))
+                    .unwrap_or(u32::MAX);
+                initial_ms.saturating_mul(factor).min(max_ms)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// How often, and how far apart, one kind of failure is retried
+pub struct Retry {
+    /// Max number of retries after the first attempt
+    pub max_retries: usize,
+    /// Wait before each retry
+    pub backoff: Backoff,
+}
+
+impl Retry {
+    /// Fail on the first error.
+    pub const NEVER: Self = Self {
+        max_retries: 0,
+        backoff: Backoff::None,
+    };
+
+    /// Retry up to `max_retries` times, waiting `ms` before each retry.
+    #[must_use]
+    pub const fn fixed(max_retries: usize, ms: u32) -> Self {
+        Self {
+            max_retries,
+            backoff: Backoff::Fixed { ms },
+        }
+    }
+
+    /// Retry up to `max_retries` times, waiting `initial_ms` before the first retry and doubling the wait up to
+    /// `max_ms`.
+    #[must_use]
+    pub const fn exponential(max_retries: usize, initial_ms: u32, max_ms: u32) -> Self {
+        Self {
+            max_retries,
+            backoff: Backoff::Exponential { initial_ms, max_ms },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Retry behavior of every bus transfer, by kind of failure
+///
+/// The failures of one operation share a retry count: after `n` retries, a failure is only retried again if the
+/// [`Retry`] for its kind allows more than `n` retries, and it waits the backoff of retry `n`.
+pub struct RetryPolicy {
+    /// The gauge did not acknowledge its address or data, e.g. while busy writing flash
+    pub nack: Retry,
+    /// The PEC byte does not match the data read
+    pub pec: Retry,
+    /// Any other bus error, such as arbitration loss, and timeouts
+    pub bus: Retry,
+}
+
+impl RetryPolicy {
+    /// Retry every kind of failure 3 times, 10 ms apart.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self::uniform(Retry::fixed(DEFAULT_BUS_RETRIES, DEFAULT_ERROR_BACKOFF_DELAY_MS))
+    }
+
+    /// Apply `retry` to every kind of failure.
+    #[must_use]
+    pub const fn uniform(retry: Retry) -> Self {
+        Self {
+            nack: retry,
+            pec: retry,
+            bus: retry,
+        }
+    }
+
+    /// The retry behavior for `error`. Errors that are not bus failures are never retried.
+    #[must_use]
+    pub fn retry_for<E: embedded_hal::i2c::Error>(&self, error: &BQ40Z50Error<E>) -> Retry {
+        match error {
+            BQ40Z50Error::I2c(e) if matches!(e.kind(), ErrorKind::NoAcknowledge(_)) => self.nack,
+            BQ40Z50Error::Pec => self.pec,
+            BQ40Z50Error::I2c(_) | BQ40Z50Error::Timeout => self.bus,
+            _ => Retry::NEVER,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Wait out the backoff before the next attempt, after `retries` retries failed with `error`.
+    ///
+    /// Returns `error` once the retry policy gives up on it.
+    pub(crate) async fn retry_after(
+        &mut self,
+        retries: &mut usize,
+        error: BQ40Z50Error<I2C::Error>,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        let retry = self.config.retry.retry_for(&error);
+        if *retries >= retry.max_retries {
+            return Err(error);
+        }
+
+        let delay_ms = retry.backoff.delay_ms(*retries);
+        *retries += 1;
+        if delay_ms > 0 {
+            // The fuel gauge might still be "thinking" from a previous command
+            self.delay.delay_ms(delay_ms).await;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,170 @@
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{
+    MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, OPERATION_STATUS_REG, OPERATION_STATUS_SEC_MASK, OPERATION_STATUS_SEC_SHIFT,
+    OPERATION_STATUS_SIZE_BYTES, SEAL_CMD, WRONG_KEY_LOCKOUT_MS,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Security mode reported by the `SEC` bits of `OperationStatus`
+pub enum SecurityState {
+    FullAccess,
+    Unsealed,
+    Sealed,
+}
+
+impl SecurityState {
+    /// Decode the two `SEC` bits of `OperationStatus`. The reserved encoding returns `None`.
+    #[must_use]
+    pub const fn from_sec_bits(sec: u8) -> Option<Self> {
+        match sec {
+            1 => Some(Self::FullAccess),
+            2 => Some(Self::Unsealed),
+            3 => Some(Self::Sealed),
+            _ => None,
+        }
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read the current security mode from `OperationStatus`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the gauge reports the
+    /// reserved security mode.
+    pub async fn read_security_state(&mut self) -> Result<SecurityState, BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; OPERATION_STATUS_SIZE_BYTES];
+        self.read_with_retries(&[OPERATION_STATUS_REG], &mut buf, self.config.pec_read)
+            .await?;
+
+        let sec = (u32::from_le_bytes(buf) >> OPERATION_STATUS_SEC_SHIFT) & OPERATION_STATUS_SEC_MASK;
+        // Infallible because the mask keeps only two bits
+        SecurityState::from_sec_bits(sec.try_into().unwrap()).ok_or(BQ40Z50Error::UnexpectedResponse)
+    }
+
+    /// Send `MAC_SEAL` and verify the gauge reports [`SecurityState::Sealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge did not
+    /// seal.
+    pub async fn seal(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.mac_write_with_retries(
+            &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, SEAL_CMD[0], SEAL_CMD[1]],
+            self.config.pec_write,
+        )
+        .await?;
+
+        let actual = self.read_security_state().await?;
+        if actual != SecurityState::Sealed {
+            return Err(BQ40Z50Error::SecurityStateMismatch {
+                expected: SecurityState::Sealed,
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Send the unseal key and verify the gauge reports [`SecurityState::Unsealed`] afterwards.
+    ///
+    /// Does nothing if the gauge is already unsealed or in full access.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the
+    /// key.
+    pub async fn unseal(&mut self, key_lower: u16, key_upper: u16) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        if self.read_security_state().await? != SecurityState::Sealed {
+            return Ok(());
+        }
+
+        self.send_key_and_verify(key_lower, key_upper, SecurityState::Unsealed)
+            .await
+    }
+
+    /// Send the full access key and verify the gauge reports [`SecurityState::FullAccess`] afterwards.
+    ///
+    /// The gauge only accepts the full access key while unsealed, so [`unseal`](Self::unseal) must succeed first.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::SecurityStateMismatch`] if the gauge is sealed, or
+    /// [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the key.
+    pub async fn enter_full_access(&mut self, key_lower: u16, key_upper: u16) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        match self.read_security_state().await? {
+            SecurityState::FullAccess => Ok(()),
+            SecurityState::Sealed => Err(BQ40Z50Error::SecurityStateMismatch {
+                expected: SecurityState::Unsealed,
+                actual: SecurityState::Sealed,
+            }),
+            SecurityState::Unsealed => {
+                self.send_key_and_verify(key_lower, key_upper, SecurityState::FullAccess)
+                    .await
+            }
+        }
+    }
+
+    /// Seal the gauge before the next bus transfer.
+    ///
+    /// For code that cannot await [`seal`](Self::seal), such as a `Drop` impl. The seal command is sent ahead of the
+    /// next transfer, with that transfer's retries, but the resulting security state is not verified.
+    pub fn seal_before_next_transfer(&mut self) {
+        self.seal_pending = true;
+    }
+
+    /// Send `MAC_SEAL` if [`seal_before_next_transfer`](Self::seal_before_next_transfer) asked for it.
+    pub(crate) async fn seal_if_pending(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        if !self.seal_pending {
+            return Ok(());
+        }
+
+        let mut buf = [MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, SEAL_CMD[0], SEAL_CMD[1], 0];
+        let len = if self.config.pec_write {
+            buf[4] = smbus_pec::pec(&[self.config.address << 1, buf[0], buf[1], buf[2], buf[3]]);
+            5
+        } else {
+            4
+        };
+        self.timed_write(&buf[..len]).await?;
+        self.seal_pending = false;
+
+        Ok(())
+    }
+
+    async fn send_key_and_verify(
+        &mut self,
+        key_lower: u16,
+        key_upper: u16,
+        expected: SecurityState,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        // The gauge ignores every key for 4 seconds after a wrong one. There is no clock to tell how much of that
+        // time has already passed, so sit out the whole lockout before trying again.
+        if self.key_lockout {
+            self.delay.delay_ms(WRONG_KEY_LOCKOUT_MS).await;
+            self.key_lockout = false;
+        }
+
+        for key in [key_lower, key_upper] {
+            let key = key.to_le_bytes();
+            self.mac_write_with_retries(
+                &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, key[0], key[1]],
+                self.config.pec_write,
+            )
+            .await?;
+        }
+
+        let state = self.read_security_state().await?;
+        if state != expected {
+            self.key_lockout = true;
+            return Err(BQ40Z50Error::KeyRejected { state });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+use crate::common::ChargingVoltageOverride;
+use crate::consts::{AUTH_KEY_LEN_BYTES, SECURITY_KEYS_DATA_LEN_BYTES};
+use crate::dataflash::{DfParam, DfValue};
+use crate::security::SecurityState;
+use crate::traits::Bq40z50;
+
+/// Unsealed access to a gauge, borrowed from its driver for the duration of the privileged operations.
+///
+/// The gauge is unsealed (and verified) when the session begins, and put back in the security state it was found in by
+/// [`finish`](Self::finish). A gauge that was already unsealed or in full access is left that way. There is no async
+/// drop, so a session dropped without finishing (e.g. by an early `?`) asks the driver to seal the gauge before its
+/// next bus transfer instead. Prefer [`scoped`](Self::scoped), which reseals on every exit path of the privileged
+/// operations, including errors, and reports whether sealing succeeded.
+#[must_use = "the gauge stays unsealed until the session is finished or the driver is used again"]
+pub struct UnsealedSession<'a, T: Bq40z50> {
+    bq: &'a mut T,
+    /// Security state of the gauge before the session
+    prior: SecurityState,
+    /// Set until the gauge is back in its prior security state
+    restore_pending: bool,
+}
+
+impl<'a, T: Bq40z50> UnsealedSession<'a, T> {
+    /// Unseal the gauge and start a session.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or the gauge rejected the unseal key.
+    pub async fn begin(bq: &'a mut T, unseal_key_lower: u16, unseal_key_upper: u16) -> Result<Self, T::Error> {
+        let prior = bq.security_state().await?;
+        if prior == SecurityState::Sealed {
+            bq.unseal(unseal_key_lower, unseal_key_upper).await?;
+        }
+
+        Ok(Self {
+            bq,
+            prior,
+            restore_pending: true,
+        })
+    }
+
+    /// Unseal the gauge, run `f` with the session and restore the gauge's security state, whether `f` succeeded or
+    /// not.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if unsealing fails, `f` fails, or resealing fails. An error from `f` takes precedence over an
+    /// error while resealing.
+    pub async fn scoped<R>(
+        bq: &'a mut T,
+        unseal_key_lower: u16,
+        unseal_key_upper: u16,
+        f: impl AsyncFnOnce(&mut UnsealedSession<'a, T>) -> Result<R, T::Error>,
+    ) -> Result<R, T::Error> {
+        let mut session = Self::begin(bq, unseal_key_lower, unseal_key_upper).await?;
+        let result = f(&mut session).await;
+        let sealed = session.finish().await;
+
+        let value = result?;
+        sealed?;
+        Ok(value)
+    }
+
+    /// Security state of the gauge before the session began.
+    #[must_use]
+    pub fn prior_state(&self) -> SecurityState {
+        self.prior
+    }
+
+    /// Reseal the gauge if it was sealed before the session, and end the session.
+    ///
+    /// If sealing fails, the driver seals the gauge before its next bus transfer.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or the gauge did not seal.
+    pub async fn finish(mut self) -> Result<(), T::Error> {
+        if self.prior == SecurityState::Sealed {
+            self.bq.seal().await?;
+        }
+        self.restore_pending = false;
+
+        Ok(())
+    }
+
+    /// Access the driver for operations not wrapped by the session.
+    pub fn driver(&mut self) -> &mut T {
+        self.bq
+    }
+
+    /// Read from the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_dataflash(&mut self, starting_address: u16, read: &mut [u8]) -> Result<(), T::Error> {
+        self.bq.read_dataflash(starting_address, read).await
+    }
+
+    /// Write to the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_dataflash(&mut self, starting_address: u16, write: &[u8]) -> Result<(), T::Error> {
+        self.bq.write_dataflash(starting_address, write).await
+    }
+
+    /// Read a data flash parameter and decode it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or the data flash holds a string longer than the parameter allows.
+    pub async fn read_df_param(&mut self, param: &DfParam) -> Result<DfValue, T::Error> {
+        self.bq.read_df_param(param).await
+    }
+
+    /// Range-check and encode a data flash parameter, then write it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or `value` does not match the parameter's type or range.
+    pub async fn write_df_param(&mut self, param: &DfParam, value: &DfValue) -> Result<(), T::Error> {
+        self.bq.write_df_param(param, value).await
+    }
+
+    /// Write to the `MfgInfo` register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or `data` is larger than 32 bytes.
+    pub async fn write_mfg_info(&mut self, data: &[u8]) -> Result<(), T::Error> {
+        self.bq.write_mfg_info(data).await
+    }
+
+    /// Write to the `MfgInfoC` MAC register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, `data` is larger than 32 bytes, or the revision has no `MfgInfoC`
+    /// register.
+    pub async fn write_mfg_info_c(&mut self, access: T::MfgInfoCAccess, data: &[u8]) -> Result<(), T::Error> {
+        self.bq.write_mfg_info_c(access, data).await
+    }
+
+    /// Write MAC Register 0x0035 Security Keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_security_keys(
+        &mut self,
+        security_keys: &[u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+    ) -> Result<(), T::Error> {
+        self.bq.write_security_keys(security_keys).await
+    }
+
+    /// Write MAC Register 0x0037 Authentication Key.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn write_authentication_key(
+        &mut self,
+        auth_key: &[u8; AUTH_KEY_LEN_BYTES as usize],
+    ) -> Result<(), T::Error> {
+        self.bq.write_authentication_key(auth_key).await
+    }
+
+    /// Write to the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or the revision has no `ChargingVoltageOverride` command.
+    pub async fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), T::Error> {
+        self.bq.write_charging_voltage_override(override_struct).await
+    }
+}
+
+impl<T: Bq40z50> Drop for UnsealedSession<'_, T> {
+    fn drop(&mut self) {
+        if self.restore_pending && self.prior == SecurityState::Sealed {
+            self.bq.seal_before_next_transfer();
+        }
+    }
+}
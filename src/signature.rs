@@ -0,0 +1,189 @@
+//! Flash signatures, to detect tampering or corruption of the firmware and data flash against a golden pack.
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{
+    ALL_DF_SIG_CMD, INSTRUCTION_FLASH_SIG_CMD, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, SIGNATURE_DELAY_MS,
+    SIGNATURE_MISMATCH_MASK, SIGNATURE_REGIONS, STATIC_CHEM_DF_SIG_CMD, STATIC_DF_SIG_CMD,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Flash region covered by a signature
+pub enum SignatureRegion {
+    /// Firmware, `MAC_INSTRUCTION_FLASH_SIGNATURE`
+    InstructionFlash,
+    /// Static data flash, `MAC_STATIC_DF_SIGNATURE`
+    StaticDataFlash,
+    /// Static chemistry data flash, `MAC_STATIC_CHEM_DF_SIG`
+    StaticChemDataFlash,
+    /// All data flash, `MAC_ALL_DF_SIGNATURE`
+    AllDataFlash,
+}
+
+impl SignatureRegion {
+    /// Every region, in MAC command order
+    pub const ALL: [Self; SIGNATURE_REGIONS] = [
+        Self::InstructionFlash,
+        Self::StaticDataFlash,
+        Self::StaticChemDataFlash,
+        Self::AllDataFlash,
+    ];
+
+    const fn cmd(self) -> [u8; MAC_CMD_ADDR_SIZE_BYTES as usize] {
+        match self {
+            Self::InstructionFlash => INSTRUCTION_FLASH_SIG_CMD,
+            Self::StaticDataFlash => STATIC_DF_SIG_CMD,
+            Self::StaticChemDataFlash => STATIC_CHEM_DF_SIG_CMD,
+            Self::AllDataFlash => ALL_DF_SIG_CMD,
+        }
+    }
+
+    /// The instruction flash signature has no mismatch flag, so it uses all 16 bits.
+    const fn value_mask(self) -> u16 {
+        match self {
+            Self::InstructionFlash => u16::MAX,
+            _ => !SIGNATURE_MISMATCH_MASK,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Signature of every flash region, either read from a pack or recorded from a golden one
+pub struct Signatures {
+    pub instruction_flash: u16,
+    pub static_df: u16,
+    pub static_chem_df: u16,
+    pub all_df: u16,
+    /// Bit per [`SignatureRegion`] whose `SIG_MISMATCH` flag was set
+    mismatch_flags: u8,
+}
+
+impl Signatures {
+    /// Expected signatures, e.g. recorded from a golden pack.
+    #[must_use]
+    pub const fn new(instruction_flash: u16, static_df: u16, static_chem_df: u16, all_df: u16) -> Self {
+        Self {
+            instruction_flash,
+            static_df,
+            static_chem_df,
+            all_df,
+            mismatch_flags: 0,
+        }
+    }
+
+    /// Signature of `region`, without the mismatch flag.
+    #[must_use]
+    pub const fn get(&self, region: SignatureRegion) -> u16 {
+        match region {
+            SignatureRegion::InstructionFlash => self.instruction_flash,
+            SignatureRegion::StaticDataFlash => self.static_df,
+            SignatureRegion::StaticChemDataFlash => self.static_chem_df,
+            SignatureRegion::AllDataFlash => self.all_df,
+        }
+    }
+
+    /// The gauge flagged that the signature it computed for `region` does not match the stored one.
+    #[must_use]
+    pub const fn mismatch_flagged(&self, region: SignatureRegion) -> bool {
+        self.mismatch_flags & (1 << region as u8) != 0
+    }
+
+    const fn set(&mut self, region: SignatureRegion, value: u16) {
+        match region {
+            SignatureRegion::InstructionFlash => self.instruction_flash = value,
+            SignatureRegion::StaticDataFlash => self.static_df = value,
+            SignatureRegion::StaticChemDataFlash => self.static_chem_df = value,
+            SignatureRegion::AllDataFlash => self.all_df = value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Signatures read from a pack, compared against the expected ones
+pub struct SignatureReport {
+    pub actual: Signatures,
+    pub expected: Signatures,
+}
+
+impl SignatureReport {
+    /// Compare previously read signatures, e.g. logged from a field return, against the expected ones.
+    #[must_use]
+    pub const fn new(actual: Signatures, expected: Signatures) -> Self {
+        Self { actual, expected }
+    }
+
+    /// `region` differs from the expected signature, or the gauge flagged it as mismatched.
+    #[must_use]
+    pub const fn changed(&self, region: SignatureRegion) -> bool {
+        self.actual.get(region) != self.expected.get(region) || self.actual.mismatch_flagged(region)
+    }
+
+    /// Regions that changed.
+    pub fn changed_regions(&self) -> impl Iterator<Item = SignatureRegion> + '_ {
+        SignatureRegion::ALL.into_iter().filter(|&region| self.changed(region))
+    }
+
+    /// No region changed.
+    #[must_use]
+    pub fn is_intact(&self) -> bool {
+        self.changed_regions().next().is_none()
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read the signature of every flash region, waiting for the gauge to compute each one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the gauge answers
+    /// with another command.
+    pub(crate) async fn read_signatures(&mut self) -> Result<Signatures, BQ40Z50Error<I2C::Error>> {
+        let mut signatures = Signatures::new(0, 0, 0, 0);
+
+        for region in SignatureRegion::ALL {
+            let cmd = region.cmd();
+            self.mac_write_with_retries(
+                &[MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, cmd[0], cmd[1]],
+                self.config.pec_write,
+            )
+            .await?;
+            // The signature is only valid once the gauge has computed it over the whole region
+            self.delay.delay_ms(SIGNATURE_DELAY_MS).await;
+
+            // Response looks like [ Length (1 byte) | Command (2 bytes) | Signature (2 bytes) ]
+            let mut response = [0u8; 1 + MAC_CMD_ADDR_SIZE_BYTES as usize + 2];
+            self.read_with_retries(&[MAC_CMD], &mut response, self.config.pec_read)
+                .await?;
+            if response[..3] != [MAC_CMD_ADDR_SIZE_BYTES + 2, cmd[0], cmd[1]] {
+                return Err(BQ40Z50Error::UnexpectedResponse);
+            }
+
+            let value = u16::from_le_bytes([response[3], response[4]]);
+            signatures.set(region, value & region.value_mask());
+            if value & !region.value_mask() != 0 {
+                signatures.mismatch_flags |= 1 << region as u8;
+            }
+        }
+
+        Ok(signatures)
+    }
+
+    /// Read the signature of every flash region and compare them against `expected`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::UnexpectedResponse`] if the gauge answers
+    /// with another command.
+    pub(crate) async fn verify_signatures(
+        &mut self,
+        expected: Signatures,
+    ) -> Result<SignatureReport, BQ40Z50Error<I2C::Error>> {
+        Ok(SignatureReport::new(self.read_signatures().await?, expected))
+    }
+}
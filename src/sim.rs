@@ -0,0 +1,698 @@
+//! Simulated BQ40Z50 for testing drivers and applications without hardware.
+//!
+//! [`SimulatedGauge`] implements the `embedded-hal-async` I2C trait and answers bus transfers the way a gauge does:
+//!
+//! - SBS registers hold their raw little endian value, with the telemetry registers (voltage, current, state of
+//!   charge, capacities, time estimates, `BatteryStatus()`, cell voltages) computed from a [`BatteryModel`].
+//! - `ManufacturerName()`, `DeviceName()`, `DeviceChemistry()`, `ManufacturerData()` and `Authenticate()` are `SMBus`
+//!   blocks, starting with their length byte.
+//! - `ManufacturerBlockAccess()` (`0x44`) runs MAC commands, and reads and writes data flash with the address
+//!   incrementing by 32 bytes on every block read.
+//! - Writes carrying a PEC byte are checked and not acknowledged if it is wrong, and reads clocking one byte past the
+//!   response receive its PEC.
+//! - The gauge starts sealed. Data flash and MAC writes with data are only accepted once unsealed with the unseal key,
+//!   and the full access key and `MAC_SEAL` move between the security modes.
+//!
+//! ```
+//! # use bq40z50_rx::Revision;
+//! # use bq40z50_rx::sim::SimulatedGauge;
+//! let mut gauge = SimulatedGauge::new(Revision::R5);
+//! gauge.battery_mut().set_state_of_charge(80);
+//! gauge.battery_mut().current_ma = -1500;
+//! // An hour of discharge at 1.5 A
+//! gauge.step(60 * 60 * 1000);
+//! assert_eq!(gauge.battery().relative_state_of_charge(), 30);
+//! ```
+
+use core::hash::Hasher;
+use std::collections::BTreeMap;
+
+use embedded_hal_async::i2c::{ErrorKind, ErrorType, I2c as I2cTrait, NoAcknowledgeSource, Operation, SevenBitAddress};
+
+use crate::auth::{AuthDigest, AuthKey, expected_digest};
+use crate::common::Revision;
+use crate::consts::{
+    AUTH_CHALLENGE_LEN_BYTES, AUTHENTICATE_CMD, BQ_ADDR, BQ40Z50_DEVICE_TYPE, DEVICE_TYPE_CMD, DF_END_ADDR,
+    DF_START_ADDR, FIRMWARE_VERSION_CMD, FIRMWARE_VERSION_SIZE_BYTES, GAUGING_STATUS_REG, GAUGING_STATUS_SIZE_BYTES,
+    LARGEST_DF_BLOCK_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, OPERATION_STATUS_REG, OPERATION_STATUS_SEC_MASK,
+    OPERATION_STATUS_SEC_SHIFT, OPERATION_STATUS_SIZE_BYTES, PF_ALERT_REG, PF_STATUS_REG, SAFETY_ALERT_REG,
+    SAFETY_STATUS_REG, SBS_ABSOLUTE_STATE_OF_CHARGE_CMD, SBS_AVERAGE_CURRENT_CMD, SBS_AVERAGE_TIME_TO_EMPTY_CMD,
+    SBS_AVERAGE_TIME_TO_FULL_CMD, SBS_BATTERY_STATUS_CMD, SBS_CELL_COUNT, SBS_CELL_VOLTAGE_4_CMD, SBS_CURRENT_CMD,
+    SBS_CYCLE_COUNT_CMD, SBS_FULL_CHARGE_CAPACITY_CMD, SBS_RELATIVE_STATE_OF_CHARGE_CMD, SBS_REMAINING_CAPACITY_CMD,
+    SBS_RUN_TIME_TO_EMPTY_CMD, SBS_TEMPERATURE_CMD, SBS_VOLTAGE_CMD, SEAL_CMD,
+};
+use crate::security::SecurityState;
+
+const MS_PER_HOUR: i64 = 60 * 60 * 1000;
+// Time constant AverageCurrent() follows Current() with
+const AVERAGE_CURRENT_TAU_MS: i64 = 14_000;
+
+// ManufacturerAccess() takes a MAC command as a word
+const MANUFACTURER_ACCESS_REG: u8 = 0x00;
+const REMAINING_CAPACITY_ALARM_REG: u8 = 0x01;
+const DESIGN_CAPACITY_REG: u8 = 0x18;
+// ManufacturerName() to ManufacturerData() are blocks
+const FIRST_BLOCK_REG: u8 = 0x20;
+const LAST_BLOCK_REG: u8 = 0x23;
+// Word registers accepting writes
+const WRITABLE_REGS: [u8; 19] = [
+    0x01, 0x02, 0x03, 0x04, 0x17, 0x18, 0x19, 0x1A, 0x1B, 0x1C, 0x4A, 0x4B, 0x59, 0x5A, 0x5B, 0x5C, 0x5D, 0x5E, 0x5F,
+];
+
+// BatteryStatus() flags
+const BATTERY_STATUS_FD: u16 = 1 << 4;
+const BATTERY_STATUS_FC: u16 = 1 << 5;
+const BATTERY_STATUS_DSG: u16 = 1 << 6;
+const BATTERY_STATUS_INIT: u16 = 1 << 7;
+const BATTERY_STATUS_RCA: u16 = 1 << 9;
+const BATTERY_STATUS_TDA: u16 = 1 << 11;
+
+const DEFAULT_UNSEAL_KEY: (u16, u16) = (0x0414, 0x3672);
+const DEFAULT_FULL_ACCESS_KEY: (u16, u16) = (0xFFFF, 0xFFFF);
+const DEFAULT_AUTH_KEY: AuthKey = [
+    0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF, 0xFE, 0xDC, 0xBA, 0x98, 0x76, 0x54, 0x32, 0x10,
+];
+
+// Data flash as seen through ManufacturerBlockAccess()
+#[allow(clippy::cast_possible_truncation)]
+const DF_START: u16 = DF_START_ADDR as u16;
+#[allow(clippy::cast_possible_truncation)]
+const DF_SIZE_BYTES: usize = (DF_END_ADDR - DF_START_ADDR + 1) as usize;
+
+const NACK_DATA: ErrorKind = ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Battery pack behind a [`SimulatedGauge`]
+///
+/// Charge is counted from `current_ma` every time the model is stepped. The open circuit cell voltage rises linearly
+/// from `empty_cell_mv` to `full_cell_mv` with the state of charge, and the current drops across `resistance_mohm`.
+pub struct BatteryModel {
+    /// Number of cells in series, 1 to 4
+    pub cells: u8,
+    pub design_capacity_mah: u16,
+    pub full_charge_capacity_mah: u16,
+    pub empty_cell_mv: u16,
+    pub full_cell_mv: u16,
+    /// Pack resistance, in mΩ
+    pub resistance_mohm: u16,
+    /// Pack current, positive while charging
+    pub current_ma: i16,
+    /// Pack temperature, in 0.1 K
+    pub temperature_dk: u16,
+    pub cycle_count: u16,
+    average_current_ma: i16,
+    charge_ma_ms: i64,
+    // Charge discharged since the cycle count was last incremented
+    discharged_ma_ms: i64,
+}
+
+impl BatteryModel {
+    /// A 3-cell 3000 mAh pack at rest, half charged, at 25 °C.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            cells: 3,
+            design_capacity_mah: 3000,
+            full_charge_capacity_mah: 3000,
+            empty_cell_mv: 3000,
+            full_cell_mv: 4200,
+            resistance_mohm: 100,
+            current_ma: 0,
+            temperature_dk: 2982,
+            cycle_count: 0,
+            average_current_ma: 0,
+            charge_ma_ms: 1500 * MS_PER_HOUR,
+            discharged_ma_ms: 0,
+        }
+    }
+
+    /// Set the remaining capacity to `percent` of the full charge capacity, capped at 100%.
+    pub fn set_state_of_charge(&mut self, percent: u8) {
+        self.charge_ma_ms = self.full_charge_ma_ms() * i64::from(percent.min(100)) / 100;
+    }
+
+    /// Advance the model by `elapsed_ms`, counting the charge moved by `current_ma`.
+    ///
+    /// The charge stays between empty and the full charge capacity, and the cycle count increments every time a full
+    /// charge capacity worth of charge has been discharged.
+    pub fn step(&mut self, elapsed_ms: u32) {
+        let elapsed_ms = i64::from(elapsed_ms);
+        let full = self.full_charge_ma_ms();
+        let charge = (self.charge_ma_ms + i64::from(self.current_ma) * elapsed_ms).clamp(0, full);
+
+        if charge < self.charge_ma_ms {
+            self.discharged_ma_ms += self.charge_ma_ms - charge;
+            while full > 0 && self.discharged_ma_ms >= full {
+                self.discharged_ma_ms -= full;
+                self.cycle_count = self.cycle_count.saturating_add(1);
+            }
+        }
+        self.charge_ma_ms = charge;
+
+        let average = i64::from(self.average_current_ma);
+        let average =
+            average + (i64::from(self.current_ma) - average) * elapsed_ms / (elapsed_ms + AVERAGE_CURRENT_TAU_MS);
+        // The average lies between two i16 values, so the fallback is never taken
+        self.average_current_ma = average.try_into().unwrap_or(self.current_ma);
+    }
+
+    #[must_use]
+    pub fn remaining_capacity_mah(&self) -> u16 {
+        u16::try_from(self.charge_ma_ms / MS_PER_HOUR).unwrap_or(u16::MAX)
+    }
+
+    /// Remaining capacity in percent of the full charge capacity.
+    #[must_use]
+    pub fn relative_state_of_charge(&self) -> u8 {
+        Self::percent(self.charge_ma_ms, self.full_charge_ma_ms())
+    }
+
+    /// Remaining capacity in percent of the design capacity.
+    #[must_use]
+    pub fn absolute_state_of_charge(&self) -> u8 {
+        Self::percent(
+            self.charge_ma_ms,
+            i64::from(self.design_capacity_mah) * MS_PER_HOUR,
+        )
+    }
+
+    /// Current filtered with a 14 second time constant.
+    #[must_use]
+    pub const fn average_current_ma(&self) -> i16 {
+        self.average_current_ma
+    }
+
+    /// Voltage of each cell under the present current.
+    #[must_use]
+    pub fn cell_voltage_mv(&self) -> u16 {
+        let full = self.full_charge_ma_ms();
+        let span = i64::from(self.full_cell_mv) - i64::from(self.empty_cell_mv);
+        let open_circuit = i64::from(self.empty_cell_mv) + if full > 0 { span * self.charge_ma_ms / full } else { 0 };
+        let drop = i64::from(self.current_ma) * i64::from(self.resistance_mohm) / 1000 / i64::from(self.cells.max(1));
+        u16::try_from((open_circuit + drop).max(0)).unwrap_or(u16::MAX)
+    }
+
+    #[must_use]
+    pub fn voltage_mv(&self) -> u16 {
+        self.cell_voltage_mv().saturating_mul(u16::from(self.cells))
+    }
+
+    /// Minutes until empty at `current_ma`, or `u16::MAX` while not discharging.
+    #[must_use]
+    pub fn time_to_empty_min(&self, current_ma: i16) -> u16 {
+        if current_ma >= 0 {
+            return u16::MAX;
+        }
+        Self::minutes(self.charge_ma_ms, current_ma)
+    }
+
+    /// Minutes until full at `current_ma`, or `u16::MAX` while not charging.
+    #[must_use]
+    pub fn time_to_full_min(&self, current_ma: i16) -> u16 {
+        if current_ma <= 0 {
+            return u16::MAX;
+        }
+        Self::minutes(self.full_charge_ma_ms() - self.charge_ma_ms, current_ma)
+    }
+
+    fn full_charge_ma_ms(&self) -> i64 {
+        i64::from(self.full_charge_capacity_mah) * MS_PER_HOUR
+    }
+
+    fn percent(charge_ma_ms: i64, full_ma_ms: i64) -> u8 {
+        if full_ma_ms <= 0 {
+            return 0;
+        }
+        u8::try_from((charge_ma_ms * 100 / full_ma_ms).clamp(0, 100)).unwrap_or(100)
+    }
+
+    fn minutes(charge_ma_ms: i64, current_ma: i16) -> u16 {
+        let minutes = charge_ma_ms / (i64::from(current_ma).abs() * 60 * 1000);
+        // u16::MAX is reserved for "not applicable"
+        u16::try_from(minutes).unwrap_or(u16::MAX - 1).min(u16::MAX - 1)
+    }
+}
+
+impl Default for BatteryModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MacRead {
+    Command(u16),
+    DataFlash(u16),
+}
+
+/// Simulated BQ40Z50 on an I2C bus.
+///
+/// Errors are reported as [`ErrorKind::NoAcknowledge`]: for the address when a transfer targets another address, and
+/// for data when the gauge rejects a transfer (unknown register, bad PEC, or an access the security mode forbids).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulatedGauge {
+    address: u8,
+    battery: BatteryModel,
+    registers: BTreeMap<u8, Vec<u8>>,
+    mac_responses: BTreeMap<u16, Vec<u8>>,
+    mac_log: Vec<u16>,
+    data_flash: Vec<u8>,
+    security: SecurityState,
+    unseal_key: (u16, u16),
+    full_access_key: (u16, u16),
+    auth_key: AuthKey,
+    auth_digest: Option<AuthDigest>,
+    last_key: Option<u16>,
+    mac_read: Option<MacRead>,
+}
+
+impl SimulatedGauge {
+    /// A sealed gauge at the Smart Battery address identifying as `revision`, with erased data flash and the TI
+    /// default unseal (`0x0414`, `0x3672`), full access (`0xFFFF`, `0xFFFF`) and authentication keys.
+    #[must_use]
+    pub fn new(revision: Revision) -> Self {
+        let battery = BatteryModel::new();
+
+        let mut registers = BTreeMap::new();
+        for (reg, value) in [
+            (REMAINING_CAPACITY_ALARM_REG, 300u16),
+            (0x02, 10),
+            (0x03, 0),
+            (0x04, 0),
+            (0x05, u16::MAX),
+            (0x06, u16::MAX),
+            (0x07, 1),
+            (0x0C, 1),
+            (0x14, 2000),
+            (0x15, u16::from(battery.cells) * battery.full_cell_mv),
+            (0x19, u16::from(battery.cells) * 3600),
+            (0x1A, 0x0031),
+            (0x1B, 0),
+            (0x1C, 0x0001),
+            (0x57, 0),
+        ] {
+            registers.insert(reg, value.to_le_bytes().to_vec());
+        }
+        for reg in [SAFETY_ALERT_REG, SAFETY_STATUS_REG, PF_ALERT_REG, PF_STATUS_REG] {
+            registers.insert(reg, vec![0; 4]);
+        }
+        registers.insert(OPERATION_STATUS_REG, vec![0; OPERATION_STATUS_SIZE_BYTES]);
+        registers.insert(GAUGING_STATUS_REG, vec![0; GAUGING_STATUS_SIZE_BYTES]);
+        registers.insert(FIRST_BLOCK_REG, b"Texas Inst.".to_vec());
+        registers.insert(FIRST_BLOCK_REG + 1, b"bq40z50".to_vec());
+        registers.insert(FIRST_BLOCK_REG + 2, b"LION".to_vec());
+        registers.insert(LAST_BLOCK_REG, vec![0; 14]);
+
+        let mut mac_responses = BTreeMap::new();
+        mac_responses.insert(
+            u16::from_le_bytes(DEVICE_TYPE_CMD),
+            BQ40Z50_DEVICE_TYPE.to_le_bytes().to_vec(),
+        );
+        // Device number and version are big endian words in the firmware version block
+        let mut firmware_version = vec![0; FIRMWARE_VERSION_SIZE_BYTES];
+        firmware_version[..2].copy_from_slice(&BQ40Z50_DEVICE_TYPE.to_be_bytes());
+        firmware_version[2] = revision.major_version();
+        firmware_version[3] = 0x01;
+        mac_responses.insert(u16::from_le_bytes(FIRMWARE_VERSION_CMD), firmware_version);
+
+        Self {
+            address: BQ_ADDR,
+            battery,
+            registers,
+            mac_responses,
+            mac_log: Vec::new(),
+            data_flash: vec![0; DF_SIZE_BYTES],
+            security: SecurityState::Sealed,
+            unseal_key: DEFAULT_UNSEAL_KEY,
+            full_access_key: DEFAULT_FULL_ACCESS_KEY,
+            auth_key: DEFAULT_AUTH_KEY,
+            auth_digest: None,
+            last_key: None,
+            mac_read: None,
+        }
+    }
+
+    /// Answer on `address` instead of the Smart Battery address.
+    #[must_use]
+    pub fn with_address(mut self, address: u8) -> Self {
+        self.address = address;
+        self
+    }
+
+    #[must_use]
+    pub const fn battery(&self) -> &BatteryModel {
+        &self.battery
+    }
+
+    pub fn battery_mut(&mut self) -> &mut BatteryModel {
+        &mut self.battery
+    }
+
+    /// Advance the battery model by `elapsed_ms`.
+    pub fn step(&mut self, elapsed_ms: u32) {
+        self.battery.step(elapsed_ms);
+    }
+
+    #[must_use]
+    pub const fn security_state(&self) -> SecurityState {
+        self.security
+    }
+
+    pub fn set_security_state(&mut self, state: SecurityState) {
+        self.security = state;
+    }
+
+    /// Keys expected, lower word first, by [`unseal`](crate::Bq40z50::unseal) and
+    /// [`enter_full_access`](crate::Bq40z50::enter_full_access).
+    pub fn set_keys(&mut self, unseal: (u16, u16), full_access: (u16, u16)) {
+        self.unseal_key = unseal;
+        self.full_access_key = full_access;
+    }
+
+    /// Key the `Authenticate()` digest is computed with.
+    pub fn set_auth_key(&mut self, key: AuthKey) {
+        self.auth_key = key;
+    }
+
+    /// Raw value of the register at `reg`, without the length byte of blocks.
+    ///
+    /// Registers computed from the battery model are not stored and return `None`.
+    #[must_use]
+    pub fn register(&self, reg: u8) -> Option<&[u8]> {
+        self.registers.get(&reg).map(Vec::as_slice)
+    }
+
+    /// Set the raw value of the register at `reg`, e.g. to raise flags in `SafetyAlert()` or `PFStatus()`.
+    ///
+    /// The `SEC` bits of `OperationStatus()` always reflect the simulated security mode.
+    pub fn set_register(&mut self, reg: u8, value: &[u8]) {
+        self.registers.insert(reg, value.to_vec());
+    }
+
+    /// Set the data returned by the MAC command `cmd`, without the length byte and command echo.
+    pub fn set_mac_response(&mut self, cmd: u16, data: &[u8]) {
+        self.mac_responses.insert(cmd, data.to_vec());
+    }
+
+    /// Every MAC command received, in order, including the words of security keys.
+    #[must_use]
+    pub fn mac_log(&self) -> &[u16] {
+        &self.mac_log
+    }
+
+    /// `len` bytes of data flash starting at `address`, or `None` if they are outside data flash.
+    #[must_use]
+    pub fn data_flash(&self, address: u16, len: usize) -> Option<&[u8]> {
+        let start = Self::df_offset(address)?;
+        self.data_flash.get(start..start + len)
+    }
+
+    /// Program data flash directly, regardless of the security mode.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` does not fit in data flash starting at `address`.
+    pub fn set_data_flash(&mut self, address: u16, data: &[u8]) {
+        let start = Self::df_offset(address).expect("address outside data flash");
+        self.data_flash[start..start + data.len()].copy_from_slice(data);
+    }
+
+    fn df_offset(address: u16) -> Option<usize> {
+        let offset = usize::from(address.checked_sub(DF_START)?);
+        (offset < DF_SIZE_BYTES).then_some(offset)
+    }
+
+    fn pec(&self, write: &[u8], read: Option<&[u8]>) -> u8 {
+        let mut pec = smbus_pec::Pec::new();
+        pec.write_u8(self.address << 1);
+        pec.write(write);
+        if let Some(read) = read {
+            pec.write_u8(self.address << 1 | 0x01);
+            pec.write(read);
+        }
+        // Infallible because the underlying crate is guaranteed to return a u8
+        pec.finish().try_into().unwrap()
+    }
+
+    /// Check the length of a write and the PEC byte following the first `len` bytes, if there is one.
+    fn check_write(&self, write: &[u8], len: usize) -> Result<(), ErrorKind> {
+        match write.len().checked_sub(len) {
+            Some(0) => Ok(()),
+            Some(1) if write[len] == self.pec(&write[..len], None) => Ok(()),
+            _ => Err(NACK_DATA),
+        }
+    }
+
+    fn write(&mut self, write: &[u8]) -> Result<(), ErrorKind> {
+        let Some(&reg) = write.first() else {
+            // Quick command
+            return Ok(());
+        };
+
+        match reg {
+            MAC_CMD | AUTHENTICATE_CMD => {
+                let len = usize::from(*write.get(1).ok_or(NACK_DATA)?);
+                self.check_write(write, 2 + len)?;
+                let payload = &write[2..2 + len];
+                if reg == AUTHENTICATE_CMD {
+                    let challenge = payload.try_into().map_err(|_| NACK_DATA)?;
+                    self.auth_digest = Some(expected_digest(&self.auth_key, challenge));
+                    return Ok(());
+                }
+                if payload.len() < usize::from(MAC_CMD_ADDR_SIZE_BYTES) {
+                    return Err(NACK_DATA);
+                }
+                let cmd = u16::from_le_bytes([payload[0], payload[1]]);
+                self.mac_write(cmd, &payload[2..])
+            }
+            MANUFACTURER_ACCESS_REG => {
+                self.check_write(write, 3)?;
+                self.mac_write(u16::from_le_bytes([write[1], write[2]]), &[])
+            }
+            _ if WRITABLE_REGS.contains(&reg) => {
+                self.check_write(write, 3)?;
+                let value = u16::from_le_bytes([write[1], write[2]]);
+                match reg {
+                    SBS_CYCLE_COUNT_CMD => self.battery.cycle_count = value,
+                    DESIGN_CAPACITY_REG => self.battery.design_capacity_mah = value,
+                    _ => {
+                        self.registers.insert(reg, write[1..3].to_vec());
+                    }
+                }
+                Ok(())
+            }
+            _ => Err(NACK_DATA),
+        }
+    }
+
+    fn mac_write(&mut self, cmd: u16, data: &[u8]) -> Result<(), ErrorKind> {
+        if Self::df_offset(cmd).is_some() {
+            if self.security == SecurityState::Sealed {
+                return Err(NACK_DATA);
+            }
+            if data.is_empty() {
+                self.mac_read = Some(MacRead::DataFlash(cmd));
+            } else {
+                let start = Self::df_offset(cmd).unwrap();
+                let end = start + data.len();
+                self.data_flash.get_mut(start..end).ok_or(NACK_DATA)?.copy_from_slice(data);
+            }
+            return Ok(());
+        }
+
+        self.mac_log.push(cmd);
+        if !data.is_empty() {
+            if self.security == SecurityState::Sealed {
+                return Err(NACK_DATA);
+            }
+            self.mac_responses.insert(cmd, data.to_vec());
+            return Ok(());
+        }
+
+        // Keys are two consecutive words, and only move one step up from the current security mode
+        let previous = self.last_key.replace(cmd);
+        match self.security {
+            SecurityState::Sealed if (previous, cmd) == (Some(self.unseal_key.0), self.unseal_key.1) => {
+                self.security = SecurityState::Unsealed;
+                self.last_key = None;
+            }
+            SecurityState::Unsealed if (previous, cmd) == (Some(self.full_access_key.0), self.full_access_key.1) => {
+                self.security = SecurityState::FullAccess;
+                self.last_key = None;
+            }
+            _ if cmd == u16::from_le_bytes(SEAL_CMD) => self.security = SecurityState::Sealed,
+            _ => (),
+        }
+        self.mac_read = Some(MacRead::Command(cmd));
+
+        Ok(())
+    }
+
+    fn mac_read(&mut self) -> Result<Vec<u8>, ErrorKind> {
+        let (cmd, data) = match self.mac_read.ok_or(NACK_DATA)? {
+            MacRead::Command(cmd) => (cmd, self.mac_responses.get(&cmd).cloned().unwrap_or_default()),
+            MacRead::DataFlash(address) => {
+                if self.security == SecurityState::Sealed {
+                    return Err(NACK_DATA);
+                }
+                let start = Self::df_offset(address).ok_or(NACK_DATA)?;
+                let end = core::cmp::min(start + LARGEST_DF_BLOCK_SIZE_BYTES, self.data_flash.len());
+                // The next block read continues 32 bytes further
+                self.mac_read = u16::try_from(LARGEST_DF_BLOCK_SIZE_BYTES)
+                    .ok()
+                    .and_then(|step| address.checked_add(step))
+                    .map(MacRead::DataFlash);
+                (address, self.data_flash[start..end].to_vec())
+            }
+        };
+
+        // [ Length | Command (2 bytes) | Data ]
+        let mut response = vec![u8::try_from(usize::from(MAC_CMD_ADDR_SIZE_BYTES) + data.len()).map_err(|_| NACK_DATA)?];
+        response.extend_from_slice(&cmd.to_le_bytes());
+        response.extend_from_slice(&data);
+        Ok(response)
+    }
+
+    fn word(value: u16) -> Vec<u8> {
+        value.to_le_bytes().to_vec()
+    }
+
+    fn battery_status(&self) -> u16 {
+        let battery = &self.battery;
+        let rsoc = battery.relative_state_of_charge();
+        let alarm = self
+            .registers
+            .get(&REMAINING_CAPACITY_ALARM_REG)
+            .map_or(0, |alarm| u16::from_le_bytes([alarm[0], alarm[1]]));
+
+        let mut status = BATTERY_STATUS_INIT;
+        if battery.current_ma <= 0 {
+            status |= BATTERY_STATUS_DSG;
+            if battery.remaining_capacity_mah() < alarm {
+                status |= BATTERY_STATUS_RCA;
+            }
+        }
+        if rsoc == 100 {
+            status |= BATTERY_STATUS_FC;
+        }
+        if rsoc == 0 {
+            status |= BATTERY_STATUS_FD | BATTERY_STATUS_TDA;
+        }
+        status
+    }
+
+    fn read(&mut self, command: &[u8]) -> Result<Vec<u8>, ErrorKind> {
+        let [reg] = *command else {
+            return Err(NACK_DATA);
+        };
+        let battery = &self.battery;
+
+        // Casts of the signed current reinterpret its two's complement bits, as the gauge reports them
+        #[allow(clippy::cast_sign_loss)]
+        Ok(match reg {
+            MAC_CMD => return self.mac_read(),
+            AUTHENTICATE_CMD => {
+                let digest = self.auth_digest.ok_or(NACK_DATA)?;
+                let mut response = vec![AUTH_CHALLENGE_LEN_BYTES];
+                response.extend_from_slice(&digest);
+                response
+            }
+            FIRST_BLOCK_REG..=LAST_BLOCK_REG => {
+                let data = self.registers.get(&reg).ok_or(NACK_DATA)?;
+                let mut response = vec![u8::try_from(data.len()).map_err(|_| NACK_DATA)?];
+                response.extend_from_slice(data);
+                response
+            }
+            SBS_TEMPERATURE_CMD => Self::word(battery.temperature_dk),
+            SBS_VOLTAGE_CMD => Self::word(battery.voltage_mv()),
+            SBS_CURRENT_CMD => Self::word(battery.current_ma as u16),
+            SBS_AVERAGE_CURRENT_CMD => Self::word(battery.average_current_ma() as u16),
+            SBS_RELATIVE_STATE_OF_CHARGE_CMD => Self::word(battery.relative_state_of_charge().into()),
+            SBS_ABSOLUTE_STATE_OF_CHARGE_CMD => Self::word(battery.absolute_state_of_charge().into()),
+            SBS_REMAINING_CAPACITY_CMD => Self::word(battery.remaining_capacity_mah()),
+            SBS_FULL_CHARGE_CAPACITY_CMD => Self::word(battery.full_charge_capacity_mah),
+            SBS_RUN_TIME_TO_EMPTY_CMD => Self::word(battery.time_to_empty_min(battery.current_ma)),
+            SBS_AVERAGE_TIME_TO_EMPTY_CMD => Self::word(battery.time_to_empty_min(battery.average_current_ma())),
+            SBS_AVERAGE_TIME_TO_FULL_CMD => Self::word(battery.time_to_full_min(battery.average_current_ma())),
+            SBS_BATTERY_STATUS_CMD => Self::word(self.battery_status()),
+            SBS_CYCLE_COUNT_CMD => Self::word(battery.cycle_count),
+            DESIGN_CAPACITY_REG => Self::word(battery.design_capacity_mah),
+            // CellVoltage4() counts up to CellVoltage1()
+            #[allow(clippy::cast_possible_truncation)]
+            _ if (SBS_CELL_VOLTAGE_4_CMD..SBS_CELL_VOLTAGE_4_CMD + SBS_CELL_COUNT as u8).contains(&reg) => {
+                let cell = SBS_CELL_COUNT as u8 - (reg - SBS_CELL_VOLTAGE_4_CMD);
+                Self::word(if cell <= battery.cells {
+                    battery.cell_voltage_mv()
+                } else {
+                    0
+                })
+            }
+            OPERATION_STATUS_REG => {
+                let stored = self.registers.get(&reg).ok_or(NACK_DATA)?;
+                let mut status = [0u8; OPERATION_STATUS_SIZE_BYTES];
+                status.copy_from_slice(stored);
+                let sec = match self.security {
+                    SecurityState::FullAccess => 1,
+                    SecurityState::Unsealed => 2,
+                    SecurityState::Sealed => 3,
+                };
+                let status = u32::from_le_bytes(status) & !(OPERATION_STATUS_SEC_MASK << OPERATION_STATUS_SEC_SHIFT)
+                    | sec << OPERATION_STATUS_SEC_SHIFT;
+                status.to_le_bytes().to_vec()
+            }
+            _ => self.registers.get(&reg).ok_or(NACK_DATA)?.clone(),
+        })
+    }
+
+    /// Fill `read` with `response`, followed by its PEC and then `0xFF` if the master keeps clocking.
+    fn fill(&self, command: &[u8], response: &[u8], read: &mut [u8]) {
+        let pec = self.pec(command, Some(response));
+        for (i, byte) in read.iter_mut().enumerate() {
+            *byte = match i.cmp(&response.len()) {
+                core::cmp::Ordering::Less => response[i],
+                core::cmp::Ordering::Equal => pec,
+                core::cmp::Ordering::Greater => 0xFF,
+            };
+        }
+    }
+}
+
+impl ErrorType for SimulatedGauge {
+    type Error = ErrorKind;
+}
+
+impl I2cTrait for SimulatedGauge {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+
+        // Adjacent writes are one transfer. A transfer followed by a read selects what is read, anything else is a
+        // complete write.
+        let mut command = Vec::new();
+        for i in 0..operations.len() {
+            let read_follows = matches!(operations.get(i + 1), Some(Operation::Read(_)));
+            let write_follows = matches!(operations.get(i + 1), Some(Operation::Write(_)));
+            match &mut operations[i] {
+                Operation::Write(bytes) => {
+                    command.extend_from_slice(bytes);
+                    if !read_follows && !write_follows {
+                        self.write(&command)?;
+                        command.clear();
+                    }
+                }
+                Operation::Read(read) => {
+                    let response = self.read(&command)?;
+                    self.fill(&command, &response, read);
+                    command.clear();
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+//! Telemetry snapshot batching the standard SBS reads a status page or polling task needs.
+
+use core::ops::{BitOr, BitOrAssign};
+
+use embedded_batteries_async::smart_battery::{
+    BatteryStatusFields, CapacityModeValue, Cycles, DeciKelvin, MilliAmpsSigned, MilliVolts, Minutes, Percent,
+};
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::common::CapacityModeState;
+use crate::consts::{
+    SBS_ABSOLUTE_STATE_OF_CHARGE_CMD, SBS_AVERAGE_CURRENT_CMD, SBS_AVERAGE_TIME_TO_EMPTY_CMD,
+    SBS_AVERAGE_TIME_TO_FULL_CMD, SBS_BATTERY_STATUS_CMD, SBS_CELL_COUNT, SBS_CELL_VOLTAGE_4_CMD, SBS_CURRENT_CMD,
+    SBS_CYCLE_COUNT_CMD, SBS_FULL_CHARGE_CAPACITY_CMD, SBS_RELATIVE_STATE_OF_CHARGE_CMD, SBS_REMAINING_CAPACITY_CMD,
+    SBS_RUN_TIME_TO_EMPTY_CMD, SBS_TEMPERATURE_CMD, SBS_VOLTAGE_CMD,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Set of fields read by [`read_snapshot`](crate::Bq40z50::read_snapshot), combined with `|`
+pub struct SnapshotFields(u32);
+
+impl SnapshotFields {
+    /// No fields, only the timestamps are taken
+    pub const NONE: Self = Self(0);
+    /// `Voltage()`
+    pub const VOLTAGE: Self = Self(1 << 0);
+    /// `Current()`
+    pub const CURRENT: Self = Self(1 << 1);
+    /// `AverageCurrent()`
+    pub const AVERAGE_CURRENT: Self = Self(1 << 2);
+    /// `Temperature()`
+    pub const TEMPERATURE: Self = Self(1 << 3);
+    /// `RelativeStateOfCharge()`
+    pub const RELATIVE_STATE_OF_CHARGE: Self = Self(1 << 4);
+    /// `AbsoluteStateOfCharge()`
+    pub const ABSOLUTE_STATE_OF_CHARGE: Self = Self(1 << 5);
+    /// `RemainingCapacity()`
+    pub const REMAINING_CAPACITY: Self = Self(1 << 6);
+    /// `FullChargeCapacity()`
+    pub const FULL_CHARGE_CAPACITY: Self = Self(1 << 7);
+    /// `RunTimeToEmpty()`, `AverageTimeToEmpty()` and `AverageTimeToFull()`
+    pub const TIME_ESTIMATES: Self = Self(1 << 8);
+    /// `BatteryStatus()`
+    pub const BATTERY_STATUS: Self = Self(1 << 9);
+    /// `CycleCount()`
+    pub const CYCLE_COUNT: Self = Self(1 << 10);
+    /// `CellVoltage1()` to `CellVoltage4()`
+    pub const CELL_VOLTAGES: Self = Self(1 << 11);
+    /// Every field of a [`Snapshot`]
+    pub const ALL: Self = Self((1 << 12) - 1);
+
+    /// Check whether every field of `other` is part of this set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Combine two sets of fields.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl Default for SnapshotFields {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+impl BitOr for SnapshotFields {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for SnapshotFields {
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Decoded telemetry read in one [`read_snapshot`](crate::Bq40z50::read_snapshot) call.
+///
+/// Fields outside the requested [`SnapshotFields`] are `None`.
+pub struct Snapshot {
+    /// Timestamp taken before the first read
+    pub started_at: u64,
+    /// Timestamp taken after the last read
+    pub finished_at: u64,
+    pub voltage: Option<MilliVolts>,
+    pub current: Option<MilliAmpsSigned>,
+    pub average_current: Option<MilliAmpsSigned>,
+    pub temperature: Option<DeciKelvin>,
+    pub relative_state_of_charge: Option<Percent>,
+    pub absolute_state_of_charge: Option<Percent>,
+    /// In the same units as [`full_charge_capacity`](Self::full_charge_capacity)
+    pub remaining_capacity: Option<CapacityModeValue>,
+    /// In the same units as [`remaining_capacity`](Self::remaining_capacity)
+    pub full_charge_capacity: Option<CapacityModeValue>,
+    pub run_time_to_empty: Option<Minutes>,
+    pub average_time_to_empty: Option<Minutes>,
+    pub average_time_to_full: Option<Minutes>,
+    /// Reported as read, including a non-zero error code
+    pub battery_status: Option<BatteryStatusFields>,
+    pub cycle_count: Option<Cycles>,
+    /// Cell 1 to cell 4
+    pub cell_voltages: Option<[MilliVolts; SBS_CELL_COUNT]>,
+}
+
+impl Snapshot {
+    /// Time spent reading the snapshot, in the units of the timestamp hook.
+    #[must_use]
+    pub const fn bus_time(&self) -> u64 {
+        self.finished_at.saturating_sub(self.started_at)
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read the requested `fields`, one `SMBus` word read each, with the capacities in the units of `capacity_mode`.
+    ///
+    /// `timestamp` is called once before the first and once after the last read.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub(crate) async fn read_snapshot(
+        &mut self,
+        fields: SnapshotFields,
+        capacity_mode: CapacityModeState,
+        mut timestamp: impl FnMut() -> u64,
+    ) -> Result<Snapshot, BQ40Z50Error<I2C::Error>> {
+        let mut snapshot = Snapshot {
+            started_at: timestamp(),
+            ..Snapshot::default()
+        };
+        let capacity = |value| match capacity_mode {
+            CapacityModeState::Milliamps => CapacityModeValue::MilliAmpUnsigned(value),
+            CapacityModeState::Centiwatt => CapacityModeValue::CentiWattUnsigned(value),
+        };
+
+        if fields.contains(SnapshotFields::VOLTAGE) {
+            snapshot.voltage = Some(self.read_word(SBS_VOLTAGE_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::CURRENT) {
+            snapshot.current = Some(self.read_signed_word(SBS_CURRENT_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::AVERAGE_CURRENT) {
+            snapshot.average_current = Some(self.read_signed_word(SBS_AVERAGE_CURRENT_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::TEMPERATURE) {
+            snapshot.temperature = Some(self.read_word(SBS_TEMPERATURE_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::RELATIVE_STATE_OF_CHARGE) {
+            snapshot.relative_state_of_charge = Some(self.read_percent(SBS_RELATIVE_STATE_OF_CHARGE_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::ABSOLUTE_STATE_OF_CHARGE) {
+            snapshot.absolute_state_of_charge = Some(self.read_percent(SBS_ABSOLUTE_STATE_OF_CHARGE_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::REMAINING_CAPACITY) {
+            snapshot.remaining_capacity = Some(capacity(self.read_word(SBS_REMAINING_CAPACITY_CMD).await?));
+        }
+        if fields.contains(SnapshotFields::FULL_CHARGE_CAPACITY) {
+            snapshot.full_charge_capacity = Some(capacity(self.read_word(SBS_FULL_CHARGE_CAPACITY_CMD).await?));
+        }
+        if fields.contains(SnapshotFields::TIME_ESTIMATES) {
+            snapshot.run_time_to_empty = Some(self.read_word(SBS_RUN_TIME_TO_EMPTY_CMD).await?);
+            snapshot.average_time_to_empty = Some(self.read_word(SBS_AVERAGE_TIME_TO_EMPTY_CMD).await?);
+            snapshot.average_time_to_full = Some(self.read_word(SBS_AVERAGE_TIME_TO_FULL_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::BATTERY_STATUS) {
+            snapshot.battery_status = Some(self.read_word(SBS_BATTERY_STATUS_CMD).await?.into());
+        }
+        if fields.contains(SnapshotFields::CYCLE_COUNT) {
+            snapshot.cycle_count = Some(self.read_word(SBS_CYCLE_COUNT_CMD).await?);
+        }
+        if fields.contains(SnapshotFields::CELL_VOLTAGES) {
+            let mut cells = [0; SBS_CELL_COUNT];
+            // The cell voltage commands count down from cell 4
+            for (cell, cmd) in cells.iter_mut().rev().zip(SBS_CELL_VOLTAGE_4_CMD..) {
+                *cell = self.read_word(cmd).await?;
+            }
+            snapshot.cell_voltages = Some(cells);
+        }
+
+        snapshot.finished_at = timestamp();
+        Ok(snapshot)
+    }
+
+    async fn read_word(&mut self, cmd: u8) -> Result<u16, BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; 2];
+        self.read_with_retries(&[cmd], &mut buf, self.config.pec_read).await?;
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    async fn read_signed_word(&mut self, cmd: u8) -> Result<i16, BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; 2];
+        self.read_with_retries(&[cmd], &mut buf, self.config.pec_read).await?;
+        Ok(i16::from_le_bytes(buf))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    async fn read_percent(&mut self, cmd: u8) -> Result<Percent, BQ40Z50Error<I2C::Error>> {
+        // The gauge answers state of charge commands with a word, the range is 0 - 100 so it never exceeds 1 byte.
+        Ok(self.read_word(cmd).await? as u8)
+    }
+}
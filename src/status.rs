@@ -0,0 +1,537 @@
+use core::fmt;
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::consts::{
+    OPERATION_STATUS_REG, PF_ALERT_REG, PF_STATUS_REG, SAFETY_ALERT_REG, SAFETY_STATUS_REG, STATUS_REG_SIZE_BYTES,
+};
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// 32-bit status register, at the same command on every revision
+pub enum StatusRegister {
+    /// `SafetyAlert()`, protections that are about to trip
+    SafetyAlert,
+    /// `SafetyStatus()`, protections that have tripped
+    SafetyStatus,
+    /// `PFAlert()`, permanent failures that are about to latch
+    PfAlert,
+    /// `PFStatus()`, latched permanent failures
+    PfStatus,
+    /// `OperationStatus()`
+    OperationStatus,
+}
+
+impl StatusRegister {
+    /// SBS command of the register.
+    #[must_use]
+    pub const fn command(self) -> u8 {
+        match self {
+            Self::SafetyAlert => SAFETY_ALERT_REG,
+            Self::SafetyStatus => SAFETY_STATUS_REG,
+            Self::PfAlert => PF_ALERT_REG,
+            Self::PfStatus => PF_STATUS_REG,
+            Self::OperationStatus => OPERATION_STATUS_REG,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// How bad an active flag is, depending on the register it was read from
+pub enum Severity {
+    /// `SafetyAlert()` or `PFAlert()`, the condition is detected but has not tripped yet
+    Alert,
+    /// `SafetyStatus()`, the protection has tripped and recovers on its own
+    Protection,
+    /// `PFStatus()`, the permanent failure has latched
+    PermanentFailure,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Condition that clears a tripped flag, as listed in the TRM protection descriptions
+pub enum Recovery {
+    /// The measured voltage, current or temperature returns past its recovery threshold
+    Threshold,
+    /// The protection recovers automatically once its recovery time has elapsed
+    Timer,
+    /// The protection latched after repeated trips and clears once the latch reset time has elapsed
+    Latch,
+    /// The pack is discharged or the charger is removed
+    Discharge,
+    /// The charger is removed
+    ChargerRemoval,
+    /// Never recovers, the permanent failure data has to be cleared in full access
+    Permanent,
+}
+
+impl Recovery {
+    /// Description of the recovery condition.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Threshold => "clears when the measurement returns past its recovery threshold",
+            Self::Timer => "clears after the recovery time",
+            Self::Latch => "clears after the latch reset time",
+            Self::Discharge => "clears when the pack is discharged or the charger is removed",
+            Self::ChargerRemoval => "clears when the charger is removed",
+            Self::Permanent => "never clears, the permanent failure must be reset in full access",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Bit of `SafetyAlert()`, `SafetyStatus()`, `PFAlert()` or `PFStatus()`.
+///
+/// The bit layouts are the same on R1, R3, R4 and R5.
+pub enum StatusFlag {
+    /// Cell Undervoltage
+    Cuv,
+    /// Cell Overvoltage
+    Cov,
+    /// Overcurrent in Charge 1st Tier
+    Occ1,
+    /// Overcurrent in Charge 2nd Tier
+    Occ2,
+    /// Overcurrent in Discharge 1st Tier
+    Ocd1,
+    /// Overcurrent in Discharge 2nd Tier
+    Ocd2,
+    /// Overload in Discharge
+    Aold,
+    /// Overload in Discharge Latch
+    Aoldl,
+    /// Short Circuit in Charge
+    Ascc,
+    /// Short Circuit in Charge Latch
+    Asccl,
+    /// Short Circuit in Discharge
+    Ascd,
+    /// Short Circuit in Discharge Latch
+    Ascdl,
+    /// Overtemperature in Charge
+    Otc,
+    /// Overtemperature in Discharge
+    Otd,
+    /// Cell Undervoltage Compensated
+    Cuvc,
+    /// Overtemperature FET
+    Otf,
+    /// Precharge Timeout
+    Pto,
+    /// Precharge Timeout Suspend
+    Ptos,
+    /// Charge Timeout
+    Cto,
+    /// Charge Timeout Suspend
+    Ctos,
+    /// Overcharge
+    Oc,
+    /// Overcharging Current
+    Chgc,
+    /// Overcharging Voltage
+    Chgv,
+    /// Over-Precharge Current
+    Pchgc,
+    /// Undertemperature in Charge
+    Utc,
+    /// Undertemperature in Discharge
+    Utd,
+    /// Safety Cell Undervoltage Failure
+    Suv,
+    /// Safety Cell Overvoltage Failure
+    Sov,
+    /// Safety Overcurrent in Charge Failure
+    Socc,
+    /// Safety Overcurrent in Discharge Failure
+    Socd,
+    /// Safety Overtemperature Cell Failure
+    Sot,
+    /// Safety Overtemperature FET Failure
+    Sotf,
+    /// `QMax` Imbalance Failure
+    Qim,
+    /// Cell Balancing Failure
+    Cb,
+    /// Cell Impedance Failure
+    Imp,
+    /// Capacity Degradation Failure
+    Cd,
+    /// Voltage Imbalance at Rest Failure
+    Vimr,
+    /// Voltage Imbalance while Pack Is Active Failure
+    Vima,
+    /// Charge FET Failure
+    Cfetf,
+    /// Discharge FET Failure
+    Dfetf,
+    /// Chemical Fuse Failure
+    Fuse,
+    /// AFE Register Failure
+    Afer,
+    /// AFE Communication Failure
+    Afec,
+    /// Second Level Protector Failure
+    SecondLvl,
+    /// PTC Failure
+    Ptc,
+    /// Instruction Flash Checksum Failure
+    Ifc,
+    /// Open Cell Tab Connection Failure
+    OpnCell,
+    /// Data Flash Wearout Failure
+    Dfw,
+    /// Open Thermistor TS1 Failure
+    Ts1,
+    /// Open Thermistor TS2 Failure
+    Ts2,
+    /// Open Thermistor TS3 Failure
+    Ts3,
+    /// Open Thermistor TS4 Failure
+    Ts4,
+}
+
+/// `SafetyAlert()` and `SafetyStatus()` flags by bit, checked against each revision's generated field sets in the tests
+const SAFETY_FLAGS: [Option<StatusFlag>; 32] = [
+    Some(StatusFlag::Cuv),
+    Some(StatusFlag::Cov),
+    Some(StatusFlag::Occ1),
+    Some(StatusFlag::Occ2),
+    Some(StatusFlag::Ocd1),
+    Some(StatusFlag::Ocd2),
+    Some(StatusFlag::Aold),
+    Some(StatusFlag::Aoldl),
+    Some(StatusFlag::Ascc),
+    Some(StatusFlag::Asccl),
+    Some(StatusFlag::Ascd),
+    Some(StatusFlag::Ascdl),
+    Some(StatusFlag::Otc),
+    Some(StatusFlag::Otd),
+    Some(StatusFlag::Cuvc),
+    None,
+    Some(StatusFlag::Otf),
+    None,
+    Some(StatusFlag::Pto),
+    Some(StatusFlag::Ptos),
+    Some(StatusFlag::Cto),
+    Some(StatusFlag::Ctos),
+    Some(StatusFlag::Oc),
+    Some(StatusFlag::Chgc),
+    Some(StatusFlag::Chgv),
+    Some(StatusFlag::Pchgc),
+    Some(StatusFlag::Utc),
+    Some(StatusFlag::Utd),
+    None,
+    None,
+    None,
+    None,
+];
+
+/// `PFAlert()` and `PFStatus()` flags by bit, checked against each revision's generated field sets in the tests
+const PF_FLAGS: [Option<StatusFlag>; 32] = [
+    Some(StatusFlag::Suv),
+    Some(StatusFlag::Sov),
+    Some(StatusFlag::Socc),
+    Some(StatusFlag::Socd),
+    Some(StatusFlag::Sot),
+    None,
+    Some(StatusFlag::Sotf),
+    Some(StatusFlag::Qim),
+    Some(StatusFlag::Cb),
+    Some(StatusFlag::Imp),
+    Some(StatusFlag::Cd),
+    Some(StatusFlag::Vimr),
+    Some(StatusFlag::Vima),
+    None,
+    None,
+    None,
+    Some(StatusFlag::Cfetf),
+    Some(StatusFlag::Dfetf),
+    None,
+    Some(StatusFlag::Fuse),
+    Some(StatusFlag::Afer),
+    Some(StatusFlag::Afec),
+    Some(StatusFlag::SecondLvl),
+    Some(StatusFlag::Ptc),
+    Some(StatusFlag::Ifc),
+    Some(StatusFlag::OpnCell),
+    Some(StatusFlag::Dfw),
+    None,
+    Some(StatusFlag::Ts1),
+    Some(StatusFlag::Ts2),
+    Some(StatusFlag::Ts3),
+    Some(StatusFlag::Ts4),
+];
+
+/// `AOLD`, `ASCC` and `ASCD` are only reported in `SafetyStatus()`
+const SAFETY_ALERT_RESERVED: u32 = 1 << 6 | 1 << 8 | 1 << 10;
+/// `CTOS` is only reported in `SafetyAlert()`
+const SAFETY_STATUS_RESERVED: u32 = 1 << 21;
+/// `PTC`, `IFC` and `DFW` are only reported in `PFStatus()`
+const PF_ALERT_RESERVED: u32 = 1 << 23 | 1 << 24 | 1 << 26;
+
+impl StatusFlag {
+    /// Short name used by the TRM, e.g. `COV`.
+    #[must_use]
+    pub const fn short_name(self) -> &'static str {
+        match self {
+            Self::Cuv => "CUV",
+            Self::Cov => "COV",
+            Self::Occ1 => "OCC1",
+            Self::Occ2 => "OCC2",
+            Self::Ocd1 => "OCD1",
+            Self::Ocd2 => "OCD2",
+            Self::Aold => "AOLD",
+            Self::Aoldl => "AOLDL",
+            Self::Ascc => "ASCC",
+            Self::Asccl => "ASCCL",
+            Self::Ascd => "ASCD",
+            Self::Ascdl => "ASCDL",
+            Self::Otc => "OTC",
+            Self::Otd => "OTD",
+            Self::Cuvc => "CUVC",
+            Self::Otf => "OTF",
+            Self::Pto => "PTO",
+            Self::Ptos => "PTOS",
+            Self::Cto => "CTO",
+            Self::Ctos => "CTOS",
+            Self::Oc => "OC",
+            Self::Chgc => "CHGC",
+            Self::Chgv => "CHGV",
+            Self::Pchgc => "PCHGC",
+            Self::Utc => "UTC",
+            Self::Utd => "UTD",
+            Self::Suv => "SUV",
+            Self::Sov => "SOV",
+            Self::Socc => "SOCC",
+            Self::Socd => "SOCD",
+            Self::Sot => "SOT",
+            Self::Sotf => "SOTF",
+            Self::Qim => "QIM",
+            Self::Cb => "CB",
+            Self::Imp => "IMP",
+            Self::Cd => "CD",
+            Self::Vimr => "VIMR",
+            Self::Vima => "VIMA",
+            Self::Cfetf => "CFETF",
+            Self::Dfetf => "DFETF",
+            Self::Fuse => "FUSE",
+            Self::Afer => "AFER",
+            Self::Afec => "AFEC",
+            Self::SecondLvl => "2LVL",
+            Self::Ptc => "PTC",
+            Self::Ifc => "IFC",
+            Self::OpnCell => "OPNCELL",
+            Self::Dfw => "DFW",
+            Self::Ts1 => "TS1",
+            Self::Ts2 => "TS2",
+            Self::Ts3 => "TS3",
+            Self::Ts4 => "TS4",
+        }
+    }
+
+    /// Long description, e.g. `Cell Overvoltage`.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Cuv => "Cell Undervoltage",
+            Self::Cov => "Cell Overvoltage",
+            Self::Occ1 => "Overcurrent in Charge 1st Tier",
+            Self::Occ2 => "Overcurrent in Charge 2nd Tier",
+            Self::Ocd1 => "Overcurrent in Discharge 1st Tier",
+            Self::Ocd2 => "Overcurrent in Discharge 2nd Tier",
+            Self::Aold => "Overload in Discharge",
+            Self::Aoldl => "Overload in Discharge Latch",
+            Self::Ascc => "Short Circuit in Charge",
+            Self::Asccl => "Short Circuit in Charge Latch",
+            Self::Ascd => "Short Circuit in Discharge",
+            Self::Ascdl => "Short Circuit in Discharge Latch",
+            Self::Otc => "Overtemperature in Charge",
+            Self::Otd => "Overtemperature in Discharge",
+            Self::Cuvc => "Cell Undervoltage Compensated",
+            Self::Otf => "Overtemperature FET",
+            Self::Pto => "Precharge Timeout",
+            Self::Ptos => "Precharge Timeout Suspend",
+            Self::Cto => "Charge Timeout",
+            Self::Ctos => "Charge Timeout Suspend",
+            Self::Oc => "Overcharge",
+            Self::Chgc => "Overcharging Current",
+            Self::Chgv => "Overcharging Voltage",
+            Self::Pchgc => "Over-Precharge Current",
+            Self::Utc => "Undertemperature in Charge",
+            Self::Utd => "Undertemperature in Discharge",
+            Self::Suv => "Safety Cell Undervoltage Failure",
+            Self::Sov => "Safety Cell Overvoltage Failure",
+            Self::Socc => "Safety Overcurrent in Charge Failure",
+            Self::Socd => "Safety Overcurrent in Discharge Failure",
+            Self::Sot => "Safety Overtemperature Cell Failure",
+            Self::Sotf => "Safety Overtemperature FET Failure",
+            Self::Qim => "QMax Imbalance Failure",
+            Self::Cb => "Cell Balancing Failure",
+            Self::Imp => "Cell Impedance Failure",
+            Self::Cd => "Capacity Degradation Failure",
+            Self::Vimr => "Voltage Imbalance at Rest Failure",
+            Self::Vima => "Voltage Imbalance while Pack Is Active Failure",
+            Self::Cfetf => "Charge FET Failure",
+            Self::Dfetf => "Discharge FET Failure",
+            Self::Fuse => "Chemical Fuse Failure",
+            Self::Afer => "AFE Register Failure",
+            Self::Afec => "AFE Communication Failure",
+            Self::SecondLvl => "Second Level Protector Failure",
+            Self::Ptc => "PTC Failure",
+            Self::Ifc => "Instruction Flash Checksum Failure",
+            Self::OpnCell => "Open Cell Tab Connection Failure",
+            Self::Dfw => "Data Flash Wearout Failure",
+            Self::Ts1 => "Open Thermistor TS1 Failure",
+            Self::Ts2 => "Open Thermistor TS2 Failure",
+            Self::Ts3 => "Open Thermistor TS3 Failure",
+            Self::Ts4 => "Open Thermistor TS4 Failure",
+        }
+    }
+
+    /// Condition that clears the flag once it has tripped.
+    #[must_use]
+    pub const fn recovery(self) -> Recovery {
+        match self {
+            Self::Cuv
+            | Self::Cov
+            | Self::Occ1
+            | Self::Occ2
+            | Self::Ocd1
+            | Self::Ocd2
+            | Self::Otc
+            | Self::Otd
+            | Self::Cuvc
+            | Self::Otf
+            | Self::Ptos
+            | Self::Ctos
+            | Self::Utc
+            | Self::Utd => Recovery::Threshold,
+            Self::Aold | Self::Ascc | Self::Ascd => Recovery::Timer,
+            Self::Aoldl | Self::Asccl | Self::Ascdl => Recovery::Latch,
+            Self::Pto | Self::Cto | Self::Oc => Recovery::Discharge,
+            Self::Chgc | Self::Chgv | Self::Pchgc => Recovery::ChargerRemoval,
+            Self::Suv
+            | Self::Sov
+            | Self::Socc
+            | Self::Socd
+            | Self::Sot
+            | Self::Sotf
+            | Self::Qim
+            | Self::Cb
+            | Self::Imp
+            | Self::Cd
+            | Self::Vimr
+            | Self::Vima
+            | Self::Cfetf
+            | Self::Dfetf
+            | Self::Fuse
+            | Self::Afer
+            | Self::Afec
+            | Self::SecondLvl
+            | Self::Ptc
+            | Self::Ifc
+            | Self::OpnCell
+            | Self::Dfw
+            | Self::Ts1
+            | Self::Ts2
+            | Self::Ts3
+            | Self::Ts4 => Recovery::Permanent,
+        }
+    }
+}
+
+impl fmt::Display for StatusFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.short_name(), self.description())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+/// Flag set in a status register
+pub struct ActiveFlag {
+    pub flag: StatusFlag,
+    pub severity: Severity,
+}
+
+impl fmt::Display for ActiveFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?}: {}, {}",
+            self.severity,
+            self.flag,
+            self.flag.recovery().description()
+        )
+    }
+}
+
+/// Iterator over the flags set in a status register value, from the lowest bit up.
+#[derive(Debug, Clone)]
+pub struct ActiveFlags {
+    register: StatusRegister,
+    bits: u32,
+}
+
+impl ActiveFlags {
+    /// Decode the raw value of `register`, as returned by `read_status`. `OperationStatus()` holds no flags.
+    #[must_use]
+    pub const fn new(register: StatusRegister, bits: u32) -> Self {
+        let reserved = match register {
+            StatusRegister::SafetyAlert => SAFETY_ALERT_RESERVED,
+            StatusRegister::SafetyStatus => SAFETY_STATUS_RESERVED,
+            StatusRegister::PfAlert => PF_ALERT_RESERVED,
+            StatusRegister::PfStatus | StatusRegister::OperationStatus => 0,
+        };
+
+        Self {
+            register,
+            bits: bits & !reserved,
+        }
+    }
+}
+
+impl Iterator for ActiveFlags {
+    type Item = ActiveFlag;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (flags, severity) = match self.register {
+            StatusRegister::SafetyAlert => (&SAFETY_FLAGS, Severity::Alert),
+            StatusRegister::SafetyStatus => (&SAFETY_FLAGS, Severity::Protection),
+            StatusRegister::PfAlert => (&PF_FLAGS, Severity::Alert),
+            StatusRegister::PfStatus => (&PF_FLAGS, Severity::PermanentFailure),
+            StatusRegister::OperationStatus => return None,
+        };
+
+        while self.bits != 0 {
+            let bit = self.bits.trailing_zeros();
+            self.bits &= self.bits - 1;
+            // Reserved bits are skipped
+            if let Some(flag) = flags[bit as usize] {
+                return Some(ActiveFlag { flag, severity });
+            }
+        }
+
+        None
+    }
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Read the raw value of a status register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub async fn read_status(&mut self, register: StatusRegister) -> Result<u32, BQ40Z50Error<I2C::Error>> {
+        let mut buf = [0u8; STATUS_REG_SIZE_BYTES];
+        self.read_with_retries(&[register.command()], &mut buf, self.config.pec_read)
+            .await?;
+        Ok(u32::from_le_bytes(buf))
+    }
+}
@@ -1,11 +1,11 @@
 macro_rules! bq40z50_tests {
-    ($revision:ident) => {
+    ($revision:ident, $blocking:ident) => {
         #[cfg(test)]
         mod tests {
             use embedded_batteries_async::smart_battery::SmartBattery;
             use embedded_hal_mock::eh1::delay::{CheckedDelay, NoopDelay, Transaction as DelayTransaction};
             use embedded_hal_mock::eh1::i2c::{Mock, Transaction};
-            use $revision as Bq40z50;
+            use {$blocking as Bq40z50Blocking, $revision as Bq40z50};
 
             use super::*;
             use crate::common::{CapacityModeState, Config};
@@ -151,122 +151,1733 @@ macro_rules! bq40z50_tests {
 
             #[tokio::test]
             async fn read_too_large_manufacture_name() {
-                let expectations = vec![Transaction::write_read(BQ_ADDR, vec![0x20], vec![0x00])];
+                // The gauge reports 5 bytes, more than the buffer holds
+                let expectations = vec![Transaction::write_read(BQ_ADDR, vec![0x20], vec![0x05, b'T'])];
                 let i2c = Mock::new(&expectations);
                 let mut bq = Device::new(DeviceInterface::new(i2c, NoopDelay::new()));
 
                 let mut manufacture_name = [0u8; 1];
 
-                bq.manufacture_name()
-                    .read_async(&mut manufacture_name)
+                assert_eq!(
+                    bq.manufacture_name().read_async(&mut manufacture_name).await,
+                    Err(BQ40Z50Error::DataTooLarge)
+                );
+                bq.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn read_block_pec() {
+                let expectations = vec![
+                    // PEC mismatch, retried
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x21],
+                        vec![0x03, b'b', b'q', b'4', 0x05, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+                    ),
+                    // PEC follows the 3 byte payload, the rest of the read is padding
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x21],
+                        vec![0x03, b'b', b'q', b'4', 0x04, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF],
+                    ),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Device::new(DeviceInterface::new_with_config(
+                    i2c,
+                    NoopDelay::new(),
+                    Config {
+                        pec_read: true,
+                        ..Default::default()
+                    },
+                ));
+
+                let mut device_name = [0xAAu8; 8];
+
+                assert_eq!(bq.device_name().read_async(&mut device_name).await, Ok(3));
+                assert_eq!(device_name, [b'b', b'q', b'4', 0, 0, 0, 0, 0]);
+                bq.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn write_unseal_keys() {
+                let expectations = vec![
+                    Transaction::write(
+                        BQ_ADDR,
+                        vec![
+                            0x44, 0x0A, 0x35, 0x00, 0x30, 0x30, 0x60, 0x60, 0x01, 0x01, 0x10, 0x10,
+                        ],
+                    ),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x35, 0x00]),
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x44],
+                        vec![0x0A, 0x35, 0x00, 0x30, 0x30, 0x60, 0x60, 0x01, 0x01, 0x10, 0x10],
+                    ),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let security_keys = [0x30u8, 0x30u8, 0x60u8, 0x60u8, 0x01u8, 0x01u8, 0x10u8, 0x10u8];
+
+                bq.write_security_keys(&security_keys).await.unwrap();
+
+                let mut result = [0u8; 8];
+                bq.read_security_keys(&mut result).await.unwrap();
+
+                assert_eq!(u16::from_le_bytes([result[0], result[1]]), 0x3030);
+                assert_eq!(u16::from_le_bytes([result[2], result[3]]), 0x6060);
+                assert_eq!(u16::from_le_bytes([result[4], result[5]]), 0x0101);
+                assert_eq!(u16::from_le_bytes([result[6], result[7]]), 0x1010);
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_battery_status() {
+                let expectations = vec![Transaction::write_read(BQ_ADDR, vec![0x16], vec![0x30, 0x30])];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let status = match bq.battery_status().await {
+                    Ok(status) => status,
+                    Err(e) => match e {
+                        _ => unreachable!(),
+                    },
+                };
+
+                assert_eq!(status.error_code(), ErrorCode::Ok);
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_battery_status_pec() {
+                // Full bus transaction looks like [ 0x0B << 1 || 0x16 || 0x0B << 1 | 1 || 0x30 || 0x30 || 0xB7 (PEC)]
+                let expectations = vec![Transaction::write_read(
+                    BQ_ADDR,
+                    vec![0x16],
+                    vec![0x30, 0x30, 0xB7],
+                )];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new_with_config(
+                    i2c,
+                    NoopDelay::new(),
+                    Config {
+                        pec_read: true,
+                        ..Default::default()
+                    },
+                );
+
+                let status = match bq.battery_status().await {
+                    Ok(status) => status,
+                    Err(e) => match e {
+                        _ => unreachable!(),
+                    },
+                };
+
+                assert_eq!(status.error_code(), ErrorCode::Ok);
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_alternate_address_pec() {
+                // PEC is computed over the configured address: [ 0x0C << 1 || 0x16 || 0x0C << 1 | 1 || 0x30 || 0x30 ]
+                let expectations = vec![
+                    Transaction::write_read(0x0C, vec![0x16], vec![0x30, 0x30, 0xC9]),
+                    Transaction::write(0x0C, vec![0x44, 0x02, 0x21, 0x00, 0x85]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new_with_config(
+                    i2c,
+                    NoopDelay::new(),
+                    Config {
+                        address: 0x0C,
+                        pec_read: true,
+                        pec_write: true,
+                        ..Default::default()
+                    },
+                );
+
+                let status = bq.battery_status().await.unwrap();
+                assert_eq!(status.error_code(), ErrorCode::Ok);
+
+                bq.device.mac_gauging().dispatch_async().await.unwrap();
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            #[allow(unsafe_code)]
+            async fn test_read_write_unchecked() {
+                let expectations = vec![
+                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0x30, 0x30]),
+                    Transaction::write(BQ_ADDR, vec![0x16, 0x2F, 0x30]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let mut data = [0u8; 2];
+
+                unsafe {
+                    bq.read_register_unchecked(0x16, &mut data).await.unwrap();
+                }
+                data[0] -= 1;
+
+                unsafe {
+                    bq.write_register_unchecked(0x16, &data).await.unwrap();
+                }
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_generic_driver_trait() {
+                async fn seal_and_read_df<T: crate::Bq40z50>(bq: &mut T, read: &mut [u8]) -> Result<(), T::Error> {
+                    bq.read_dataflash(0x4000, read).await?;
+                    bq.seal().await
+                }
+
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x00, 0x40]),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], vec![0x22, 0x00, 0x40, 0x01, 0x02]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x30, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x03, 0x00, 0x00]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let mut read = [0u8; 2];
+                seal_and_read_df(&mut bq, &mut read).await.unwrap();
+                assert_eq!(read, [0x01, 0x02]);
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_probe_revision() {
+                let revision = <Bq40z50<Mock, NoopDelay> as crate::Bq40z50>::REVISION;
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x01, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], vec![0x04, 0x01, 0x00, 0x00, 0x45]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x02, 0x00]),
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x44],
+                        vec![
+                            0x0D,
+                            0x02,
+                            0x00,
+                            0x45,
+                            0x00,
+                            revision.major_version(),
+                            0x02,
+                            0x00,
+                            0x00,
+                            0x00,
+                            0x00,
+                            0x00,
+                            0x00,
+                            0x00,
+                        ],
+                    ),
+                    Transaction::write_read(BQ_ADDR, vec![0x09], vec![0x10, 0x27]),
+                ];
+                let mut i2c = Mock::new(&expectations);
+                let mut bq = crate::AnyBq40z50::probe(i2c.clone(), NoopDelay::new(), Config::default())
                     .await
+                    .map_err(|(e, _, _)| e)
                     .unwrap();
-                bq.interface.i2c.done();
+
+                assert_eq!(bq.revision(), revision);
+                assert_eq!(bq.voltage().await.unwrap(), 10000);
+
+                i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_probe_unknown_device() {
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x01, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], vec![0x04, 0x01, 0x00, 0x50, 0x04]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x02, 0x00]),
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x44],
+                        vec![
+                            0x0D, 0x02, 0x00, 0x04, 0x50, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                        ],
+                    ),
+                ];
+                let Err((error, mut i2c, _delay)) =
+                    crate::AnyBq40z50::probe(Mock::new(&expectations), NoopDelay::new(), Config::default()).await
+                else {
+                    panic!("probe identified an unknown device");
+                };
+
+                assert_eq!(
+                    error,
+                    crate::BQ40Z50Error::UnknownRevision {
+                        device_type: 0x0450,
+                        firmware_version: 0x0102,
+                    }
+                );
+
+                i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_authenticate() {
+                let key: crate::AuthKey = core::array::from_fn(|i| u8::try_from(i).unwrap() + 0x01);
+                let challenge: crate::AuthChallenge = core::array::from_fn(|i| u8::try_from(i).unwrap() + 0x20);
+                let mut write = vec![0x2F, 20];
+                write.extend_from_slice(&challenge);
+                let digest = vec![
+                    20, 0x7E, 0xA4, 0xB9, 0xB0, 0x87, 0xC2, 0xE7, 0xFD, 0x4B, 0x01, 0x55, 0x58, 0x86, 0x3A, 0x32, 0x43,
+                    0x54, 0x98, 0x8C, 0x9D,
+                ];
+                let mut counterfeit = digest.clone();
+                counterfeit[20] ^= 0x01;
+
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, write.clone()),
+                    Transaction::write_read(BQ_ADDR, vec![0x2F], digest),
+                    Transaction::write(BQ_ADDR, write),
+                    Transaction::write_read(BQ_ADDR, vec![0x2F], counterfeit),
+                ];
+                let i2c = Mock::new(&expectations);
+                let delay_expectations = vec![DelayTransaction::delay_ms(250), DelayTransaction::delay_ms(250)];
+                let mut bq = Bq40z50::new(i2c, CheckedDelay::new(&delay_expectations));
+
+                bq.authenticate(&key, &challenge).await.unwrap();
+                assert_eq!(
+                    bq.authenticate(&key, &challenge).await,
+                    Err(BQ40Z50Error::AuthenticationFailed)
+                );
+
+                bq.device.interface.i2c.done();
+                bq.device.interface.delay.done();
+            }
+
+            #[tokio::test]
+            async fn test_security_transitions() {
+                let sealed = vec![0x00, 0x03, 0x00, 0x00];
+                let unsealed = vec![0x00, 0x02, 0x00, 0x00];
+                let full_access = vec![0x00, 0x01, 0x00, 0x00];
+                let expectations = vec![
+                    // Wrong unseal key
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x14, 0x04]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x00, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                    // Retry after the lockout
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x14, 0x04]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x72, 0x36]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], unsealed.clone()),
+                    // Full access
+                    Transaction::write_read(BQ_ADDR, vec![0x54], unsealed),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0xFF, 0xFF]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0xFF, 0xFF]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], full_access),
+                    // Seal
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x30, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed),
+                ];
+                let i2c = Mock::new(&expectations);
+                let delay_expectations = vec![DelayTransaction::delay_ms(4000)];
+                let mut bq = Bq40z50::new(i2c, CheckedDelay::new(&delay_expectations));
+
+                assert_eq!(
+                    bq.unseal(0x0414, 0x0000).await,
+                    Err(BQ40Z50Error::KeyRejected {
+                        state: crate::SecurityState::Sealed
+                    })
+                );
+                bq.unseal(0x0414, 0x3672).await.unwrap();
+                bq.enter_full_access(0xFFFF, 0xFFFF).await.unwrap();
+                bq.seal().await.unwrap();
+
+                bq.device.interface.i2c.done();
+                bq.device.interface.delay.done();
+            }
+
+            #[tokio::test]
+            async fn test_unsealed_session_reseals() {
+                let sealed = vec![0x00, 0x03, 0x00, 0x00];
+                let unsealed = vec![0x00, 0x02, 0x00, 0x00];
+                let mut expectations = Vec::new();
+                for _ in 0..2 {
+                    expectations.extend([
+                        Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                        Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x14, 0x04]),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x72, 0x36]),
+                        Transaction::write_read(BQ_ADDR, vec![0x54], unsealed.clone()),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x06, 0x00, 0x40, 0xFE, 0xCA, 0xFE, 0xC0]),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x30, 0x00]),
+                        Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                    ]);
+                }
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                // The gauge is resealed even though the privileged operations bail out early.
+                let res = crate::UnsealedSession::scoped(&mut bq, 0x0414, 0x3672, async |session| {
+                    session.write_dataflash(0x4000, &[0xFE, 0xCA, 0xFE, 0xC0]).await?;
+                    Err::<(), _>(BQ40Z50Error::DataTooLarge)
+                })
+                .await;
+                assert_eq!(res, Err(BQ40Z50Error::DataTooLarge));
+
+                let mut session = crate::UnsealedSession::begin(&mut bq, 0x0414, 0x3672)
+                    .await
+                    .unwrap();
+                session
+                    .write_dataflash(0x4000, &[0xFE, 0xCA, 0xFE, 0xC0])
+                    .await
+                    .unwrap();
+                session.finish().await.unwrap();
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_unsealed_session_restores_prior_state() {
+                let sealed = vec![0x00, 0x03, 0x00, 0x00];
+                let unsealed = vec![0x00, 0x02, 0x00, 0x00];
+                let expectations = vec![
+                    // A gauge that was already unsealed is left unsealed.
+                    Transaction::write_read(BQ_ADDR, vec![0x54], unsealed.clone()),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x06, 0x00, 0x40, 0xFE, 0xCA, 0xFE, 0xC0]),
+                    // A dropped session seals the gauge ahead of the next transfer.
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed.clone()),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x14, 0x04]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x72, 0x36]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], unsealed),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x30, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], sealed),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let mut session = crate::UnsealedSession::begin(&mut bq, 0x0414, 0x3672)
+                    .await
+                    .unwrap();
+                assert_eq!(session.prior_state(), crate::SecurityState::Unsealed);
+                session
+                    .write_dataflash(0x4000, &[0xFE, 0xCA, 0xFE, 0xC0])
+                    .await
+                    .unwrap();
+                session.finish().await.unwrap();
+
+                let session = crate::UnsealedSession::begin(&mut bq, 0x0414, 0x3672)
+                    .await
+                    .unwrap();
+                assert_eq!(session.prior_state(), crate::SecurityState::Sealed);
+                drop(session);
+                assert_eq!(bq.security_state().await, Ok(crate::SecurityState::Sealed));
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_df_params() {
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x06, 0x40]),
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x44],
+                        vec![0x22, 0x06, 0x40, 0x00, 0x00, 0x60, 0x40],
+                    ),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x04, 0x00, 0x40, 0xE0, 0x2E]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+                let params = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::DATA_FLASH;
+
+                let cc_gain = crate::DfParam::lookup(params, "Calibration", "Current", "CC Gain").unwrap();
+                assert_eq!(bq.read_df_param(cc_gain).await, Ok(crate::DfValue::Float(3.5)));
+
+                let cell_gain = crate::DfParam::lookup(params, "Calibration", "Voltage", "Cell Gain").unwrap();
+                bq.write_df_param(cell_gain, &crate::DfValue::Int(12000))
+                    .await
+                    .unwrap();
+
+                // Values outside the parameter's type or range never reach the bus.
+                let temp_offset =
+                    crate::DfParam::lookup(params, "Calibration", "Temperature", "Internal Temp Offset").unwrap();
+                assert_eq!(
+                    bq.write_df_param(temp_offset, &crate::DfValue::Int(200)).await,
+                    Err(BQ40Z50Error::InvalidDfValue)
+                );
+                assert_eq!(
+                    bq.write_df_param(cell_gain, &crate::DfValue::Float(1.0)).await,
+                    Err(BQ40Z50Error::InvalidDfValue)
+                );
+
+                let name = crate::DfParam {
+                    class: "SBS Configuration",
+                    subclass: "Data",
+                    name: "Device Chemistry",
+                    address: 0x4000,
+                    ty: crate::DfType::S(4),
+                    units: "",
+                    range: crate::DfRange::None,
+                    default: crate::DfValue::Str(crate::DfString::new(b"LION").unwrap()),
+                };
+                let mut buf = [0u8; 5];
+                assert_eq!(
+                    name.encode(&crate::DfValue::Str("LiP".try_into().unwrap()), &mut buf),
+                    Some(5)
+                );
+                assert_eq!(buf, [0x03, b'L', b'i', b'P', 0x00]);
+                assert_eq!(
+                    name.decode(&buf),
+                    Some(crate::DfValue::Str("LiP".try_into().unwrap()))
+                );
+                assert_eq!(name.encode(&name.default, &mut buf), Some(5));
+                assert_eq!(
+                    name.encode(&crate::DfValue::Str("LiPo2".try_into().unwrap()), &mut buf),
+                    None
+                );
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[cfg(feature = "std")]
+            #[tokio::test]
+            async fn test_gg_import() {
+                let contents = concat!(
+                    "* Texas Instruments Data Flash File\n",
+                    "\"Calibration\",\"Voltage\",\"Cell Gain\",\"12101\",\"\"\n",
+                    "\"Calibration\",\"Temperature\",\"Internal Temp Offset\",\"-5\",\"0.1 degC\"\n",
+                );
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x00, 0x40]),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], vec![0x22, 0x00, 0x40, 0x45, 0x2F]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x14, 0x40]),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], vec![0x22, 0x14, 0x40, 0x00]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x03, 0x14, 0x40, 0xFB]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+                let params = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::DATA_FLASH;
+
+                let file = crate::gg::GgFile::parse(contents).unwrap();
+                assert_eq!(file.to_string(), contents);
+
+                // Only the parameter that differs from the gauge is written.
+                let settings = file.resolve(params).unwrap().settings;
+                let changes = crate::gg::diff(&mut bq, &settings).await.unwrap();
+                assert_eq!(changes.len(), 1);
+                assert_eq!(
+                    changes[0].to_string(),
+                    "Calibration:Temperature:Internal Temp Offset: 0 -> -5"
+                );
+                crate::gg::program(&mut bq, &changes).await.unwrap();
+
+                let unknown = crate::gg::GgFile::parse("\"Calibration\",\"Voltage\",\"Gain\",\"1\",\"\"").unwrap();
+                let resolution = unknown.resolve(params).unwrap();
+                assert!(resolution.settings.is_empty());
+                assert_eq!(resolution.unknown, unknown.entries);
+                let out_of_range = crate::gg::GgFile::parse(&contents.replace("-5", "-500")).unwrap();
+                assert_eq!(
+                    out_of_range.resolve(params),
+                    Err(crate::gg::GgError::InvalidValue { line: 3 })
+                );
+                assert_eq!(
+                    crate::gg::GgFile::parse("\"Calibration\",\"Voltage\""),
+                    Err(crate::gg::GgError::Syntax { line: 1 })
+                );
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[cfg(feature = "std")]
+            #[test]
+            fn test_gg_resolve_full_export() {
+                // A full-size export in the bqStudio layout, covering every data flash class.
+                let contents = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/testdata/bq40z50.gg.csv"));
+                let params = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::DATA_FLASH;
+
+                let file = crate::gg::GgFile::parse(contents).unwrap();
+                assert_eq!(file.header.len(), 13);
+                assert_eq!(file.entries.len(), 435);
+
+                // Every parameter of the revision's table is found, the rest are set aside.
+                let resolution = file.resolve(params).unwrap();
+                assert_eq!(resolution.settings.len(), params.len());
+                assert_eq!(resolution.settings.len() + resolution.unknown.len(), file.entries.len());
+                for (setting, param) in resolution.settings.iter().zip(params) {
+                    assert_eq!(setting.param, param);
+                    assert_eq!(setting.value, param.default);
+                }
+            }
+
+            #[tokio::test]
+            async fn test_flash_stream() {
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x00, 0x14, 0x04]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x02]),
+                    Transaction::write_read(BQ_ADDR, vec![0x0D], vec![0x50, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x02]),
+                    Transaction::write(0x10, vec![0xAA]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(
+                    i2c,
+                    CheckedDelay::new(&[DelayTransaction::delay_ms(5), DelayTransaction::delay_ms(0)]),
+                );
+
+                let mut lines = Vec::new();
+                bq.run_flash_stream(
+                    "; unseal\nW: 16 00 14 04\n\nX: 5\nC: 16 54 00 02\nR: 16 0D 02\n",
+                    |progress| {
+                        assert_eq!(progress.commands_total, 4);
+                        lines.push(progress.line);
+                    },
+                )
+                .await
+                .unwrap();
+                assert_eq!(lines, [2, 4, 5, 6]);
+
+                // Failures report the line of the offending command.
+                assert_eq!(
+                    bq.run_flash_stream("X: 0\nC: 16 54 00 03", |_| {}).await,
+                    Err(crate::FlashStreamError::Command {
+                        line: 2,
+                        error: BQ40Z50Error::CompareMismatch
+                    })
+                );
+                // A malformed stream is rejected before anything is sent.
+                assert_eq!(
+                    bq.run_flash_stream("W: 16 00 14 04\nW: 16 0G", |_| {}).await,
+                    Err(crate::FlashStreamError::Syntax { line: 2 })
+                );
+                // Commands go to the address named in the stream, the configured address is left alone.
+                bq.run_flash_stream("W: 20 AA", |_| {}).await.unwrap();
+                assert_eq!(bq.config().address, BQ_ADDR);
+
+                bq.device.interface.i2c.done();
+                bq.device.interface.delay.done();
+            }
+
+            #[tokio::test]
+            async fn test_snapshot() {
+                use crate::SnapshotFields as Fields;
+
+                let expectations = vec![
+                    Transaction::write_read(BQ_ADDR, vec![0x09], vec![0x10, 0x3B]),
+                    Transaction::write_read(BQ_ADDR, vec![0x0A], vec![0x18, 0xFC]),
+                    Transaction::write_read(BQ_ADDR, vec![0x0D], vec![55, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x0F], vec![0xC4, 0x09]),
+                    Transaction::write_read(BQ_ADDR, vec![0x10], vec![0x88, 0x13]),
+                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0xC0, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x3C], vec![0xA4, 0x0E]),
+                    Transaction::write_read(BQ_ADDR, vec![0x3D], vec![0xA5, 0x0E]),
+                    Transaction::write_read(BQ_ADDR, vec![0x3E], vec![0xA6, 0x0E]),
+                    Transaction::write_read(BQ_ADDR, vec![0x3F], vec![0xA7, 0x0E]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let fields = Fields::VOLTAGE
+                    | Fields::CURRENT
+                    | Fields::RELATIVE_STATE_OF_CHARGE
+                    | Fields::REMAINING_CAPACITY
+                    | Fields::FULL_CHARGE_CAPACITY
+                    | Fields::BATTERY_STATUS
+                    | Fields::CELL_VOLTAGES;
+                let mut now = 100;
+                let snapshot = bq
+                    .read_snapshot(fields, || {
+                        now += 7;
+                        now
+                    })
+                    .await
+                    .unwrap();
+
+                assert_eq!(snapshot.voltage, Some(15120));
+                assert_eq!(snapshot.current, Some(-1000));
+                assert_eq!(snapshot.average_current, None);
+                assert_eq!(snapshot.relative_state_of_charge, Some(55));
+                assert_eq!(
+                    snapshot.remaining_capacity,
+                    Some(CapacityModeValue::MilliAmpUnsigned(2500))
+                );
+                assert_eq!(
+                    snapshot.full_charge_capacity,
+                    Some(CapacityModeValue::MilliAmpUnsigned(5000))
+                );
+                let status = snapshot.battery_status.unwrap();
+                assert!(status.initialized() && status.discharging());
+                assert_eq!(snapshot.cell_voltages, Some([3751, 3750, 3749, 3748]));
+                assert_eq!(snapshot.bus_time(), 7);
+
+                // Only the timestamps are taken for an empty mask.
+                let snapshot = bq.read_snapshot(Fields::NONE, || 0).await.unwrap();
+                assert_eq!(snapshot, crate::Snapshot::default());
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_monitor() {
+                use crate::{Monitor, MonitorConfig, MonitorEvent, SecurityState};
+
+                let expectations = vec![
+                    // t = 0 ms, every register is due
+                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0xC0, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x50], vec![0x02, 0x00, 0x00, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x53], vec![0x00, 0x00, 0x00, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x03, 0x00, 0x00]),
+                    // t = 1000 ms
+                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0xA0, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x50], vec![0x00, 0x00, 0x00, 0x00]),
+                    // t = 5000 ms
+                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0xA0, 0x08]),
+                    Transaction::write_read(BQ_ADDR, vec![0x50], vec![0x00, 0x00, 0x00, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x53], vec![0x00, 0x01, 0x00, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x02, 0x00, 0x00]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+                let mut monitor = Monitor::new(MonitorConfig::default());
+                let mut events = Vec::new();
+
+                assert_eq!(monitor.poll(&mut bq, 0, |e| events.push(e)).await, Ok(1000));
+                assert_eq!(events, vec![MonitorEvent::SafetyFaultRaised { bits: 0x02 }]);
+
+                events.clear();
+                assert_eq!(monitor.poll(&mut bq, 1000, |e| events.push(e)).await, Ok(2000));
+                assert_eq!(
+                    events,
+                    vec![
+                        MonitorEvent::ChargeStarted,
+                        MonitorEvent::FullyCharged,
+                        MonitorEvent::SafetyFaultCleared { bits: 0x02 },
+                    ]
+                );
+
+                events.clear();
+                assert_eq!(monitor.poll(&mut bq, 5000, |e| events.push(e)).await, Ok(6000));
+                assert_eq!(
+                    events,
+                    vec![
+                        MonitorEvent::TerminateDischargeAlarm,
+                        MonitorEvent::PermanentFailure { bits: 0x100 },
+                        MonitorEvent::SecurityStateChanged {
+                            previous: SecurityState::Sealed,
+                            current: SecurityState::Unsealed,
+                        },
+                    ]
+                );
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[test]
+            fn test_status_flags() {
+                use crate::{ActiveFlag, ActiveFlags, Recovery, Severity, StatusFlag, StatusRegister};
+
+                // Decoded flags agree with the revision's generated field sets.
+                let bits: u32 = 1 << 11 | 1 << 19;
+                let pf_status = field_sets::PfStatus::from(bits.to_le_bytes());
+                assert!(pf_status.vimr() && pf_status.fuse());
+                let flags: Vec<_> = ActiveFlags::new(StatusRegister::PfStatus, bits).collect();
+                assert_eq!(
+                    flags,
+                    vec![
+                        ActiveFlag {
+                            flag: StatusFlag::Vimr,
+                            severity: Severity::PermanentFailure,
+                        },
+                        ActiveFlag {
+                            flag: StatusFlag::Fuse,
+                            severity: Severity::PermanentFailure,
+                        },
+                    ]
+                );
+                assert_eq!(flags[0].flag.short_name(), "VIMR");
+                assert_eq!(flags[1].flag.recovery(), Recovery::Permanent);
+
+                // Bits reserved in a register are skipped, e.g. AOLD only exists in SafetyStatus().
+                let bits: u32 = 1 << 1 | 1 << 6 | 1 << 21;
+                let safety_alert = field_sets::SafetyAlert::from(bits.to_le_bytes());
+                assert!(safety_alert.cov() && safety_alert.ctos());
+                let flags: Vec<_> = ActiveFlags::new(StatusRegister::SafetyAlert, bits)
+                    .map(|f| f.flag)
+                    .collect();
+                assert_eq!(flags, vec![StatusFlag::Cov, StatusFlag::Ctos]);
+
+                let cov = ActiveFlags::new(StatusRegister::SafetyStatus, bits)
+                    .next()
+                    .unwrap();
+                assert!(
+                    cov.to_string()
+                        .starts_with("Protection: COV (Cell Overvoltage), clears when the measurement returns")
+                );
+                assert_eq!(
+                    ActiveFlags::new(StatusRegister::OperationStatus, u32::MAX).count(),
+                    0
+                );
+            }
+
+            /// Check that every bit of `register` decodes to the flags whose generated accessors are set.
+            fn check_status_flag_table<F: From<[u8; 4]>>(
+                register: crate::StatusRegister,
+                fields: &[(crate::StatusFlag, fn(&F) -> bool)],
+            ) {
+                for bit in 0..32 {
+                    let bits = 1u32 << bit;
+                    let field_set = F::from(bits.to_le_bytes());
+                    let expected: Vec<_> = fields
+                        .iter()
+                        .filter(|(_, get)| get(&field_set))
+                        .map(|(flag, _)| *flag)
+                        .collect();
+                    let actual: Vec<_> = crate::ActiveFlags::new(register, bits).map(|f| f.flag).collect();
+                    assert_eq!(actual, expected, "{register:?} bit {bit}");
+                }
+            }
+
+            #[test]
+            fn test_safety_flag_table() {
+                use crate::{StatusFlag, StatusRegister};
+
+                // Every field of the revision's generated registers, by the flag it decodes to.
+                let safety_alert: &[(StatusFlag, fn(&field_sets::SafetyAlert) -> bool)] = &[
+                    (StatusFlag::Cuv, field_sets::SafetyAlert::cuv),
+                    (StatusFlag::Cov, field_sets::SafetyAlert::cov),
+                    (StatusFlag::Occ1, field_sets::SafetyAlert::occ_1),
+                    (StatusFlag::Occ2, field_sets::SafetyAlert::occ_2),
+                    (StatusFlag::Ocd1, field_sets::SafetyAlert::ocd_1),
+                    (StatusFlag::Ocd2, field_sets::SafetyAlert::ocd_2),
+                    (StatusFlag::Aoldl, field_sets::SafetyAlert::aoldl),
+                    (StatusFlag::Asccl, field_sets::SafetyAlert::asccl),
+                    (StatusFlag::Ascdl, field_sets::SafetyAlert::ascdl),
+                    (StatusFlag::Otc, field_sets::SafetyAlert::otc),
+                    (StatusFlag::Otd, field_sets::SafetyAlert::otd),
+                    (StatusFlag::Cuvc, field_sets::SafetyAlert::cuvc),
+                    (StatusFlag::Otf, field_sets::SafetyAlert::otf),
+                    (StatusFlag::Pto, field_sets::SafetyAlert::pto),
+                    (StatusFlag::Ptos, field_sets::SafetyAlert::ptos),
+                    (StatusFlag::Cto, field_sets::SafetyAlert::cto),
+                    (StatusFlag::Ctos, field_sets::SafetyAlert::ctos),
+                    (StatusFlag::Oc, field_sets::SafetyAlert::oc),
+                    (StatusFlag::Chgc, field_sets::SafetyAlert::chgc),
+                    (StatusFlag::Chgv, field_sets::SafetyAlert::chgv),
+                    (StatusFlag::Pchgc, field_sets::SafetyAlert::pchgc),
+                    (StatusFlag::Utc, field_sets::SafetyAlert::utc),
+                    (StatusFlag::Utd, field_sets::SafetyAlert::utd),
+                ];
+                let safety_status: &[(StatusFlag, fn(&field_sets::SafetyStatus) -> bool)] = &[
+                    (StatusFlag::Cuv, field_sets::SafetyStatus::cuv),
+                    (StatusFlag::Cov, field_sets::SafetyStatus::cov),
+                    (StatusFlag::Occ1, field_sets::SafetyStatus::occ_1),
+                    (StatusFlag::Occ2, field_sets::SafetyStatus::occ_2),
+                    (StatusFlag::Ocd1, field_sets::SafetyStatus::ocd_1),
+                    (StatusFlag::Ocd2, field_sets::SafetyStatus::ocd_2),
+                    (StatusFlag::Aold, field_sets::SafetyStatus::aold),
+                    (StatusFlag::Aoldl, field_sets::SafetyStatus::aoldl),
+                    (StatusFlag::Ascc, field_sets::SafetyStatus::ascc),
+                    (StatusFlag::Asccl, field_sets::SafetyStatus::asccl),
+                    (StatusFlag::Ascd, field_sets::SafetyStatus::ascd),
+                    (StatusFlag::Ascdl, field_sets::SafetyStatus::ascdl),
+                    (StatusFlag::Otc, field_sets::SafetyStatus::otc),
+                    (StatusFlag::Otd, field_sets::SafetyStatus::otd),
+                    (StatusFlag::Cuvc, field_sets::SafetyStatus::cuvc),
+                    (StatusFlag::Otf, field_sets::SafetyStatus::otf),
+                    (StatusFlag::Pto, field_sets::SafetyStatus::pto),
+                    (StatusFlag::Ptos, field_sets::SafetyStatus::ptos),
+                    (StatusFlag::Cto, field_sets::SafetyStatus::cto),
+                    (StatusFlag::Oc, field_sets::SafetyStatus::oc),
+                    (StatusFlag::Chgc, field_sets::SafetyStatus::chgc),
+                    (StatusFlag::Chgv, field_sets::SafetyStatus::chgv),
+                    (StatusFlag::Pchgc, field_sets::SafetyStatus::pchgc),
+                    (StatusFlag::Utc, field_sets::SafetyStatus::utc),
+                    (StatusFlag::Utd, field_sets::SafetyStatus::utd),
+                ];
+
+                check_status_flag_table(StatusRegister::SafetyAlert, safety_alert);
+                check_status_flag_table(StatusRegister::SafetyStatus, safety_status);
+            }
+
+            #[test]
+            fn test_pf_flag_table() {
+                use crate::{StatusFlag, StatusRegister};
+
+                // Every field of the revision's generated registers, by the flag it decodes to.
+                let pf_alert: &[(StatusFlag, fn(&field_sets::PfAlert) -> bool)] = &[
+                    (StatusFlag::Suv, field_sets::PfAlert::suv),
+                    (StatusFlag::Sov, field_sets::PfAlert::sov),
+                    (StatusFlag::Socc, field_sets::PfAlert::socc),
+                    (StatusFlag::Socd, field_sets::PfAlert::socd),
+                    (StatusFlag::Sot, field_sets::PfAlert::sot),
+                    (StatusFlag::Sotf, field_sets::PfAlert::sotf),
+                    (StatusFlag::Qim, field_sets::PfAlert::qim),
+                    (StatusFlag::Cb, field_sets::PfAlert::cb),
+                    (StatusFlag::Imp, field_sets::PfAlert::imp),
+                    (StatusFlag::Cd, field_sets::PfAlert::cd),
+                    (StatusFlag::Vimr, field_sets::PfAlert::vimr),
+                    (StatusFlag::Vima, field_sets::PfAlert::vima),
+                    (StatusFlag::Cfetf, field_sets::PfAlert::cfetf),
+                    (StatusFlag::Dfetf, field_sets::PfAlert::dfetf),
+                    (StatusFlag::Fuse, field_sets::PfAlert::fuse),
+                    (StatusFlag::Afer, field_sets::PfAlert::afer),
+                    (StatusFlag::Afec, field_sets::PfAlert::afec),
+                    (StatusFlag::SecondLvl, field_sets::PfAlert::second_lvl),
+                    (StatusFlag::OpnCell, field_sets::PfAlert::opnc),
+                    (StatusFlag::Ts1, field_sets::PfAlert::ts_1),
+                    (StatusFlag::Ts2, field_sets::PfAlert::ts_2),
+                    (StatusFlag::Ts3, field_sets::PfAlert::ts_3),
+                    (StatusFlag::Ts4, field_sets::PfAlert::ts_4),
+                ];
+                let pf_status: &[(StatusFlag, fn(&field_sets::PfStatus) -> bool)] = &[
+                    (StatusFlag::Suv, field_sets::PfStatus::suv),
+                    (StatusFlag::Sov, field_sets::PfStatus::sov),
+                    (StatusFlag::Socc, field_sets::PfStatus::socc),
+                    (StatusFlag::Socd, field_sets::PfStatus::socd),
+                    (StatusFlag::Sot, field_sets::PfStatus::sot),
+                    (StatusFlag::Sotf, field_sets::PfStatus::sotf),
+                    (StatusFlag::Qim, field_sets::PfStatus::qim),
+                    (StatusFlag::Cb, field_sets::PfStatus::cb),
+                    (StatusFlag::Imp, field_sets::PfStatus::imp),
+                    (StatusFlag::Cd, field_sets::PfStatus::cd),
+                    (StatusFlag::Vimr, field_sets::PfStatus::vimr),
+                    (StatusFlag::Vima, field_sets::PfStatus::vima),
+                    (StatusFlag::Cfetf, field_sets::PfStatus::cfetf),
+                    (StatusFlag::Dfetf, field_sets::PfStatus::dfetf),
+                    (StatusFlag::Fuse, field_sets::PfStatus::fuse),
+                    (StatusFlag::Afer, field_sets::PfStatus::afer),
+                    (StatusFlag::Afec, field_sets::PfStatus::afec),
+                    (StatusFlag::SecondLvl, field_sets::PfStatus::second_lvl),
+                    (StatusFlag::Ptc, field_sets::PfStatus::ptc),
+                    (StatusFlag::Ifc, field_sets::PfStatus::ifc),
+                    (StatusFlag::OpnCell, field_sets::PfStatus::opncell),
+                    (StatusFlag::Dfw, field_sets::PfStatus::dfw),
+                    (StatusFlag::Ts1, field_sets::PfStatus::ts_1),
+                    (StatusFlag::Ts2, field_sets::PfStatus::ts_2),
+                    (StatusFlag::Ts3, field_sets::PfStatus::ts_3),
+                    (StatusFlag::Ts4, field_sets::PfStatus::ts_4),
+                ];
+
+                check_status_flag_table(StatusRegister::PfAlert, pf_alert);
+                check_status_flag_table(StatusRegister::PfStatus, pf_status);
+            }
+
+            #[tokio::test]
+            async fn test_pf_report() {
+                use crate::{BlackBoxEvent, PfReport, StatusFlag};
+
+                let params = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::DATA_FLASH;
+                // Fill one data flash subclass, then expect it to be read with a single (auto-incrementing) DF read
+                let df_region = |class: &str, subclass: &str, values: &[(&str, &[u8])]| {
+                    let region: Vec<_> = params
+                        .iter()
+                        .filter(|p| p.class == class && p.subclass == subclass)
+                        .collect();
+                    let start = region.iter().map(|p| p.address).min().unwrap();
+                    let end = region
+                        .iter()
+                        .map(|p| usize::from(p.address) + p.ty.size_bytes())
+                        .max()
+                        .unwrap();
+                    let mut data = vec![0u8; end - usize::from(start)];
+                    for (name, value) in values {
+                        let param = crate::DfParam::lookup(params, class, subclass, name).unwrap();
+                        let offset = usize::from(param.address - start);
+                        data[offset..offset + value.len()].copy_from_slice(value);
+                    }
+
+                    let address = start.to_le_bytes();
+                    let mut transactions = vec![Transaction::write(BQ_ADDR, vec![0x44, 0x02, address[0], address[1]])];
+                    for (i, chunk) in data.chunks(32).enumerate() {
+                        let chunk_address = (start + 32 * u16::try_from(i).unwrap()).to_le_bytes();
+                        let mut response = vec![0x22, chunk_address[0], chunk_address[1]];
+                        response.extend_from_slice(chunk);
+                        transactions.push(Transaction::write_read(BQ_ADDR, vec![0x44], response));
+                    }
+                    transactions
+                };
+
+                let mut expectations = vec![
+                    Transaction::write_read(BQ_ADDR, vec![0x53], vec![0x00, 0x08, 0x00, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x52], vec![0x00, 0x00, 0x00, 0x00]),
+                ];
+                expectations.extend(df_region(
+                    "Black Box",
+                    "Safety Status",
+                    &[
+                        ("1st Safety Status", &[0x02, 0, 0, 0]),
+                        ("1st Time to Next Event", &[10]),
+                    ],
+                ));
+                let current = (-100i16).to_le_bytes();
+                expectations.extend(df_region(
+                    "PF Status",
+                    "Device Status Data",
+                    &[
+                        // VIMR is bit 11, in the B byte of PFStatus()
+                        ("PF Status B", &[0x08]),
+                        ("Cell 1 Voltage", &3800u16.to_le_bytes()),
+                        ("Cell 2 Voltage", &3300u16.to_le_bytes()),
+                        ("Cell 3 Voltage", &3800u16.to_le_bytes()),
+                        ("Cell 4 Voltage", &3800u16.to_le_bytes()),
+                        ("Current", &current),
+                        ("Internal Temperature", &3001u16.to_le_bytes()),
+                    ],
+                ));
+
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+                let report = PfReport::read(&mut bq).await.unwrap();
+
+                assert!(report.latched());
+                assert_eq!(
+                    report.failures().map(|f| f.flag).collect::<Vec<_>>(),
+                    vec![StatusFlag::Vimr]
+                );
+                assert_eq!(
+                    report.black_box.unwrap()[0],
+                    BlackBoxEvent {
+                        safety_status: 0x02,
+                        time_to_next_event_s: 10,
+                    }
+                );
+                let capture = report.capture.unwrap();
+                assert_eq!(capture.pf_status, report.pf_status);
+                assert_eq!(capture.cell_voltages, [3800, 3300, 3800, 3800]);
+                assert_eq!(capture.current, -100);
+                assert_eq!(capture.internal_temperature, 3001);
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[test]
+            fn test_pf_report_addresses() {
+                use crate::Revision;
+
+                let params = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::DATA_FLASH;
+                let revision = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::REVISION;
+                // (class, subclass, first parameter, address, last parameter, address)
+                let expected = match revision {
+                    Revision::R1 | Revision::R3 | Revision::R4 | Revision::R5 => [
+                        (
+                            "PF Status",
+                            "Device Status Data",
+                            "Safety Alert A",
+                            0x4580,
+                            "Internal Temperature",
+                            0x45A6,
+                        ),
+                        (
+                            "Black Box",
+                            "Safety Status",
+                            "1st Safety Status",
+                            0x45C0,
+                            "3rd Time to Next Event",
+                            0x45CE,
+                        ),
+                    ],
+                };
+
+                for (class, subclass, first, first_address, last, last_address) in expected {
+                    let address = |name| crate::DfParam::lookup(params, class, subclass, name).unwrap().address;
+                    assert_eq!(address(first), first_address, "{class}/{subclass}/{first}");
+                    assert_eq!(address(last), last_address, "{class}/{subclass}/{last}");
+
+                    // The report reads each subclass in one go, it must be contiguous
+                    let mut region: Vec<_> = params
+                        .iter()
+                        .filter(|p| p.class == class && p.subclass == subclass)
+                        .collect();
+                    region.sort_by_key(|p| p.address);
+                    for pair in region.windows(2) {
+                        assert_eq!(
+                            usize::from(pair[0].address) + pair[0].ty.size_bytes(),
+                            usize::from(pair[1].address),
+                            "{class}/{subclass}/{}",
+                            pair[1].name
+                        );
+                    }
+                }
+            }
+
+            #[tokio::test]
+            async fn test_lifetime_data() {
+                use crate::{LifetimeEvent, Revision, TemperatureBand};
+
+                let revision = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::REVISION;
+                let sizes: &[usize] = match revision {
+                    Revision::R1 => &[32, 8, 16, 32, 32],
+                    Revision::R3 => &[32, 20, 4, 32, 32, 32, 32, 32, 32, 32, 32, 32],
+                    Revision::R4 => &[26, 20, 4, 32, 32, 32, 32, 32, 32, 32, 32, 32, 30, 30, 30],
+                    Revision::R5 => &[26, 20, 4, 32, 32, 32, 32, 32, 32, 32, 32, 32, 30, 30, 30, 32],
+                };
+
+                let mut expectations = vec![];
+                for (i, (&size, cmd)) in sizes.iter().zip(0x60u8..).enumerate() {
+                    let cmd = if i == 15 { 0x7E } else { cmd };
+                    let mut data = vec![0u8; size];
+                    match i + 1 {
+                        // Cell 1 max voltage, then a discharge current of -5000 mA
+                        1 => {
+                            data[..2].copy_from_slice(&4200u16.to_le_bytes());
+                            data[20..22].copy_from_slice(&(-5000i16).to_le_bytes());
+                        }
+                        // 3 COV events, the last at cycle 120
+                        4 => data[..4].copy_from_slice(&[3, 0, 120, 0]),
+                        // 2 OTF events
+                        5 => data[12] = 2,
+                        // One hour at RSOC A and one at RSOC H in every band
+                        6..=12 => {
+                            data[..4].copy_from_slice(&3600u32.to_le_bytes());
+                            data[28..].copy_from_slice(&3600u32.to_le_bytes());
+                        }
+                        _ => {}
+                    }
+                    if revision == Revision::R1 && i == 2 {
+                        // 1 unit of 2 hours spent in the recommended band
+                        data[8] = 1;
+                    }
+
+                    let mut response = vec![u8::try_from(size).unwrap() + 2, cmd, 0x00];
+                    response.extend_from_slice(&data);
+                    expectations.push(Transaction::write(BQ_ADDR, vec![0x44, 0x02, cmd, 0x00]));
+                    expectations.push(Transaction::write_read(BQ_ADDR, vec![0x44], response));
+                }
+
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+                let lifetime = bq.read_lifetime_data().await.unwrap();
+
+                assert_eq!(lifetime.revision, revision);
+                assert!(lifetime.block_read(sizes.len()));
+                assert!(!lifetime.block_read(sizes.len() + 1));
+                assert_eq!(lifetime.max_cell_voltage[0], 4200);
+                assert_eq!(lifetime.max_discharge_current, -5000);
+                assert_eq!(lifetime.event(LifetimeEvent::CellOvervoltage).count, 3);
+                assert_eq!(lifetime.event(LifetimeEvent::CellOvervoltage).last_cycle, 120);
+                assert_eq!(lifetime.event(LifetimeEvent::OvertemperatureFet).count, 2);
+                assert_eq!(lifetime.protection_events(), 5);
+                assert_eq!(lifetime.temperatures.is_some(), sizes[0] == 32);
+                assert_eq!(lifetime.relax_temperatures.is_some(), sizes.len() >= 15);
+                assert_eq!(lifetime.below_band_rsoc_time_s.is_some(), sizes.len() == 16);
+                if revision == Revision::R1 {
+                    assert_eq!(lifetime.time_in_band(TemperatureBand::Recommended), Some(7200));
+                    assert_eq!(lifetime.rsoc_histogram(), None);
+                } else {
+                    assert_eq!(lifetime.temperature_histogram(), Some([7200; 7]));
+                    assert_eq!(lifetime.rsoc_histogram(), Some([25200, 0, 0, 0, 0, 0, 0, 25200]));
+                }
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_calibration() {
+                use crate::{CalibrationStep, DfValue, TemperatureSensor};
+
+                let params = <Bq40z50<Mock, NoopDelay> as crate::traits::Bq40z50>::DATA_FLASH;
+                let address = |subclass: &str, name: &str| {
+                    crate::DfParam::lookup(params, "Calibration", subclass, name)
+                        .unwrap()
+                        .address
+                        .to_le_bytes()
+                };
+                let df_read = |subclass: &str, name: &str, value: &[u8]| {
+                    let address = address(subclass, name);
+                    let mut response = vec![0x22, address[0], address[1]];
+                    response.extend_from_slice(value);
+                    [
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, address[0], address[1]]),
+                        Transaction::write_read(BQ_ADDR, vec![0x44], response),
+                    ]
+                };
+                let ccadc_output = |refresh_counter: u8, current: i16| {
+                    let mut response = vec![0x1A, 0x82, 0xF0, refresh_counter, 0x00];
+                    response.extend_from_slice(&current.to_le_bytes());
+                    response.extend_from_slice(&[0; 20]);
+                    [
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x82, 0xF0]),
+                        Transaction::write_read(BQ_ADDR, vec![0x44], response),
+                    ]
+                };
+
+                let mut expectations = vec![Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x2D, 0x00])];
+                expectations.extend(ccadc_output(1, -10));
+                // Same refresh counter, not a new sample
+                expectations.extend(ccadc_output(1, -10));
+                expectations.extend(ccadc_output(2, -12));
+                expectations.push(Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x80, 0xF0]));
+                expectations.push(Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x2D, 0x00]));
+                expectations.extend(df_read(
+                    "Current Offset",
+                    "Coulomb Counter Offset Samples",
+                    &[64, 0],
+                ));
+                expectations.extend(df_read("Current Offset", "CC Offset", &[0, 0]));
+                // Average of -11 counts over 64 offset samples is -704
+                let cc_offset = address("Current Offset", "CC Offset");
+                expectations.push(Transaction::write(
+                    BQ_ADDR,
+                    vec![0x44, 0x04, cc_offset[0], cc_offset[1], 0x40, 0xFD],
+                ));
+
+                let mut da_status_2 = vec![0x0C, 0x72, 0x00];
+                da_status_2.extend_from_slice(&2990u16.to_le_bytes());
+                da_status_2.extend_from_slice(&[0; 8]);
+                expectations.push(Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x72, 0x00]));
+                expectations.push(Transaction::write_read(BQ_ADDR, vec![0x44], da_status_2));
+                expectations.extend(df_read("Temperature", "Internal Temp Offset", &[0x00]));
+                let temp_offset = address("Temperature", "Internal Temp Offset");
+                expectations.push(Transaction::write(
+                    BQ_ADDR,
+                    vec![0x44, 0x03, temp_offset[0], temp_offset[1], 0xF7],
+                ));
+
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let report = bq.calibrate(CalibrationStep::CcOffset, 2).await.unwrap();
+                let change = report.iter().next().unwrap();
+                assert_eq!(change.param.name, "CC Offset");
+                assert_eq!(change.before, DfValue::Int(0));
+                assert_eq!(change.after, DfValue::Int(-704));
+                assert_eq!(report.iter().count(), 1);
+
+                let report = bq
+                    .calibrate(
+                        CalibrationStep::Temperature {
+                            sensor: TemperatureSensor::Internal,
+                            reference: 2981,
+                        },
+                        1,
+                    )
+                    .await
+                    .unwrap();
+                assert_eq!(report.changes[0].unwrap().after, DfValue::Int(-9));
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_calibration_exit() {
+                use crate::CalibrationStep;
+
+                let bus = embedded_hal::i2c::ErrorKind::ArbitrationLoss;
+                let overrun = embedded_hal::i2c::ErrorKind::Overrun;
+                let mut ccadc_output = vec![0x1A, 0x82, 0xF0, 0x01];
+                ccadc_output.resize(27, 0);
+                let expectations = vec![
+                    // Sampling fails, then so does stopping the output: the sampling error wins
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x2D, 0x00]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x82, 0xF0]).with_error(bus),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x80, 0xF0]).with_error(overrun),
+                    // Calibration mode is still toggled off
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x2D, 0x00]),
+                    // Sampling succeeds but stopping the output fails
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x2D, 0x00]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x82, 0xF0]),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], ccadc_output),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x80, 0xF0]).with_error(overrun),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x2D, 0x00]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new_with_config(
+                    i2c,
+                    NoopDelay::new(),
+                    Config {
+                        retry: crate::RetryPolicy::uniform(crate::Retry::NEVER),
+                        ..Default::default()
+                    },
+                );
+
+                assert_eq!(
+                    bq.calibrate(CalibrationStep::CcOffset, 1).await.unwrap_err(),
+                    BQ40Z50Error::I2c(bus)
+                );
+                assert_eq!(
+                    bq.calibrate(CalibrationStep::CcOffset, 1).await.unwrap_err(),
+                    BQ40Z50Error::I2c(overrun)
+                );
+
+                bq.device.interface.i2c.done();
+            }
+
+            #[tokio::test]
+            async fn test_learning_cycle() {
+                use crate::{LearningAction, LearningConfig, LearningCycle, LearningFailure, LearningPhase};
+
+                const FD: u32 = 1 << 0;
+                const FC: u32 = 1 << 1;
+                const REST: u32 = 1 << 8;
+
+                let gauge_status_3_len = match <Bq40z50<Mock, NoopDelay> as crate::Bq40z50>::REVISION {
+                    crate::Revision::R5 => 32,
+                    _ => 24,
+                };
+                let status = |gauging_status: u32, update_status: u8, dod0: u16| {
+                    let mut gauge_status_2 = vec![0x22, 0x74, 0x00, 0x00, update_status];
+                    gauge_status_2.extend_from_slice(&[0; 8]);
+                    for _ in 0..4 {
+                        gauge_status_2.extend_from_slice(&dod0.to_le_bytes());
+                    }
+                    gauge_status_2.extend_from_slice(&[0; 14]);
+                    let mut gauge_status_3 = vec![gauge_status_3_len + 2, 0x75, 0x00];
+                    gauge_status_3.resize(3 + usize::from(gauge_status_3_len), 0);
+                    [
+                        Transaction::write_read(BQ_ADDR, vec![0x56], gauging_status.to_le_bytes().to_vec()),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x74, 0x00]),
+                        Transaction::write_read(BQ_ADDR, vec![0x44], gauge_status_2),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x75, 0x00]),
+                        Transaction::write_read(BQ_ADDR, vec![0x44], gauge_status_3),
+                    ]
+                };
+                let mut expectations = vec![];
+                for (gauging_status, update_status, dod0) in [
+                    (0, 0x04, 0),
+                    (FD, 0x04, 0),
+                    (REST, 0x04, 100),
+                    (FC, 0x04, 100),
+                    (REST, 0x05, 900),
+                    (FD, 0x05, 900),
+                    (REST, 0x0E, 100),
+                    // Second cycle, which never rests after the discharge
+                    (0, 0x04, 0),
+                    (FD, 0x04, 0),
+                    (0, 0x04, 0),
+                ] {
+                    expectations.extend(status(gauging_status, update_status, dod0));
+                }
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+
+                let mut cycle = LearningCycle::new(LearningConfig::new());
+                for (now_s, phase, action) in [
+                    (0, LearningPhase::DischargeToEmpty, LearningAction::Discharge),
+                    (10, LearningPhase::RelaxDischarged, LearningAction::Relax),
+                    (20, LearningPhase::ChargeToFull, LearningAction::Charge),
+                    (30, LearningPhase::RelaxCharged, LearningAction::Relax),
+                    (
+                        40,
+                        LearningPhase::DischargeForResistance,
+                        LearningAction::Discharge,
+                    ),
+                    (50, LearningPhase::RelaxFinal, LearningAction::Relax),
+                    (60, LearningPhase::Complete, LearningAction::None),
+                ] {
+                    let progress = cycle.poll(&mut bq, now_s).await.unwrap();
+                    assert_eq!((progress.phase, progress.action), (phase, action));
+                }
+                assert_eq!(cycle.failed_in(), None);
+
+                let mut cycle = LearningCycle::new(LearningConfig::new());
+                cycle.poll(&mut bq, 0).await.unwrap();
+                cycle.poll(&mut bq, 10).await.unwrap();
+                let progress = cycle.poll(&mut bq, 10 + 7 * 60 * 60).await.unwrap();
+                assert_eq!(
+                    progress.phase,
+                    LearningPhase::Failed(LearningFailure::RelaxTimeNotMet)
+                );
+                assert_eq!(cycle.failed_in(), Some(LearningPhase::RelaxDischarged));
+
+                bq.device.interface.i2c.done();
             }
 
             #[tokio::test]
-            async fn write_unseal_keys() {
+            async fn test_learning_status_pec() {
+                let gauge_status_3_len = match <Bq40z50<Mock, NoopDelay> as crate::Bq40z50>::REVISION {
+                    crate::Revision::R5 => 32,
+                    _ => 24,
+                };
+                // Append the PEC the gauge sends after `read`, in reply to `command`.
+                let with_pec = |command: u8, mut read: Vec<u8>| {
+                    let mut transfer = vec![BQ_ADDR << 1, command, BQ_ADDR << 1 | 0x01];
+                    transfer.extend_from_slice(&read);
+                    read.push(smbus_pec::pec(&transfer));
+                    read
+                };
+                let mac_write = |command: u8| {
+                    let mut write = vec![0x44, 0x02, command, 0x00];
+                    write.push(smbus_pec::pec(&[&[BQ_ADDR << 1][..], &write].concat()));
+                    write
+                };
+
+                // Every byte up to the last one of each register is payload, so none of them may be taken as the PEC.
+                let mut gauge_status_2 = vec![0x22, 0x74, 0x00, 0x00, 0x0E];
+                gauge_status_2.resize(3 + 32, 0xAA);
+                gauge_status_2[25..27].copy_from_slice(&0x0123u16.to_le_bytes());
+                let mut gauge_status_3 = vec![gauge_status_3_len + 2, 0x75, 0x00];
+                gauge_status_3.resize(3 + usize::from(gauge_status_3_len), 0xBB);
+                gauge_status_3[21..23].copy_from_slice(&0x0456u16.to_le_bytes());
                 let expectations = vec![
-                    Transaction::write(
-                        BQ_ADDR,
-                        vec![
-                            0x44, 0x0A, 0x35, 0x00, 0x30, 0x30, 0x60, 0x60, 0x01, 0x01, 0x10, 0x10,
-                        ],
-                    ),
-                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x35, 0x00]),
-                    Transaction::write_read(
-                        BQ_ADDR,
-                        vec![0x44],
-                        vec![0x0A, 0x35, 0x00, 0x30, 0x30, 0x60, 0x60, 0x01, 0x01, 0x10, 0x10],
-                    ),
+                    Transaction::write_read(BQ_ADDR, vec![0x56], with_pec(0x56, vec![0x01, 0x01, 0x00, 0x80])),
+                    Transaction::write(BQ_ADDR, mac_write(0x74)),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], with_pec(0x44, gauge_status_2)),
+                    Transaction::write(BQ_ADDR, mac_write(0x75)),
+                    Transaction::write_read(BQ_ADDR, vec![0x44], with_pec(0x44, gauge_status_3)),
                 ];
                 let i2c = Mock::new(&expectations);
-                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+                let mut bq = Bq40z50::new_with_config(
+                    i2c,
+                    NoopDelay::new(),
+                    Config {
+                        pec_read: true,
+                        ..Default::default()
+                    },
+                );
 
-                let security_keys = [0x30u8, 0x30u8, 0x60u8, 0x60u8, 0x01u8, 0x01u8, 0x10u8, 0x10u8];
+                let status = bq.read_learning_status().await.unwrap();
+                assert_eq!(status.gauging_status, crate::GaugingStatus(0x8000_0101));
+                assert_eq!(status.update_status, crate::UpdateStatus(0x0E));
+                assert_eq!(status.dod0_time, 0x0123);
+                assert_eq!(status.qmax_time, 0x0456);
 
-                bq.write_security_keys(&security_keys).await.unwrap();
+                bq.device.interface.i2c.done();
+            }
 
-                let mut result = [0u8; 8];
-                bq.read_security_keys(&mut result).await.unwrap();
+            #[tokio::test]
+            async fn test_program_chemistry() {
+                use crate::{ChemImage, SecurityState};
+
+                const CHEM_IMAGE: &str = concat!(
+                    "; Chemistry 1210\n",
+                    "W: 16 44 05 00 4A 01 02 03\n",
+                    "X: 20\n",
+                    "\n",
+                    "W: 16 44 03 03 4A FF\n",
+                );
+
+                let chemistry = |chem_id: u16, signature: u16| {
+                    let mut chem_id_response = vec![0x04, 0x06, 0x00];
+                    chem_id_response.extend_from_slice(&chem_id.to_le_bytes());
+                    let mut signature_response = vec![0x04, 0x08, 0x00];
+                    signature_response.extend_from_slice(&signature.to_le_bytes());
+                    [
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x06, 0x00]),
+                        Transaction::write_read(BQ_ADDR, vec![0x44], chem_id_response),
+                        Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x08, 0x00]),
+                        Transaction::write_read(BQ_ADDR, vec![0x44], signature_response),
+                    ]
+                };
+                let writes = [
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x01, 0x00, 0x00]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x05, 0x00, 0x4A, 0x01, 0x02, 0x03]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x03, 0x03, 0x4A, 0xFF]),
+                ];
+                let mut expectations = vec![];
+                expectations.extend(writes.clone());
+                expectations.extend(chemistry(0x1210, 0x3A5C));
+                expectations.extend(writes.clone());
+                expectations.extend(chemistry(0x0100, 0x3A5C));
+                expectations.extend(writes);
+                expectations.extend(chemistry(0x1210, 0x3A5C | 0x8000));
+                // Nothing is written unless the gauge is in full access
+                expectations.push(Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x02, 0x00, 0x00]));
+                let i2c = Mock::new(&expectations);
+                // The signature is read once the gauge has computed it
+                let delay_expectations = vec![DelayTransaction::delay_ms(250); 3];
+                let mut bq = Bq40z50::new(i2c, CheckedDelay::new(&delay_expectations));
+
+                let records: Vec<_> = ChemImage::parse(CHEM_IMAGE).unwrap().records().collect();
+                assert_eq!((records[0].line, records[0].address), (2, 0x4A00));
+                assert_eq!(records[0].data(), [0x01, 0x02, 0x03]);
+                assert_eq!((records[1].line, records[1].address), (5, 0x4A03));
+                assert_eq!(records.len(), 2);
+                assert_eq!(ChemImage::parse("X: 20\n"), Err(0));
+                // A key sent to ManufacturerAccess(), a wrong block count and an address outside the data flash
+                assert_eq!(ChemImage::parse("W: 16 44 03 00 4A 01\nW: 16 00 14 04\n"), Err(2));
+                assert_eq!(ChemImage::parse("W: 16 44 04 00 4A 01\n"), Err(1));
+                assert_eq!(ChemImage::parse("W: 16 44 03 00 01 01\n"), Err(1));
+
+                let info = bq.program_chemistry(CHEM_IMAGE, 0x1210, 0x3A5C).await.unwrap();
+                assert_eq!(info.chem_id, 0x1210);
+                assert!(!info.signature_mismatch);
+                assert_eq!(
+                    bq.program_chemistry(CHEM_IMAGE, 0x1210, 0x3A5C).await,
+                    Err(BQ40Z50Error::ChemIdMismatch {
+                        expected: 0x1210,
+                        actual: 0x0100,
+                    })
+                );
+                assert_eq!(
+                    bq.program_chemistry(CHEM_IMAGE, 0x1210, 0x3A5C).await,
+                    Err(BQ40Z50Error::ChemSignatureMismatch {
+                        expected: 0x3A5C,
+                        actual: 0x3A5C,
+                    })
+                );
+                assert_eq!(
+                    bq.program_chemistry(CHEM_IMAGE, 0x1210, 0x3A5C).await,
+                    Err(BQ40Z50Error::SecurityStateMismatch {
+                        expected: SecurityState::FullAccess,
+                        actual: SecurityState::Unsealed,
+                    })
+                );
+                // Nothing is read or written when the image does not parse
+                assert_eq!(
+                    bq.program_chemistry("W: 16 44 05 00 4A 01 02 03\nW: 16 44 XY\n", 0x1210, 0x3A5C)
+                        .await,
+                    Err(BQ40Z50Error::InvalidImage { line: 2 })
+                );
 
-                assert_eq!(u16::from_le_bytes([result[0], result[1]]), 0x3030);
-                assert_eq!(u16::from_le_bytes([result[2], result[3]]), 0x6060);
-                assert_eq!(u16::from_le_bytes([result[4], result[5]]), 0x0101);
-                assert_eq!(u16::from_le_bytes([result[6], result[7]]), 0x1010);
                 bq.device.interface.i2c.done();
+                bq.device.interface.delay.done();
             }
 
             #[tokio::test]
-            async fn test_battery_status() {
-                let expectations = vec![Transaction::write_read(BQ_ADDR, vec![0x16], vec![0x30, 0x30])];
+            async fn test_verify_signatures() {
+                use crate::{SignatureRegion, SignatureReport, Signatures};
+
+                let signatures = |values: [u16; 4]| {
+                    let mut expectations = vec![];
+                    for (cmd, value) in [0x04u8, 0x05, 0x08, 0x09].into_iter().zip(values) {
+                        let mut response = vec![0x04, cmd, 0x00];
+                        response.extend_from_slice(&value.to_le_bytes());
+                        expectations.push(Transaction::write(BQ_ADDR, vec![0x44, 0x02, cmd, 0x00]));
+                        expectations.push(Transaction::write_read(BQ_ADDR, vec![0x44], response));
+                    }
+                    expectations
+                };
+                let mut expectations = signatures([0xA1B2, 0x1234, 0x3A5C, 0x4321]);
+                // Static data flash flagged as mismatched, all data flash changed
+                expectations.extend(signatures([0xA1B2, 0x9234, 0x3A5C, 0x4322]));
+                // Answer to another command
+                expectations.push(Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x04, 0x00]));
+                expectations.push(Transaction::write_read(
+                    BQ_ADDR,
+                    vec![0x44],
+                    vec![0x04, 0x06, 0x00, 0x10, 0x12],
+                ));
                 let i2c = Mock::new(&expectations);
                 let mut bq = Bq40z50::new(i2c, NoopDelay::new());
 
-                let status = match bq.battery_status().await {
-                    Ok(status) => status,
-                    Err(e) => match e {
-                        _ => unreachable!(),
-                    },
-                };
+                let golden = Signatures::new(0xA1B2, 0x1234, 0x3A5C, 0x4321);
+                let report = bq.verify_signatures(golden).await.unwrap();
+                assert!(report.is_intact());
+                assert_eq!(report.actual, golden);
+
+                let report = bq.verify_signatures(golden).await.unwrap();
+                assert!(!report.is_intact());
+                assert_eq!(report.actual.static_df, 0x1234);
+                assert!(report.actual.mismatch_flagged(SignatureRegion::StaticDataFlash));
+                assert!(
+                    report
+                        .changed_regions()
+                        .eq([SignatureRegion::StaticDataFlash, SignatureRegion::AllDataFlash])
+                );
+                assert_eq!(SignatureReport::new(report.actual, golden), report);
 
-                assert_eq!(status.error_code(), ErrorCode::Ok);
+                assert_eq!(bq.read_signatures().await, Err(BQ40Z50Error::UnexpectedResponse));
 
                 bq.device.interface.i2c.done();
             }
 
+            #[cfg(feature = "std")]
             #[tokio::test]
-            async fn test_battery_status_pec() {
-                // Full bus transaction looks like [ 0x0B << 1 || 0x16 || 0x0B << 1 | 1 || 0x30 || 0x30 || 0xB7 (PEC)]
-                let expectations = vec![Transaction::write_read(
-                    BQ_ADDR,
-                    vec![0x16],
-                    vec![0x30, 0x30, 0xB7],
-                )];
-                let i2c = Mock::new(&expectations);
+            async fn test_simulator_telemetry() {
+                use crate::sim::SimulatedGauge;
+
+                let revision = <Bq40z50<SimulatedGauge, NoopDelay> as crate::Bq40z50>::REVISION;
+                let mut gauge = SimulatedGauge::new(revision);
+                let key: crate::AuthKey = [0x5A; 16];
+                gauge.set_auth_key(key);
+                gauge.battery_mut().set_state_of_charge(80);
+                gauge.battery_mut().current_ma = -1500;
+                gauge.step(60 * 60 * 1000);
+                let mut bq = Bq40z50::new(gauge, NoopDelay::new());
+
+                assert_eq!(bq.device.interface.detect_revision().await, Ok(revision));
+
+                let snapshot = bq.read_snapshot(crate::SnapshotFields::ALL, || 0).await.unwrap();
+                assert_eq!(snapshot.voltage, Some(9930));
+                assert_eq!(snapshot.current, Some(-1500));
+                assert_eq!(snapshot.average_current, Some(-1494));
+                assert_eq!(snapshot.temperature, Some(2982));
+                assert_eq!(snapshot.relative_state_of_charge, Some(30));
+                assert_eq!(
+                    snapshot.remaining_capacity,
+                    Some(CapacityModeValue::MilliAmpUnsigned(900))
+                );
+                assert_eq!(snapshot.run_time_to_empty, Some(36));
+                assert_eq!(snapshot.average_time_to_full, Some(u16::MAX));
+                let status = snapshot.battery_status.unwrap();
+                assert!(status.initialized() && status.discharging() && !status.fully_charged());
+                assert_eq!(snapshot.cell_voltages, Some([3310, 3310, 3310, 0]));
+
+                let challenge: crate::AuthChallenge = core::array::from_fn(|i| u8::try_from(i).unwrap());
+                bq.authenticate(&key, &challenge).await.unwrap();
+                assert_eq!(
+                    bq.authenticate(&[0xA5; 16], &challenge).await,
+                    Err(BQ40Z50Error::AuthenticationFailed)
+                );
+            }
+
+            #[cfg(feature = "std")]
+            #[tokio::test]
+            async fn test_simulator_security() {
+                use crate::SecurityState;
+                use crate::sim::SimulatedGauge;
+
+                let revision = <Bq40z50<SimulatedGauge, NoopDelay> as crate::Bq40z50>::REVISION;
                 let mut bq = Bq40z50::new_with_config(
-                    i2c,
+                    SimulatedGauge::new(revision),
                     NoopDelay::new(),
                     Config {
                         pec_read: true,
+                        pec_write: true,
                         ..Default::default()
                     },
                 );
+                let data: Vec<u8> = (0..48).collect();
+                let mut read = [0u8; 48];
 
-                let status = match bq.battery_status().await {
-                    Ok(status) => status,
-                    Err(e) => match e {
-                        _ => unreachable!(),
-                    },
-                };
+                // Data flash is locked while sealed.
+                assert_eq!(
+                    bq.read_dataflash(0x4000, &mut read).await,
+                    Err(BQ40Z50Error::I2c(embedded_hal::i2c::ErrorKind::NoAcknowledge(
+                        embedded_hal::i2c::NoAcknowledgeSource::Data
+                    )))
+                );
+                assert!(matches!(
+                    bq.unseal(0x0414, 0x0000).await,
+                    Err(BQ40Z50Error::KeyRejected {
+                        state: SecurityState::Sealed
+                    })
+                ));
+                bq.unseal(0x0414, 0x3672).await.unwrap();
 
-                assert_eq!(status.error_code(), ErrorCode::Ok);
+                // Writes and auto-incrementing reads span two blocks.
+                bq.write_dataflash(0x4010, &data).await.unwrap();
+                bq.read_dataflash(0x4010, &mut read).await.unwrap();
+                assert_eq!(read.as_slice(), data.as_slice());
+                assert_eq!(
+                    bq.device.interface.i2c.data_flash(0x4010, 48),
+                    Some(data.as_slice())
+                );
 
-                bq.device.interface.i2c.done();
+                bq.enter_full_access(0xFFFF, 0xFFFF).await.unwrap();
+                assert_eq!(bq.security_state().await, Ok(SecurityState::FullAccess));
+                bq.seal().await.unwrap();
+                assert_eq!(bq.device.interface.i2c.security_state(), SecurityState::Sealed);
             }
 
+            #[cfg(feature = "std")]
             #[tokio::test]
-            #[allow(unsafe_code)]
-            async fn test_read_write_unchecked() {
-                let expectations = vec![
-                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0x30, 0x30]),
-                    Transaction::write(BQ_ADDR, vec![0x16, 0x2F, 0x30]),
-                ];
-                let i2c = Mock::new(&expectations);
-                let mut bq = Bq40z50::new(i2c, NoopDelay::new());
+            async fn test_fault_injection_registers() {
+                use crate::SecurityState;
+                use crate::fault::{Fault, FaultInjector};
+                use crate::sim::SimulatedGauge;
+
+                let revision = <Bq40z50<SimulatedGauge, NoopDelay> as crate::Bq40z50>::REVISION;
+                let mut gauge = SimulatedGauge::new(revision);
+                gauge.set_security_state(SecurityState::Unsealed);
+                let mut delay = CheckedDelay::new(&[DelayTransaction::delay_ms(5)]);
+                let bus = FaultInjector::new(gauge, delay.clone())
+                    // Register write
+                    .with_fault(0, Fault::Nack)
+                    .with_fault(1, Fault::ArbitrationLoss)
+                    // Register read, with a corrupted PEC byte and then a slow but intact response
+                    .with_fault(3, Fault::CorruptByte { index: 2, mask: 0x80 })
+                    .with_fault(4, Fault::Delay { ms: 5 })
+                    // MAC write
+                    .with_fault(5, Fault::NackData)
+                    // MAC read, not acknowledged after the command was written, then cut short
+                    .with_fault(8, Fault::Nack)
+                    .with_fault(10, Fault::Truncate { len: 4 });
+                let mut bq = Bq40z50::new_with_config(
+                    bus,
+                    NoopDelay::new(),
+                    Config {
+                        pec_read: true,
+                        pec_write: true,
+                        ..Default::default()
+                    },
+                );
 
-                let mut data = [0u8; 2];
+                bq.device
+                    .cycle_count()
+                    .write_async(|f| f.set_cycle_count(7))
+                    .await
+                    .unwrap();
+                assert_eq!(bq.device.interface.i2c.inner().battery().cycle_count, 7);
+                assert_eq!(bq.cycle_count().await, Ok(7));
 
-                unsafe {
-                    bq.read_register_unchecked(0x16, &mut data).await.unwrap();
-                }
-                data[0] -= 1;
+                bq.reset_pf_data().await.unwrap();
+                assert_eq!(bq.device.interface.i2c.inner().mac_log(), [0x0029]);
 
-                unsafe {
-                    bq.write_register_unchecked(0x16, &data).await.unwrap();
-                }
+                // Every failed attempt restarts with the command write.
+                assert_eq!(bq.device.interface.detect_revision().await, Ok(revision));
+                assert_eq!(bq.device.interface.i2c.transfers(), 15);
+                assert_eq!(bq.device.interface.i2c.pending(), 0);
 
-                bq.device.interface.i2c.done();
+                // Retries are exhausted after the 4th corrupted response.
+                bq.device.interface.i2c.inject_next(4, Fault::CorruptByte { index: 0, mask: 0x01 });
+                assert_eq!(bq.cycle_count().await, Err(BQ40Z50Error::Pec));
+
+                bq.device.interface.i2c.inject_next(4, Fault::ArbitrationLoss);
+                assert_eq!(
+                    bq.device.interface.detect_revision().await,
+                    Err(BQ40Z50Error::I2c(embedded_hal::i2c::ErrorKind::ArbitrationLoss))
+                );
+
+                delay.done();
+            }
+
+            #[cfg(feature = "std")]
+            #[tokio::test]
+            async fn test_fault_injection_dataflash() {
+                use crate::SecurityState;
+                use crate::fault::{Fault, FaultInjector};
+                use crate::sim::SimulatedGauge;
+
+                let revision = <Bq40z50<SimulatedGauge, NoopDelay> as crate::Bq40z50>::REVISION;
+                let mut gauge = SimulatedGauge::new(revision);
+                gauge.set_security_state(SecurityState::Unsealed);
+                let data: Vec<u8> = (0..64).collect();
+                gauge.set_data_flash(0x4000, &data);
+                let bus = FaultInjector::new(gauge, NoopDelay::new())
+                    // Second block read not acknowledged
+                    .with_fault(2, Fault::Nack)
+                    // Second block read corrupted, caught by PEC
+                    .with_fault(8, Fault::CorruptByte { index: 10, mask: 0xFF })
+                    // Second block write not acknowledged
+                    .with_fault(13, Fault::NackData);
+                let mut bq = Bq40z50::new(bus, NoopDelay::new());
+
+                // A failed block restarts the read from the starting address, since the gauge may have moved on.
+                let mut read = [0u8; 64];
+                bq.read_dataflash(0x4000, &mut read).await.unwrap();
+                assert_eq!(read.as_slice(), data.as_slice());
+                assert_eq!(bq.device.interface.i2c.transfers(), 6);
+
+                let mut config = bq.config();
+                config.pec_read = true;
+                config.pec_write = true;
+                bq.update_config(config);
+                bq.read_dataflash(0x4000, &mut read).await.unwrap();
+                assert_eq!(read.as_slice(), data.as_slice());
+                assert_eq!(bq.device.interface.i2c.transfers(), 12);
+
+                // Only the failed chunk is written again.
+                let write: Vec<u8> = (0..48).rev().collect();
+                bq.write_dataflash(0x4100, &write).await.unwrap();
+                assert_eq!(bq.device.interface.i2c.transfers(), 15);
+                assert_eq!(
+                    bq.device.interface.i2c.inner().data_flash(0x4100, 48),
+                    Some(write.as_slice())
+                );
+
+                bq.device.interface.i2c.inject_next(4, Fault::Nack);
+                assert_eq!(
+                    bq.read_dataflash(0x4000, &mut read).await,
+                    Err(BQ40Z50Error::I2c(embedded_hal::i2c::ErrorKind::NoAcknowledge(
+                        embedded_hal::i2c::NoAcknowledgeSource::Address
+                    )))
+                );
             }
 
             #[tokio::test]
@@ -379,6 +1990,192 @@ macro_rules! bq40z50_tests {
                 bq.device.interface.delay.done();
             }
 
+            #[tokio::test]
+            async fn test_retry_policy() {
+                let nack = embedded_hal::i2c::ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Address);
+                let expectations = vec![
+                    // NACKs back off exponentially up to the cap
+                    Transaction::write(BQ_ADDR, vec![0x17, 100, 0]).with_error(nack),
+                    Transaction::write(BQ_ADDR, vec![0x17, 100, 0]).with_error(nack),
+                    Transaction::write(BQ_ADDR, vec![0x17, 100, 0]).with_error(nack),
+                    Transaction::write(BQ_ADDR, vec![0x17, 100, 0]).with_error(nack),
+                    Transaction::write(BQ_ADDR, vec![0x17, 100, 0]),
+                    // Bus errors fail fast
+                    Transaction::write(BQ_ADDR, vec![0x17, 100, 0])
+                        .with_error(embedded_hal::i2c::ErrorKind::ArbitrationLoss),
+                    // PEC mismatches fail fast
+                    Transaction::write_read(BQ_ADDR, vec![0x17], vec![100, 0, 0x00]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let delay_expectations = vec![
+                    DelayTransaction::delay_ms(10),
+                    DelayTransaction::delay_ms(20),
+                    DelayTransaction::delay_ms(25),
+                    DelayTransaction::delay_ms(25),
+                ];
+                let mut bq = Bq40z50::new_with_config(
+                    i2c,
+                    CheckedDelay::new(&delay_expectations),
+                    Config {
+                        pec_read: true,
+                        retry: crate::RetryPolicy {
+                            nack: crate::Retry::exponential(4, 10, 25),
+                            pec: crate::Retry::NEVER,
+                            bus: crate::Retry::NEVER,
+                        },
+                        ..Default::default()
+                    },
+                );
+
+                bq.device
+                    .cycle_count()
+                    .write_async(|f| f.set_cycle_count(100))
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    bq.device
+                        .cycle_count()
+                        .write_async(|f| f.set_cycle_count(100))
+                        .await,
+                    Err(BQ40Z50Error::I2c(embedded_hal::i2c::ErrorKind::ArbitrationLoss))
+                );
+                assert_eq!(bq.cycle_count().await, Err(BQ40Z50Error::Pec));
+
+                bq.device.interface.i2c.done();
+                bq.device.interface.delay.done();
+            }
+
+            #[tokio::test]
+            async fn test_timeout() {
+                // A gauge holding the bus forever
+                struct StuckBus;
+                impl embedded_hal_async::i2c::ErrorType for StuckBus {
+                    type Error = embedded_hal::i2c::ErrorKind;
+                }
+                impl embedded_hal_async::i2c::I2c for StuckBus {
+                    async fn transaction(
+                        &mut self,
+                        _address: u8,
+                        _operations: &mut [embedded_hal_async::i2c::Operation<'_>],
+                    ) -> Result<(), Self::Error> {
+                        core::future::pending().await
+                    }
+                }
+
+                let delay_expectations = vec![
+                    DelayTransaction::delay_ms(5),
+                    DelayTransaction::delay_ms(10),
+                    DelayTransaction::delay_ms(5),
+                ];
+                let mut bq = Bq40z50::new_with_config(
+                    StuckBus,
+                    CheckedDelay::new(&delay_expectations),
+                    Config {
+                        timeout_ms: Some(5),
+                        retry: crate::RetryPolicy::uniform(crate::Retry::fixed(1, 10)),
+                        ..Default::default()
+                    },
+                );
+
+                assert_eq!(bq.cycle_count().await, Err(BQ40Z50Error::Timeout));
+
+                bq.device.interface.delay.done();
+            }
+
+            #[test]
+            fn blocking_read_firmware_version_pec() {
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x02, 0x00, 0x46]),
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x44],
+                        vec![
+                            0x0A, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0,
+                        ],
+                    ),
+                    Transaction::write_read(BQ_ADDR, vec![0x16], vec![0x30, 0x30, 0xB7]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50Blocking::new_with_config(
+                    i2c,
+                    NoopDelay::new(),
+                    Config {
+                        pec_read: true,
+                        ..Default::default()
+                    },
+                );
+
+                bq.device().mac_firmware_version().dispatch().unwrap();
+                let status = bq.device().battery_status().read().unwrap();
+                assert!(status.fc());
+
+                bq.device().interface.i2c.0.done();
+            }
+
+            #[test]
+            fn blocking_unseal_and_df_transactions() {
+                let expectations = vec![
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x03, 0x00, 0x00]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x14, 0x04]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x72, 0x36]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x02, 0x00, 0x00]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x06, 0x00, 0x40, 0xFE, 0xCA, 0xFE, 0xC0]),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x00, 0x40]),
+                    Transaction::write_read(
+                        BQ_ADDR,
+                        vec![0x44],
+                        vec![0x22, 0x00, 0x40, 0xFE, 0xCA, 0xFE, 0xC0],
+                    ),
+                    Transaction::write(BQ_ADDR, vec![0x44, 0x02, 0x30, 0x00]),
+                    Transaction::write_read(BQ_ADDR, vec![0x54], vec![0x00, 0x03, 0x00, 0x00]),
+                ];
+                let i2c = Mock::new(&expectations);
+                let mut bq = Bq40z50Blocking::new(i2c, NoopDelay::new());
+
+                bq.unseal(0x0414, 0x3672).unwrap();
+                bq.write_dataflash(0x4000, &[0xFE, 0xCA, 0xFE, 0xC0]).unwrap();
+                let mut read = [0u8; 4];
+                bq.read_dataflash(0x4000, &mut read).unwrap();
+                assert_eq!(read, [0xFE, 0xCA, 0xFE, 0xC0]);
+                bq.seal().unwrap();
+
+                bq.device().interface.i2c.0.done();
+            }
+
+            #[test]
+            fn blocking_cmd_retries() {
+                let expectations = vec![
+                    Transaction::write(BQ_ADDR, vec![0x44, 2, 0x53, 0]).with_error(
+                        embedded_hal::i2c::ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Address),
+                    ),
+                    Transaction::write(BQ_ADDR, vec![0x44, 2, 0x53, 0]).with_error(
+                        embedded_hal::i2c::ErrorKind::NoAcknowledge(embedded_hal::i2c::NoAcknowledgeSource::Address),
+                    ),
+                ];
+                let i2c = Mock::new(&expectations);
+                let delay_expectations = vec![DelayTransaction::delay_ms(10)];
+                let mut bq = Bq40z50Blocking::new_with_config(
+                    i2c,
+                    CheckedDelay::new(&delay_expectations),
+                    Config {
+                        retry: crate::RetryPolicy::uniform(crate::Retry::fixed(1, 10)),
+                        ..Default::default()
+                    },
+                );
+
+                let res = bq.device().mac_pf_status().dispatch();
+
+                assert_eq!(
+                    res,
+                    Err(BQ40Z50Error::I2c(embedded_hal::i2c::ErrorKind::NoAcknowledge(
+                        embedded_hal::i2c::NoAcknowledgeSource::Address
+                    )))
+                );
+
+                bq.device().interface.i2c.0.done();
+                bq.device().interface.delay.0.done();
+            }
+
             #[cfg(not(feature = "r1"))]
             #[tokio::test]
             async fn test_charging_override_voltage() {
@@ -0,0 +1,85 @@
+//! Bus transfer timeouts, raced against the driver's delay.
+
+use core::future::{Future, poll_fn};
+use core::pin::pin;
+use core::task::Poll;
+
+use embedded_hal_async::delay::DelayNs as DelayTrait;
+use embedded_hal_async::i2c::I2c as I2cTrait;
+
+use crate::error::BQ40Z50Error;
+use crate::interface::DeviceInterface;
+
+/// Run `transfer` until it completes, or fail with [`BQ40Z50Error::Timeout`] once `delay` has waited `timeout_ms`.
+///
+/// `transfer` is polled first, so a transfer that completes right away never touches `delay`.
+async fn with_timeout<E>(
+    transfer: impl Future<Output = Result<(), E>>,
+    delay: &mut impl DelayTrait,
+    timeout_ms: Option<u32>,
+) -> Result<(), BQ40Z50Error<E>> {
+    let Some(timeout_ms) = timeout_ms else {
+        return transfer.await.map_err(BQ40Z50Error::I2c);
+    };
+
+    let mut transfer = pin!(transfer);
+    let mut timer = pin!(delay.delay_ms(timeout_ms));
+    poll_fn(|cx| {
+        if let Poll::Ready(res) = transfer.as_mut().poll(cx) {
+            Poll::Ready(res.map_err(BQ40Z50Error::I2c))
+        } else if timer.as_mut().poll(cx).is_ready() {
+            Poll::Ready(Err(BQ40Z50Error::Timeout))
+        } else {
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+impl<I2C: I2cTrait, DELAY: DelayTrait> DeviceInterface<I2C, DELAY> {
+    /// Write `write` to the gauge, within the configured timeout.
+    pub(crate) async fn bus_write(&mut self, write: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.bus_write_to(self.config.address, write).await
+    }
+
+    /// Write `write` to the device at `address`, within the configured timeout.
+    pub(crate) async fn bus_write_to(&mut self, address: u8, write: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.seal_if_pending().await?;
+        with_timeout(self.i2c.write(address, write), &mut self.delay, self.config.timeout_ms).await
+    }
+
+    /// Write `write` to the gauge, within the configured timeout, without sealing a pending seal first.
+    pub(crate) async fn timed_write(&mut self, write: &[u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        with_timeout(
+            self.i2c.write(self.config.address, write),
+            &mut self.delay,
+            self.config.timeout_ms,
+        )
+        .await
+    }
+
+    /// Write `write` to the gauge and read `read` back, within the configured timeout.
+    pub(crate) async fn bus_write_read(
+        &mut self,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.bus_write_read_to(self.config.address, write, read).await
+    }
+
+    /// Write `write` to the device at `address` and read `read` back, within the configured timeout.
+    pub(crate) async fn bus_write_read_to(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        self.seal_if_pending().await?;
+        with_timeout(
+            self.i2c.write_read(address, write, read),
+            &mut self.delay,
+            self.config.timeout_ms,
+        )
+        .await
+    }
+}
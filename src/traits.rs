@@ -0,0 +1,353 @@
+use embedded_batteries_async::smart_battery::SmartBattery;
+
+use crate::auth::{AuthChallenge, AuthKey};
+use crate::common::{ChargingVoltageOverride, Config, Revision};
+use crate::consts::{AUTH_KEY_DATA_LEN_BYTES, AUTH_KEY_LEN_BYTES, SECURITY_KEYS_DATA_LEN_BYTES};
+use crate::dataflash::{DfParam, DfValue};
+use crate::flashstream::{FlashStreamError, FlashStreamProgress};
+use crate::security::SecurityState;
+
+/// Revision-agnostic driver API, implemented by every supported chip revision.
+///
+/// Application code can be written generically over `T: Bq40z50` to support mixed fleets of gauges. Parts of the API
+/// that differ between revisions are expressed through associated types, and operations a revision does not support
+/// return [`BQ40Z50Error::Unsupported`](crate::BQ40Z50Error::Unsupported).
+#[allow(async_fn_in_trait)]
+pub trait Bq40z50: SmartBattery {
+    /// Chip revision implemented by this driver.
+    const REVISION: Revision;
+
+    /// Data flash parameters defined for this revision, e.g. `df_r5::PARAMS`.
+    const DATA_FLASH: &'static [DfParam];
+
+    /// Revision-specific register and command map, generated from the revision's manifest.
+    type Device;
+
+    /// Credentials required to write the `MfgInfoC` MAC register.
+    ///
+    /// This is `(access_key_lower, access_key_upper)` on R5, which requires the `MfgInfoC` access keys to be sent
+    /// first, and `()` on revisions that need no keys.
+    type MfgInfoCAccess;
+
+    /// Access the register level API of this revision.
+    fn device(&mut self) -> &mut Self::Device;
+
+    /// Current interface config.
+    fn config(&self) -> Config;
+
+    /// Change interface config.
+    fn update_config(&mut self, config: Config);
+
+    /// Seal the fuel gauge and verify it reports [`SecurityState::Sealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`crate::BQ40Z50Error::SecurityStateMismatch`] if the gauge
+    /// did not seal.
+    #[deprecated(note = "use `seal`")]
+    async fn seal_fg(&mut self) -> Result<(), Self::Error>;
+
+    /// Unseal the fuel gauge and verify it reports [`SecurityState::Unsealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`crate::BQ40Z50Error::KeyRejected`] if the gauge did not
+    /// accept the key.
+    #[deprecated(note = "use `unseal`")]
+    async fn unseal_fg(&mut self, unseal_key_lower: u16, unseal_key_upper: u16) -> Result<(), Self::Error>;
+
+    /// Send access keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn send_access_key(&mut self, access_key_lower: u16, access_key_upper: u16) -> Result<(), Self::Error>;
+
+    /// Read the current security mode from `OperationStatus`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn security_state(&mut self) -> Result<SecurityState, Self::Error>;
+
+    /// Seal the fuel gauge and verify it reports [`SecurityState::Sealed`] afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or
+    /// [`BQ40Z50Error::SecurityStateMismatch`](crate::BQ40Z50Error::SecurityStateMismatch) if the gauge did not seal.
+    async fn seal(&mut self) -> Result<(), Self::Error>;
+
+    /// Unseal the fuel gauge and verify it reports [`SecurityState::Unsealed`] afterwards.
+    ///
+    /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`](crate::BQ40Z50Error::KeyRejected)
+    /// if the gauge did not accept the key.
+    async fn unseal(&mut self, key_lower: u16, key_upper: u16) -> Result<(), Self::Error>;
+
+    /// Send the full access key to an unsealed gauge and verify it reports [`SecurityState::FullAccess`] afterwards.
+    ///
+    /// After a rejected key the next attempt first waits out the gauge's 4 second lockout.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs,
+    /// [`BQ40Z50Error::SecurityStateMismatch`](crate::BQ40Z50Error::SecurityStateMismatch) if the gauge is sealed, or
+    /// [`BQ40Z50Error::KeyRejected`](crate::BQ40Z50Error::KeyRejected) if the gauge did not accept the key.
+    async fn enter_full_access(&mut self, key_lower: u16, key_upper: u16) -> Result<(), Self::Error>;
+
+    /// Seal the gauge before its next bus transfer.
+    ///
+    /// For code that cannot await [`seal`](Self::seal), such as a `Drop` impl. The seal is not verified.
+    fn seal_before_next_transfer(&mut self);
+
+    /// Read MAC Register 0x0035 Security Keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_security_keys(
+        &mut self,
+        output_buf: &mut [u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+    ) -> Result<(), Self::Error>;
+
+    /// Write MAC Register 0x0035 Security Keys.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn write_security_keys(
+        &mut self,
+        security_keys: &[u8; SECURITY_KEYS_DATA_LEN_BYTES as usize],
+    ) -> Result<(), Self::Error>;
+
+    /// Read MAC Register 0x0037 Authentication Key.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_authentication_key(
+        &mut self,
+        output_buf: &mut [u8; AUTH_KEY_DATA_LEN_BYTES as usize],
+    ) -> Result<(), Self::Error>;
+
+    /// Write MAC Register 0x0037 Authentication Key.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn write_authentication_key(
+        &mut self,
+        auth_key: &[u8; AUTH_KEY_LEN_BYTES as usize],
+    ) -> Result<(), Self::Error>;
+
+    /// Challenge the pack through `Authenticate()` and verify its SHA-1 digest against `key`.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or
+    /// [`BQ40Z50Error::AuthenticationFailed`](crate::BQ40Z50Error::AuthenticationFailed) if the pack returned the wrong
+    /// digest.
+    async fn authenticate(&mut self, key: &AuthKey, challenge: &AuthChallenge) -> Result<(), Self::Error>;
+
+    /// Write to the `MfgInfo` register. Requires the fuel gauge to be unsealed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or `data` is larger than 32 bytes.
+    async fn write_mfg_info(&mut self, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read from the `MfgInfo` register, including the leading size byte.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or `data` is larger than 33 bytes.
+    async fn read_mfg_info(&mut self, data: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write to the `MfgInfoC` MAC register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, `data` is larger than 32 bytes, or the revision has no `MfgInfoC`
+    /// register.
+    async fn write_mfg_info_c(&mut self, access: Self::MfgInfoCAccess, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read from the `MfgInfoC` MAC register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, `data` is larger than 32 bytes, or the revision has no `MfgInfoC`
+    /// register.
+    async fn read_mfg_info_c(&mut self, data: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write to the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or the revision has no `ChargingVoltageOverride` command.
+    async fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), Self::Error>;
+
+    /// Read from the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs or the revision has no `ChargingVoltageOverride` command.
+    async fn read_charging_voltage_override(&mut self) -> Result<ChargingVoltageOverride, Self::Error>;
+
+    /// Read from the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_dataflash(&mut self, starting_address: u16, read: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Write to the data flash (DF). Starting address should be between 0x4000 and 0x5FFF.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn write_dataflash(&mut self, starting_address: u16, write: &[u8]) -> Result<(), Self::Error>;
+
+    /// Read a data flash parameter and decode it.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or
+    /// [`BQ40Z50Error::UnexpectedResponse`](crate::BQ40Z50Error::UnexpectedResponse) if the data flash holds a string
+    /// longer than the parameter allows.
+    async fn read_df_param(&mut self, param: &DfParam) -> Result<DfValue, Self::Error>;
+
+    /// Range-check and encode a data flash parameter, then write it. Requires the fuel gauge to be unsealed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, or
+    /// [`BQ40Z50Error::InvalidDfValue`](crate::BQ40Z50Error::InvalidDfValue) if `value` does not match the parameter's
+    /// type or is outside its range.
+    async fn write_df_param(&mut self, param: &DfParam, value: &DfValue) -> Result<(), Self::Error>;
+
+    /// Play a `FlashStream` (`.bq.fs` / `.df.fs`) file against the device, calling `progress` after every command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` with the line of the failing command if the stream does not parse, an I2C bus error occurs,
+    /// or a `C:` command read back different bytes.
+    async fn run_flash_stream(
+        &mut self,
+        contents: &str,
+        progress: impl FnMut(FlashStreamProgress),
+    ) -> Result<(), FlashStreamError<Self::Error>>;
+
+    /// Read the requested `fields` of the standard SBS telemetry in one call.
+    ///
+    /// Capacities are reported in the units of the current `CAPACITY_MODE`. `timestamp` is called before the first and
+    /// after the last read, so [`Snapshot::bus_time`](crate::Snapshot::bus_time) measures the time spent on the bus.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_snapshot(
+        &mut self,
+        fields: crate::SnapshotFields,
+        timestamp: impl FnMut() -> u64,
+    ) -> Result<crate::Snapshot, Self::Error>;
+
+    /// Read the raw value of a 32-bit status register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_status(&mut self, register: crate::StatusRegister) -> Result<u32, Self::Error>;
+
+    /// Send `MAC_PF_DATA_RST` to clear the permanent failure flags and the device status captured at the time of
+    /// failure. Requires the fuel gauge to be in full access.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn reset_pf_data(&mut self) -> Result<(), Self::Error>;
+
+    /// Send `MAC_BLK_BOX_REC_RESET` to clear the black box recorder. Requires the fuel gauge to be in full access.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn reset_black_box(&mut self) -> Result<(), Self::Error>;
+
+    /// Read every `MAC_LIFETIME_DATA_BLOCK_n` recorded by this revision and decode them into one report, for warranty
+    /// analysis. Requires the fuel gauge to be unsealed.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_lifetime_data(&mut self) -> Result<crate::lifetime::LifetimeData, Self::Error>;
+
+    /// Run a calibration `step` against its reference, averaging `samples` readings, and write the new coefficients to
+    /// the `Calibration` data flash. Requires the fuel gauge to be unsealed.
+    ///
+    /// Returns the coefficients before and after calibration.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, [`BQ40Z50Error::Timeout`](crate::BQ40Z50Error::Timeout) if the
+    /// CCADC output stops refreshing, [`BQ40Z50Error::UnexpectedResponse`](crate::BQ40Z50Error::UnexpectedResponse) if
+    /// the readings do not allow computing a coefficient, or
+    /// [`BQ40Z50Error::InvalidDfValue`](crate::BQ40Z50Error::InvalidDfValue) if a new coefficient is out of range.
+    async fn calibrate(
+        &mut self,
+        step: crate::calibration::CalibrationStep,
+        samples: u8,
+    ) -> Result<crate::calibration::CalibrationReport, Self::Error>;
+
+    /// Read `GaugingStatus()`, `GaugeStatus2()` and `GaugeStatus3()`, which track the Impedance Track learning cycle.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_learning_status(&mut self) -> Result<crate::learning::LearningStatus, Self::Error>;
+
+    /// Read the chem ID and static chemistry data flash signature of the programmed chemistry.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_chemistry(&mut self) -> Result<crate::ChemistryInfo, Self::Error>;
+
+    /// Write the chemistry tables of a chemistry image to data flash, then verify the gauge reports `chem_id` and the
+    /// static chemistry data flash `signature`. Requires the fuel gauge to be in full access.
+    ///
+    /// See [`ChemImage`](crate::ChemImage) for the format. The whole image is validated before anything is written.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs, the gauge is not in full access, the image does not parse, or the
+    /// gauge reports a different chem ID or signature.
+    async fn program_chemistry(
+        &mut self,
+        contents: &str,
+        chem_id: u16,
+        signature: u16,
+    ) -> Result<crate::ChemistryInfo, Self::Error>;
+
+    /// Read the instruction flash, static data flash, static chemistry data flash and all data flash signatures,
+    /// waiting for the gauge to compute each one.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn read_signatures(&mut self) -> Result<crate::Signatures, Self::Error>;
+
+    /// Read every flash signature and compare them against `expected`, e.g. recorded from a golden pack, to detect
+    /// which regions were tampered with or corrupted.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    async fn verify_signatures(&mut self, expected: crate::Signatures) -> Result<crate::SignatureReport, Self::Error>;
+}
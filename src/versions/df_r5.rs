@@ -0,0 +1,9 @@
+//! Data flash parameters of the BQ40Z50-R5, generated from `df_r5.csv` next to the register manifest.
+//!
+//! The table follows the columns of the data flash tables in the [technical reference manual] (class, subclass, name,
+//! address, type, range, default and units). It covers the `Calibration` class and the `PF Status` and `Black Box`
+//! parameters read by the driver, not the full data flash map; parameters missing from the table cannot be looked up.
+//!
+//! [technical reference manual]: https://www.ti.com/lit/ug/sluucn4b/sluucn4b.pdf
+
+include!(concat!(env!("OUT_DIR"), "/df_r5.rs"));
@@ -1,4 +1,16 @@
 #[cfg(feature = "r1")]
+#[allow(clippy::unreadable_literal)]
+pub mod df_r1;
+#[cfg(feature = "r3")]
+#[allow(clippy::unreadable_literal)]
+pub mod df_r3;
+#[cfg(feature = "r4")]
+#[allow(clippy::unreadable_literal)]
+pub mod df_r4;
+#[cfg(feature = "r5")]
+#[allow(clippy::unreadable_literal)]
+pub mod df_r5;
+#[cfg(feature = "r1")]
 #[allow(clippy::all)]
 #[allow(clippy::pedantic)]
 #[allow(unsafe_code)]
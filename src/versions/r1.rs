@@ -3,7 +3,7 @@ use core::cell::Cell;
 use embedded_hal_async::delay::DelayNs as DelayTrait;
 use embedded_hal_async::i2c::I2c as I2cTrait;
 
-use crate::common::{CapacityModeState, Config};
+use crate::common::{CapacityModeState, ChargingVoltageOverride, Config, Revision};
 use crate::consts::{
     AUTH_KEY_CMD, AUTH_KEY_DATA_LEN_BYTES, AUTH_KEY_LEN_BYTES, LARGEST_REG_SIZE_BYTES, MAC_CMD,
     MAC_CMD_ADDR_SIZE_BYTES, MFG_INFO_CMD, SECURITY_KEYS_CMD, SECURITY_KEYS_DATA_LEN_BYTES, SECURITY_KEYS_LEN_BYTES,
@@ -211,25 +211,30 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R1<I2C, DELAY> {
             .await
     }
 
-    /// Seal the fuel gauge.
+    /// Seal the fuel gauge and verify it reports [`crate::SecurityState::Sealed`] afterwards.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if an I2C bus error occurs.
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge did not
+    /// seal.
+    #[deprecated(note = "use `seal`")]
     pub async fn seal_fg(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        self.device.mac_seal().dispatch_async().await
+        self.seal().await
     }
 
-    /// Unseal the fuel gauge.
+    /// Unseal the fuel gauge and verify it reports [`crate::SecurityState::Unsealed`] afterwards.
     ///
     /// # Errors
     ///
-    /// Will return `Err` if an I2C bus error occurs.
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the
+    /// key.
+    #[deprecated(note = "use `unseal`")]
     pub async fn unseal_fg(
         &mut self,
         unseal_key_lower: u16,
         unseal_key_upper: u16,
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        self.send_access_key(unseal_key_lower, unseal_key_upper).await
+        self.unseal(unseal_key_lower, unseal_key_upper).await
     }
 
     /// Send access keys.
@@ -268,7 +273,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R1<I2C, DELAY> {
 
     /// Write to the `MfgInfo` register. Despite it not being a MAC cmd, it uses the `SMBus` block command.
     ///
-    /// Requires fuel gauge to be unsealed. Send `unseal_fg()` first, and then reseal with `seal_fg()` after this command.
+    /// Requires fuel gauge to be unsealed. Call `unseal()` first, and then reseal with `seal()` after this command.
     ///
     /// `data` can be at most 32 bytes large.
     /// # Errors
@@ -378,6 +383,31 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R1<I2C, DELAY> {
     }
 }
 
+crate::common::implement_driver_operations!(Bq40z50R1, Revision::R1, df_r1);
+
 crate::common::implement_embedded_batteries!(Bq40z50R1);
 
-crate::tests::bq40z50_tests!(Bq40z50R1);
+crate::common::implement_bq40z50!(Bq40z50R1, Revision::R1, crate::df_r1::PARAMS, (), {
+    async fn write_mfg_info_c(&mut self, _access: (), _data: &[u8]) -> Result<(), Self::Error> {
+        Err(BQ40Z50Error::Unsupported)
+    }
+
+    async fn read_mfg_info_c(&mut self, _data: &mut [u8]) -> Result<(), Self::Error> {
+        Err(BQ40Z50Error::Unsupported)
+    }
+
+    async fn write_charging_voltage_override(
+        &mut self,
+        _override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), Self::Error> {
+        Err(BQ40Z50Error::Unsupported)
+    }
+
+    async fn read_charging_voltage_override(&mut self) -> Result<ChargingVoltageOverride, Self::Error> {
+        Err(BQ40Z50Error::Unsupported)
+    }
+});
+
+crate::common::implement_blocking!(Bq40z50R1Blocking, Bq40z50R1);
+
+crate::tests::bq40z50_tests!(Bq40z50R1, Bq40z50R1Blocking);
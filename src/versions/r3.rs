@@ -3,7 +3,7 @@ use core::cell::Cell;
 use embedded_hal_async::delay::DelayNs as DelayTrait;
 use embedded_hal_async::i2c::I2c as I2cTrait;
 
-use crate::common::{CapacityModeState, ChargingVoltageOverride, Config};
+use crate::common::{CapacityModeState, ChargingVoltageOverride, Config, Revision};
 use crate::consts::{
     AUTH_KEY_CMD, AUTH_KEY_DATA_LEN_BYTES, AUTH_KEY_LEN_BYTES, CHRG_VOLTAGE_OVERRIDE_CMD,
     CHRG_VOLTAGE_OVERRIDE_SIZE_BYTES, LARGEST_REG_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES, MFG_INFO_CMD,
@@ -213,25 +213,30 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R3<I2C, DELAY> {
             .await
     }
 
-    /// Seal the fuel gauge.
+    /// Seal the fuel gauge and verify it reports [`crate::SecurityState::Sealed`] afterwards.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if an I2C bus error occurs.
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge did not
+    /// seal.
+    #[deprecated(note = "use `seal`")]
     pub async fn seal_fg(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        self.device.mac_seal().dispatch_async().await
+        self.seal().await
     }
 
-    /// Unseal the fuel gauge.
+    /// Unseal the fuel gauge and verify it reports [`crate::SecurityState::Unsealed`] afterwards.
     ///
     /// # Errors
     ///
-    /// Will return `Err` if an I2C bus error occurs.
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the
+    /// key.
+    #[deprecated(note = "use `unseal`")]
     pub async fn unseal_fg(
         &mut self,
         unseal_key_lower: u16,
         unseal_key_upper: u16,
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        self.send_access_key(unseal_key_lower, unseal_key_upper).await
+        self.unseal(unseal_key_lower, unseal_key_upper).await
     }
 
     /// Send access keys.
@@ -270,7 +275,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R3<I2C, DELAY> {
 
     /// Write to the `MfgInfo` register. Despite it not being a MAC cmd, it uses the `SMBus` block command.
     ///
-    /// Requires fuel gauge to be unsealed. Send `unseal_fg()` first, and then reseal with `seal_fg()` after this command.
+    /// Requires fuel gauge to be unsealed. Call `unseal()` first, and then reseal with `seal()` after this command.
     ///
     /// `data` can be at most 32 bytes large.
     /// # Errors
@@ -438,6 +443,54 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R3<I2C, DELAY> {
     }
 }
 
+crate::common::implement_driver_operations!(Bq40z50R3, Revision::R3, df_r3);
+
 crate::common::implement_embedded_batteries!(Bq40z50R3);
 
-crate::tests::bq40z50_tests!(Bq40z50R3);
+crate::common::implement_bq40z50!(Bq40z50R3, Revision::R3, crate::df_r3::PARAMS, (), {
+    async fn write_mfg_info_c(&mut self, _access: (), _data: &[u8]) -> Result<(), Self::Error> {
+        Err(BQ40Z50Error::Unsupported)
+    }
+
+    async fn read_mfg_info_c(&mut self, _data: &mut [u8]) -> Result<(), Self::Error> {
+        Err(BQ40Z50Error::Unsupported)
+    }
+
+    async fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), Self::Error> {
+        Bq40z50R3::write_charging_voltage_override(self, override_struct).await
+    }
+
+    async fn read_charging_voltage_override(&mut self) -> Result<ChargingVoltageOverride, Self::Error> {
+        Bq40z50R3::read_charging_voltage_override(self).await
+    }
+});
+
+crate::common::implement_blocking!(Bq40z50R3Blocking, Bq40z50R3);
+
+impl<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> Bq40z50R3Blocking<I2C, DELAY> {
+    /// Write to the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        block_on(self.inner.write_charging_voltage_override(override_struct))
+    }
+
+    /// Read from the `ChargingVoltageOverride` register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub fn read_charging_voltage_override(&mut self) -> Result<ChargingVoltageOverride, BQ40Z50Error<I2C::Error>> {
+        block_on(self.inner.read_charging_voltage_override())
+    }
+}
+
+crate::tests::bq40z50_tests!(Bq40z50R3, Bq40z50R3Blocking);
@@ -3,7 +3,7 @@ use core::cell::Cell;
 use embedded_hal_async::delay::DelayNs as DelayTrait;
 use embedded_hal_async::i2c::I2c as I2cTrait;
 
-use crate::common::{CapacityModeState, ChargingVoltageOverride, Config};
+use crate::common::{CapacityModeState, ChargingVoltageOverride, Config, Revision};
 use crate::consts::{
     AUTH_KEY_CMD, AUTH_KEY_DATA_LEN_BYTES, AUTH_KEY_LEN_BYTES, CHRG_VOLTAGE_OVERRIDE_CMD,
     CHRG_VOLTAGE_OVERRIDE_SIZE_BYTES, LARGEST_CMD_SIZE_BYTES, LARGEST_REG_SIZE_BYTES, MAC_CMD, MAC_CMD_ADDR_SIZE_BYTES,
@@ -213,25 +213,30 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R5<I2C, DELAY> {
             .await
     }
 
-    /// Seal the fuel gauge.
+    /// Seal the fuel gauge and verify it reports [`crate::SecurityState::Sealed`] afterwards.
+    ///
     /// # Errors
     ///
-    /// Will return `Err` if an I2C bus error occurs.
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::SecurityStateMismatch`] if the gauge did not
+    /// seal.
+    #[deprecated(note = "use `seal`")]
     pub async fn seal_fg(&mut self) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        self.device.mac_seal().dispatch_async().await
+        self.seal().await
     }
 
-    /// Unseal the fuel gauge.
+    /// Unseal the fuel gauge and verify it reports [`crate::SecurityState::Unsealed`] afterwards.
     ///
     /// # Errors
     ///
-    /// Will return `Err` if an I2C bus error occurs.
+    /// Will return `Err` if an I2C bus error occurs, or [`BQ40Z50Error::KeyRejected`] if the gauge did not accept the
+    /// key.
+    #[deprecated(note = "use `unseal`")]
     pub async fn unseal_fg(
         &mut self,
         unseal_key_lower: u16,
         unseal_key_upper: u16,
     ) -> Result<(), BQ40Z50Error<I2C::Error>> {
-        self.send_access_key(unseal_key_lower, unseal_key_upper).await
+        self.unseal(unseal_key_lower, unseal_key_upper).await
     }
 
     /// Send access keys.
@@ -343,7 +348,7 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R5<I2C, DELAY> {
 
     /// Write to the `MfgInfo` register. Despite it not being a MAC cmd, it uses the `SMBus` block command.
     ///
-    /// Requires fuel gauge to be unsealed. Send `unseal_fg()` first, and then reseal with `seal_fg()` after this command.
+    /// Requires fuel gauge to be unsealed. Call `unseal()` first, and then reseal with `seal()` after this command.
     ///
     /// `data` can be at most 32 bytes large.
     /// # Errors
@@ -511,6 +516,81 @@ impl<I2C: I2cTrait, DELAY: DelayTrait> Bq40z50R5<I2C, DELAY> {
     }
 }
 
+crate::common::implement_driver_operations!(Bq40z50R5, Revision::R5, df_r5);
+
 crate::common::implement_embedded_batteries!(Bq40z50R5);
 
-crate::tests::bq40z50_tests!(Bq40z50R5);
+crate::common::implement_bq40z50!(Bq40z50R5, Revision::R5, crate::df_r5::PARAMS, (u16, u16), {
+    async fn write_mfg_info_c(
+        &mut self,
+        (access_key_lower, access_key_upper): (u16, u16),
+        data: &[u8],
+    ) -> Result<(), Self::Error> {
+        Bq40z50R5::write_mfg_info_c(self, access_key_lower, access_key_upper, data).await
+    }
+
+    async fn read_mfg_info_c(&mut self, data: &mut [u8]) -> Result<(), Self::Error> {
+        Bq40z50R5::read_mfg_info_c(self, data).await
+    }
+
+    async fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), Self::Error> {
+        Bq40z50R5::write_charging_voltage_override(self, override_struct).await
+    }
+
+    async fn read_charging_voltage_override(&mut self) -> Result<ChargingVoltageOverride, Self::Error> {
+        Bq40z50R5::read_charging_voltage_override(self).await
+    }
+});
+
+crate::common::implement_blocking!(Bq40z50R5Blocking, Bq40z50R5);
+
+impl<I2C: BlockingI2cTrait, DELAY: BlockingDelayTrait> Bq40z50R5Blocking<I2C, DELAY> {
+    /// Write to `MfgInfoC` MAC register, sending the `MfgInfoC` access keys first.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub fn write_mfg_info_c(
+        &mut self,
+        access_key_lower: u16,
+        access_key_upper: u16,
+        data: &[u8],
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        block_on(self.inner.write_mfg_info_c(access_key_lower, access_key_upper, data))
+    }
+
+    /// Read from the `MfgInfoC` MAC register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub fn read_mfg_info_c(&mut self, data: &mut [u8]) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        block_on(self.inner.read_mfg_info_c(data))
+    }
+
+    /// Write to the `ChargingVoltageOverride` MAC Command.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub fn write_charging_voltage_override(
+        &mut self,
+        override_struct: &ChargingVoltageOverride,
+    ) -> Result<(), BQ40Z50Error<I2C::Error>> {
+        block_on(self.inner.write_charging_voltage_override(override_struct))
+    }
+
+    /// Read from the `ChargingVoltageOverride` register.
+    ///
+    /// # Errors
+    ///
+    /// Will return `Err` if an I2C bus error occurs.
+    pub fn read_charging_voltage_override(&mut self) -> Result<ChargingVoltageOverride, BQ40Z50Error<I2C::Error>> {
+        block_on(self.inner.read_charging_voltage_override())
+    }
+}
+
+crate::tests::bq40z50_tests!(Bq40z50R5, Bq40z50R5Blocking);